@@ -1,5 +1,23 @@
-use clap::{Arg, App, ArgMatches};
-use std::path::PathBuf;
+use clap::{Arg, App, ArgGroup, ArgMatches};
+use std::path::{Path, PathBuf};
+use crate::machine::{Envelope, MachineModel};
+use crate::gcode::GcodeOptions;
+use crate::grbl::{Protocol, SendOptions};
+use crate::resume::ResumePoint;
+use crate::separation::SeparationOptions;
+use crate::command::parse_hex_color;
+use crate::fill::FillOptions;
+use crate::shading::{ShadingMode, ShadingOptions};
+use crate::raster::RasterOptions;
+use crate::contour::ContourOptions;
+use crate::ordering::CutOrder;
+use crate::leads::{Lead, LeadOptions, LeadShape, PierceRule, Side};
+use crate::tabs::{TabOptions, TabPlacement};
+use crate::depth::DepthOptions;
+use crate::pocket::{PocketMode, PocketOptions};
+use crate::text::{TextAlign, TextFont, TextOptions};
+use crate::barcode::{BarcodeKind, BarcodeOptions, BarcodeStyle};
+use crate::qr::EcLevel;
 
 #[derive(Debug)]
 pub struct Config {
@@ -18,6 +36,43 @@ pub struct Config {
   pub skip_canny_edge_detection: bool,
   // export configuration
   pub export_options: ExportOptions,
+
+  // kinematics used for job time estimation and simulation
+  pub machine: MachineModel,
+  // G-code flavour
+  pub gcode: GcodeOptions,
+  // stream the generated G-code to a controller
+  pub send_options: Option<SendOptions>,
+  // the input is a program to be resumed from this point
+  pub resume: Option<ResumePoint>,
+  // multi-pen color separation instead of edge detection
+  pub separation: Option<SeparationOptions>,
+  // hatch fill of the dark areas (or of each color layer)
+  pub fill: Option<FillOptions>,
+  // tone shading of the grayscale image instead of edges
+  pub shading: Option<ShadingOptions>,
+  // laser raster engraving instead of drawing
+  pub raster: Option<RasterOptions>,
+  // closed contours of the dark areas instead of edges, for cutting
+  pub contours: Option<ContourOptions>,
+  // order in which the edges are cut
+  pub cut_order: CutOrder,
+  // lead moves and pierce point of the contours
+  pub leads: Option<LeadOptions>,
+  // holding tabs on the contours of the parts
+  pub tabs: Option<TabOptions>,
+  // multi pass cutting with a Z axis
+  pub depth: Option<DepthOptions>,
+  // clears the inside of the contours instead of cutting along them
+  pub pocket: Option<PocketOptions>,
+  // largest distance between the curves of an svg or dxf drawing and their polylines (drawing units)
+  pub flatten_tolerance: f32,
+  // layers of a dxf drawing to read, all of them when empty
+  pub layers: Vec<String>,
+  // text engraved along with the paths of the input, or on its own
+  pub text: Option<TextOptions>,
+  // QR code or barcode engraved along with the paths of the input, or on its own
+  pub barcode: Option<BarcodeOptions>,
 }
 
 #[derive(Debug)]
@@ -32,6 +87,8 @@ pub struct ExportOptions {
   pub exclude_individual_edges: bool,
   // exclude cnc command generation
   pub exclude_cnc: bool,
+  // export G-code next to the cnc commands
+  pub gcode: bool,
 }
 
 impl Config {
@@ -64,7 +121,26 @@ impl Config {
         debug_preview: Some(precision),
         exclude_individual_edges: false,
         exclude_cnc: false,
+        gcode: false,
       },
+      machine: MachineModel::default(),
+      gcode: GcodeOptions::default(),
+      send_options: None,
+      resume: None,
+      separation: None,
+      fill: None,
+      shading: None,
+      raster: None,
+      contours: None,
+      cut_order: CutOrder::Traced,
+      leads: None,
+      tabs: None,
+      depth: None,
+      pocket: None,
+      flatten_tolerance: 0.1,
+      layers: vec![],
+      text: None,
+      barcode: None,
     }
   }
 }
@@ -75,8 +151,7 @@ pub fn get_raw() -> ArgMatches {
     .author("Virghileanu Teodor <@GaussianWonder>")
     .about("CNC Converter")
     .arg(Arg::new("INPUT")
-      .help("Sets the input image to use")
-      .required(true)
+      .help("Sets the input image (or svg / dxf drawing, job file, or points json / command file of a previous export) to use, it may be left out with the text and barcode subcommands")
       .index(1))
     .arg(Arg::new("output")
       .short('o')
@@ -102,6 +177,296 @@ pub fn get_raw() -> ArgMatches {
     .arg(Arg::new("skip_canny_edge_detection")
       .long("skip-canny")
       .help("Skips the Canny edge detection and uses the input image as-is after a black and white conversion"))
+    .arg(Arg::new("max_feed")
+      .long("max-feed")
+      .value_name("MM/MIN")
+      .help("Sets the feed rate of the machine while the pen is down")
+      .takes_value(true)
+      .default_value("3000.0"))
+    .arg(Arg::new("travel_rate")
+      .long("travel-rate")
+      .value_name("MM/MIN")
+      .help("Sets the feed rate of the machine while the pen is up")
+      .takes_value(true)
+      .default_value("6000.0"))
+    .arg(Arg::new("acceleration")
+      .long("acceleration")
+      .value_name("MM/S^2")
+      .help("Sets the acceleration of the machine head")
+      .takes_value(true)
+      .default_value("500.0"))
+    .arg(Arg::new("pen_delay")
+      .long("pen-delay")
+      .value_name("SECONDS")
+      .help("Sets the time it takes to raise or lower the pen")
+      .takes_value(true)
+      .default_value("0.15"))
+    .arg(Arg::new("unit_size")
+      .long("unit-size")
+      .value_name("MM")
+      .help("Sets the physical size of one command unit (one pixel of the input image)")
+      .takes_value(true)
+      .default_value("1.0"))
+    .arg(Arg::new("envelope")
+      .long("envelope")
+      .value_name("X_MIN,Y_MIN,X_MAX,Y_MAX")
+      .help("Sets the soft limits of the machine workspace (mm). Moves outside of it are reported")
+      .takes_value(true))
+    .arg(Arg::new("pen_up_gcode")
+      .long("pen-up-gcode")
+      .value_name("GCODE")
+      .help("Sets the G-code block that raises the pen")
+      .takes_value(true)
+      .default_value("M5"))
+    .arg(Arg::new("pen_down_gcode")
+      .long("pen-down-gcode")
+      .value_name("GCODE")
+      .help("Sets the G-code block that lowers the pen")
+      .takes_value(true)
+      .default_value("M3 S1000"))
+    .arg(Arg::new("colors")
+      .long("colors")
+      .value_name("COUNT")
+      .help("Quantizes the input into the given number of colors and traces each of them with its own pen")
+      .takes_value(true))
+    .arg(Arg::new("palette")
+      .long("palette")
+      .value_name("#RRGGBB,...")
+      .help("Quantizes the input to the given pen colors instead of looking for them")
+      .takes_value(true))
+    .arg(Arg::new("split_pens")
+      .long("split-pens")
+      .help("Exports one job per pen instead of a single job with tool changes"))
+    .arg(Arg::new("include_background")
+      .long("include-background")
+      .help("Also traces the lightest color, which is otherwise considered to be the paper"))
+    .arg(Arg::new("hatch")
+      .long("hatch")
+      .value_name("PIXELS")
+      .help("Fills dark areas (or color layers) with hatch lines at the given spacing")
+      .takes_value(true))
+    .arg(Arg::new("hatch_angle")
+      .long("hatch-angle")
+      .value_name("DEGREES")
+      .help("Sets the direction of the hatch lines")
+      .takes_value(true)
+      .default_value("45.0"))
+    .arg(Arg::new("cross_hatch")
+      .long("cross-hatch")
+      .help("Adds a second set of hatch lines, perpendicular to the first one"))
+    .arg(Arg::new("fill_threshold")
+      .long("fill-threshold")
+      .value_name("0-255")
+      .help("Pixels darker than this are hatched. Ignored for color layers")
+      .takes_value(true)
+      .default_value("128"))
+    .arg(Arg::new("shading")
+      .long("shading")
+      .value_name("MODE")
+      .help("Draws the tone of the image instead of its edges")
+      .takes_value(true)
+      .possible_values(["hatch", "stipple", "squiggle", "spiral", "tsp"]))
+    .arg(Arg::new("shading_spacing")
+      .long("shading-spacing")
+      .value_name("PIXELS")
+      .help("Sets the spacing of the darkest hatch lines, or of the squiggle rows")
+      .takes_value(true)
+      .default_value("4.0"))
+    .arg(Arg::new("shading_levels")
+      .long("shading-levels")
+      .value_name("COUNT")
+      .help("Sets the number of tone levels of the shading hatch")
+      .takes_value(true)
+      .default_value("4"))
+    .arg(Arg::new("stipples")
+      .long("stipples")
+      .value_name("COUNT")
+      .help("Sets the number of stipples, or of points the single line goes through")
+      .takes_value(true)
+      .default_value("5000"))
+    .arg(Arg::new("stipple_iterations")
+      .long("stipple-iterations")
+      .value_name("COUNT")
+      .help("Sets the number of relaxation steps of the stipples")
+      .takes_value(true)
+      .default_value("20"))
+    .arg(Arg::new("dot_size")
+      .long("dot-size")
+      .value_name("PIXELS")
+      .help("Sets the length of the pen down move drawing each stipple")
+      .takes_value(true)
+      .default_value("1.0"))
+    .arg(Arg::new("tsp_iterations")
+      .long("tsp-iterations")
+      .value_name("COUNT")
+      .help("Sets the maximum number of passes shortening the single line")
+      .takes_value(true)
+      .default_value("20"))
+    .arg(Arg::new("contours")
+      .long("contours")
+      .help("Traces the outlines of the dark areas as closed contours, to be cut out"))
+    .arg(Arg::new("contour_threshold")
+      .long("contour-threshold")
+      .value_name("0-255")
+      .help("Pixels darker than this are inside of the contours")
+      .takes_value(true)
+      .default_value("128"))
+    .arg(Arg::new("contour_tolerance")
+      .long("contour-tolerance")
+      .value_name("PIXELS")
+      .help("Sets how far the simplified contours may stray from the pixel outlines")
+      .takes_value(true)
+      .default_value("1.0"))
+    .arg(Arg::new("kerf")
+      .long("kerf")
+      .value_name("MM")
+      .help("Compensates the width of the cut: outer contours are moved outwards and holes inwards by half of it")
+      .takes_value(true)
+      .default_value("0.0"))
+    .arg(Arg::new("cut_order")
+      .long("cut-order")
+      .value_name("ORDER")
+      .help("Cuts the edges in the order they were traced, or everything inside of a closed edge before it (inside-out)")
+      .takes_value(true)
+      .possible_values(["traced", "inside-out"])
+      .default_value("traced"))
+    .arg(Arg::new("lead_in")
+      .long("lead-in")
+      .value_name("SHAPE")
+      .help("Starts every contour with a lead move on its scrap side")
+      .takes_value(true)
+      .possible_values(["line", "arc"]))
+    .arg(Arg::new("lead_in_length")
+      .long("lead-in-length")
+      .value_name("MM")
+      .help("Sets the length of the lead-in")
+      .takes_value(true)
+      .default_value("2.0"))
+    .arg(Arg::new("lead_in_angle")
+      .long("lead-in-angle")
+      .value_name("DEGREES")
+      .help("Sets the angle between a lead-in line and the contour, or the angle spanned by a lead-in arc")
+      .takes_value(true)
+      .default_value("90.0"))
+    .arg(Arg::new("lead_out")
+      .long("lead-out")
+      .value_name("SHAPE")
+      .help("Ends every contour with a lead move on its scrap side")
+      .takes_value(true)
+      .possible_values(["line", "arc"]))
+    .arg(Arg::new("lead_out_length")
+      .long("lead-out-length")
+      .value_name("MM")
+      .help("Sets the length of the lead-out")
+      .takes_value(true)
+      .default_value("1.0"))
+    .arg(Arg::new("lead_out_angle")
+      .long("lead-out-angle")
+      .value_name("DEGREES")
+      .help("Sets the angle between a lead-out line and the contour, or the angle spanned by a lead-out arc")
+      .takes_value(true)
+      .default_value("90.0"))
+    .arg(Arg::new("pierce")
+      .long("pierce")
+      .value_name("RULE")
+      .help("Chooses where contours are pierced: the corner nearest to the head, or the vertex nearest to a side [default: nearest-corner]")
+      .takes_value(true)
+      .possible_values(["nearest-corner", "top", "bottom", "left", "right"]))
+    .arg(Arg::new("pierce_dwell")
+      .long("pierce-dwell")
+      .value_name("SECONDS")
+      .help("Waits after each pierce, before moving")
+      .takes_value(true))
+    .arg(Arg::new("tabs")
+      .long("tabs")
+      .value_name("COUNT")
+      .help("Leaves the given number of uncut holding tabs on the contour of each part")
+      .takes_value(true))
+    .arg(Arg::new("tab_width")
+      .long("tab-width")
+      .value_name("MM")
+      .help("Sets the length of contour left uncut by each tab")
+      .takes_value(true)
+      .default_value("2.0"))
+    .arg(Arg::new("tab_placement")
+      .long("tab-placement")
+      .value_name("PLACEMENT")
+      .help("Spaces the tabs evenly, or puts them in the middle of the longest straight edges")
+      .takes_value(true)
+      .possible_values(["even", "straight"])
+      .default_value("even"))
+    .arg(Arg::new("tab_height")
+      .long("tab-height")
+      .value_name("MM")
+      .help("Crosses the tabs with the tool raised to this Z in G-code, instead of lifting the pen")
+      .takes_value(true)
+      .allow_hyphen_values(true))
+    .arg(Arg::new("pocket")
+      .long("pocket")
+      .value_name("MODE")
+      .help("Clears the inside of the contours with rings parallel to them or with zig-zag lines")
+      .takes_value(true)
+      .possible_values(["offset", "zigzag"]))
+    .arg(Arg::new("step_over")
+      .long("step-over")
+      .value_name("MM")
+      .help("Sets the distance between neighbouring passes of a pocket")
+      .takes_value(true)
+      .default_value("1.0"))
+    .arg(Arg::new("pocket_angle")
+      .long("pocket-angle")
+      .value_name("DEGREES")
+      .help("Sets the direction of the zig-zag lines of a pocket")
+      .takes_value(true)
+      .default_value("0.0"))
+    .arg(Arg::new("flatten_tolerance")
+      .long("flatten-tolerance")
+      .value_name("UNITS")
+      .help("Sets how far the polylines of svg and dxf curves may stray from them, in drawing units")
+      .takes_value(true)
+      .default_value("0.1"))
+    .arg(Arg::new("layers")
+      .long("layers")
+      .value_name("NAMES")
+      .help("Reads only these comma separated layers of a dxf drawing")
+      .takes_value(true))
+    .arg(Arg::new("safe_z")
+      .long("safe-z")
+      .value_name("MM")
+      .help("Uses the Z axis as the pen in G-code, raising the tool to this Z for travel moves")
+      .takes_value(true))
+    .arg(Arg::new("final_depth")
+      .long("final-depth")
+      .value_name("MM")
+      .help("Cuts in several passes, down to this depth below the surface")
+      .takes_value(true))
+    .arg(Arg::new("start_depth")
+      .long("start-depth")
+      .value_name("MM")
+      .help("Sets the depth of the first pass, one step down by default")
+      .takes_value(true))
+    .arg(Arg::new("step_down")
+      .long("step-down")
+      .value_name("MM")
+      .help("Sets the depth removed by each pass")
+      .takes_value(true)
+      .default_value("1.0"))
+    .arg(Arg::new("ramp")
+      .long("ramp")
+      .value_name("MM")
+      .help("Ramps down into the material along this length of closed edges, instead of plunging")
+      .takes_value(true))
+    .arg(Arg::new("plunge_feed")
+      .long("plunge-feed")
+      .value_name("MM/MIN")
+      .help("Sets the feed rate of the Z axis when lowering the tool")
+      .takes_value(true)
+      .default_value("300"))
+    .arg(Arg::new("gcode_arcs")
+      .long("gcode-arcs")
+      .value_name("MM")
+      .help("Joins the moves following a circle into G2 / G3 arcs in G-code, straying at most this far from them")
+      .takes_value(true))
     .subcommand(App::new("export")
       .about("controls export features")
       .version("0.1.1")
@@ -128,11 +493,228 @@ pub fn get_raw() -> ArgMatches {
       .arg(Arg::new("exclude_cnc")
         .long("skip-cnc")
         .help("Excludes cnc commands from the export"))
+      .arg(Arg::new("gcode")
+        .short('g')
+        .long("gcode")
+        .help("Exports G-code along with the cnc commands"))
+    )
+    .subcommand(App::new("send")
+      .about("streams the generated G-code to a GRBL controller")
+      .version("0.1.0")
+      .author("Virghileanu Teodor <@GaussianWonder>")
+      .arg(Arg::new("port")
+        .long("port")
+        .value_name("PATH")
+        .help("Serial port (or pseudo-terminal) of the controller")
+        .takes_value(true)
+        .required(true))
+      .arg(Arg::new("baud")
+        .short('b')
+        .long("baud")
+        .value_name("RATE")
+        .help("Sets the baud rate of the serial port")
+        .takes_value(true)
+        .default_value("115200"))
+      .arg(Arg::new("protocol")
+        .long("protocol")
+        .value_name("PROTOCOL")
+        .help("Character counting keeps GRBL's receive buffer full, ack waits for each line to be acknowledged")
+        .takes_value(true)
+        .possible_values(["counting", "ack"])
+        .default_value("counting"))
+      .arg(Arg::new("point_precision")
+        .short('p')
+        .long("p_precision")
+        .value_name("FLOAT32")
+        .help("Point precision of the streamed job. See the export subcommand for details")
+        .takes_value(true)
+        .default_value("1.0"))
+    )
+    .subcommand(App::new("raster")
+      .about("engraves the image row by row with a laser, the power follows the darkness of each pixel")
+      .version("0.1.0")
+      .author("Virghileanu Teodor <@GaussianWonder>")
+      .arg(Arg::new("line_interval")
+        .long("line-interval")
+        .value_name("MM")
+        .help("Sets the distance between engraved rows")
+        .takes_value(true)
+        .default_value("0.1"))
+      .arg(Arg::new("overscan")
+        .long("overscan")
+        .value_name("MM")
+        .help("Sets the distance travelled with the laser off before and after each row")
+        .takes_value(true)
+        .default_value("2.0"))
+      .arg(Arg::new("min_power")
+        .long("min-power")
+        .value_name("S")
+        .help("Sets the power used for the lightest engraved pixels")
+        .takes_value(true)
+        .default_value("0"))
+      .arg(Arg::new("max_power")
+        .long("max-power")
+        .value_name("S")
+        .help("Sets the power used for black pixels, usually the $30 setting of GRBL")
+        .takes_value(true)
+        .default_value("1000"))
+      .arg(Arg::new("gamma")
+        .long("gamma")
+        .value_name("FLOAT32")
+        .help("Applies gamma correction to the darkness of the pixels before mapping it to power")
+        .takes_value(true)
+        .default_value("1.0"))
+      .arg(Arg::new("keep_blank_rows")
+        .long("keep-blank-rows")
+        .help("Scans rows that have nothing to engrave as well"))
+    )
+    .subcommand(App::new("text")
+      .about("engraves a text, on its own or along with the paths of the input")
+      .version("0.1.0")
+      .author("Virghileanu Teodor <@GaussianWonder>")
+      .arg(Arg::new("TEXT")
+        .help("Sets the text to engrave, \\n starts a new line")
+        .required(true)
+        .index(1))
+      .arg(Arg::new("font")
+        .long("font")
+        .value_name("FONT")
+        .help("Single stroke hershey font, outlines of the embedded sans font, or the path of a TTF / OTF font")
+        .takes_value(true)
+        .default_value("hershey"))
+      .arg(Arg::new("size")
+        .long("size")
+        .value_name("MM")
+        .help("Sets the height of the capitals")
+        .takes_value(true)
+        .default_value("10"))
+      .arg(Arg::new("line_spacing")
+        .long("line-spacing")
+        .value_name("FLOAT32")
+        .help("Sets the distance between baselines, as a multiple of the size")
+        .takes_value(true)
+        .default_value("1.5"))
+      .arg(Arg::new("align")
+        .long("align")
+        .value_name("ALIGN")
+        .help("Aligns the lines on the position by their start, middle or end")
+        .takes_value(true)
+        .possible_values(["left", "center", "right"])
+        .default_value("left"))
+      .arg(Arg::new("at")
+        .long("at")
+        .value_name("X,Y")
+        .help("Sets the position of the first baseline (mm), from the top left corner")
+        .takes_value(true)
+        .default_value("0,10"))
+      .arg(Arg::new("point_precision")
+        .short('p')
+        .long("p_precision")
+        .value_name("FLOAT32")
+        .help("Point precision of the input paths. See the export subcommand for details")
+        .takes_value(true)
+        .default_value("1.0"))
+      .arg(Arg::new("gcode")
+        .short('g')
+        .long("gcode")
+        .help("Exports G-code along with the cnc commands"))
+    )
+    .subcommand(App::new("barcode")
+      .about("engraves a QR code or a Code128 barcode, on its own or along with the paths of the input")
+      .version("0.1.0")
+      .author("Virghileanu Teodor <@GaussianWonder>")
+      .arg(Arg::new("KIND")
+        .help("Sets the kind of symbol")
+        .required(true)
+        .possible_values(["qr", "code128"])
+        .index(1))
+      .arg(Arg::new("DATA")
+        .help("Sets the data to encode")
+        .required(true)
+        .index(2))
+      .arg(Arg::new("module")
+        .long("module")
+        .value_name("MM")
+        .help("Sets the width of a module, the narrowest bar or the side of a QR code square")
+        .takes_value(true)
+        .default_value("1"))
+      .arg(Arg::new("ec")
+        .long("ec")
+        .value_name("LEVEL")
+        .help("Sets the error correction level of a QR code, from L (7%) to H (30%)")
+        .takes_value(true)
+        .possible_values(["L", "M", "Q", "H"])
+        .default_value("M"))
+      .arg(Arg::new("height")
+        .long("height")
+        .value_name("MM")
+        .help("Sets the height of the bars of a Code128 barcode")
+        .takes_value(true)
+        .default_value("15"))
+      .arg(Arg::new("style")
+        .long("style")
+        .value_name("STYLE")
+        .help("Cuts along the outlines of the dark modules, or hatches them")
+        .takes_value(true)
+        .possible_values(["outline", "hatch"])
+        .default_value("outline"))
+      .arg(Arg::new("spacing")
+        .long("spacing")
+        .value_name("MM")
+        .help("Sets the distance between the hatch lines")
+        .takes_value(true)
+        .default_value("0.2"))
+      .arg(Arg::new("tool")
+        .long("tool")
+        .value_name("MM")
+        .help("Sets the width of the tool, the toolpaths stay half of it inside the modules")
+        .takes_value(true)
+        .default_value("0"))
+      .arg(Arg::new("at")
+        .long("at")
+        .value_name("X,Y")
+        .help("Sets the position of the top left corner of the symbol, quiet zone included (mm)")
+        .takes_value(true)
+        .default_value("0,0"))
+      .arg(Arg::new("point_precision")
+        .short('p')
+        .long("p_precision")
+        .value_name("FLOAT32")
+        .help("Point precision of the input paths. See the export subcommand for details")
+        .takes_value(true)
+        .default_value("1.0"))
+      .arg(Arg::new("gcode")
+        .short('g')
+        .long("gcode")
+        .help("Exports G-code along with the cnc commands"))
+    )
+    .subcommand(App::new("resume")
+      .about("resumes an interrupted job. The input is a command file or G-code instead of an image")
+      .version("0.1.0")
+      .author("Virghileanu Teodor <@GaussianWonder>")
+      .arg(Arg::new("line")
+        .long("line")
+        .value_name("LINE")
+        .help("Resumes from the given (1 based) line of the program")
+        .takes_value(true))
+      .arg(Arg::new("edge")
+        .long("edge")
+        .value_name("INDEX")
+        .help("Resumes from the start of the given (0 based) edge")
+        .takes_value(true))
+      .arg(Arg::new("percent")
+        .long("percent")
+        .value_name("FLOAT32")
+        .help("Resumes from the given percentage of the estimated job time")
+        .takes_value(true))
+      .group(ArgGroup::new("resume_point")
+        .args(&["line", "edge", "percent"])
+        .required(true))
     ).get_matches()
 }
 
 fn check_input_extension(input_file: &PathBuf) -> bool {
-  let accepted_extensions = vec!["jpg", "jpeg", "png", "gif", "ico", "pnm", "farbfeld"];
+  let accepted_extensions = vec!["jpg", "jpeg", "png", "gif", "ico", "pnm", "farbfeld", "svg", "dxf", "job", "json", "txt"];
   if let Some(extension) = input_file.extension() {
     let lower = extension.to_ascii_lowercase();
     let ext = lower.to_str().unwrap();
@@ -143,6 +725,341 @@ fn check_input_extension(input_file: &PathBuf) -> bool {
   }
 }
 
+fn check_program_extension(input_file: &Path) -> bool {
+  let accepted_extensions = ["txt", "gcode", "nc", "ngc"];
+  if let Some(extension) = input_file.extension() {
+    let lower = extension.to_ascii_lowercase();
+    let ext = lower.to_str().unwrap();
+    accepted_extensions.contains(&ext)
+  }
+  else {
+    false
+  }
+}
+
+fn get_resume_point(args: &ArgMatches) -> Option<ResumePoint> {
+  let resume = args.subcommand_matches("resume")?;
+  let index = |name: &str| match resume.value_of(name).unwrap().parse::<usize>() {
+    Ok(i) => i,
+    Err(_) => {
+      panic!("The {} provided is not a valid integer.", name);
+    }
+  };
+
+  if resume.is_present("line") {
+    Some(ResumePoint::Line(index("line")))
+  }
+  else if resume.is_present("edge") {
+    Some(ResumePoint::Edge(index("edge")))
+  }
+  else {
+    parse_f32(resume, "percent").map(ResumePoint::Percent)
+  }
+}
+
+fn get_separation(args: &ArgMatches) -> Option<SeparationOptions> {
+  let palette = args.value_of("palette").map(|palette| {
+    palette
+      .split(',')
+      .map(|color| match parse_hex_color(color.trim()) {
+        Some(c) => c,
+        None => {
+          panic!("The palette color {} is not a valid #RRGGBB color.", color);
+        }
+      })
+      .collect::<Vec<[u8; 3]>>()
+  });
+  let colors = args.value_of("colors").map(|colors| match colors.parse::<usize>() {
+    Ok(c) if c > 0 => c,
+    _ => {
+      panic!("The number of colors provided is not a valid positive integer.");
+    }
+  });
+
+  if palette.is_none() && colors.is_none() {
+    return None;
+  }
+
+  Some(SeparationOptions {
+    colors: colors.unwrap_or_else(|| palette.as_ref().unwrap().len()),
+    palette,
+    split_files: args.is_present("split_pens"),
+    include_background: args.is_present("include_background"),
+  })
+}
+
+fn get_fill(args: &ArgMatches) -> Option<FillOptions> {
+  let spacing = parse_f32(args, "hatch")?;
+  if spacing <= 0.0 {
+    panic!("The hatch spacing must be greater than 0.");
+  }
+
+  Some(FillOptions {
+    spacing,
+    angle: parse_f32(args, "hatch_angle").unwrap_or(45.0),
+    cross_hatch: args.is_present("cross_hatch"),
+    threshold: match args.value_of("fill_threshold").unwrap().parse::<u8>() {
+      Ok(t) => t,
+      Err(_) => {
+        panic!("The fill threshold provided is not a valid value between 0 and 255.");
+      }
+    },
+  })
+}
+
+fn parse_usize(args: &ArgMatches, name: &str) -> Option<usize> {
+  args.value_of(name).map(|value| match value.parse::<usize>() {
+    Ok(v) => v,
+    Err(_) => {
+      panic!("The {} provided is not a valid positive integer.", name.replace('_', " "));
+    }
+  })
+}
+
+fn get_shading(args: &ArgMatches) -> Option<ShadingOptions> {
+  let mode = match args.value_of("shading")? {
+    "stipple" => ShadingMode::Stipple,
+    "squiggle" => ShadingMode::Squiggle,
+    "spiral" => ShadingMode::Spiral,
+    "tsp" => ShadingMode::Tsp,
+    _ => ShadingMode::Hatch,
+  };
+  let default = ShadingOptions::default();
+
+  Some(ShadingOptions {
+    mode,
+    spacing: parse_f32(args, "shading_spacing").unwrap_or(default.spacing),
+    angle: parse_f32(args, "hatch_angle").unwrap_or(default.angle),
+    levels: parse_usize(args, "shading_levels").unwrap_or(default.levels),
+    points: parse_usize(args, "stipples").unwrap_or(default.points),
+    iterations: parse_usize(args, "stipple_iterations").unwrap_or(default.iterations),
+    dot_size: parse_f32(args, "dot_size").unwrap_or(default.dot_size),
+    tsp_iterations: parse_usize(args, "tsp_iterations").unwrap_or(default.tsp_iterations),
+  })
+}
+
+// pockets are traced the same way
+fn get_contours(args: &ArgMatches) -> Option<ContourOptions> {
+  if !args.is_present("contours") && !args.is_present("pocket") {
+    return None;
+  }
+  let options = ContourOptions {
+    threshold: match args.value_of("contour_threshold").unwrap().parse::<u8>() {
+      Ok(t) => t,
+      Err(_) => {
+        panic!("The contour threshold provided is not a valid value between 0 and 255.");
+      }
+    },
+    tolerance: parse_f32(args, "contour_tolerance").unwrap(),
+    kerf: parse_f32(args, "kerf").unwrap(),
+  };
+
+  if options.kerf < 0.0 {
+    panic!("The kerf must not be negative.");
+  }
+  Some(options)
+}
+
+fn get_lead(args: &ArgMatches, name: &str) -> Option<Lead> {
+  let shape = match args.value_of(name)? {
+    "arc" => LeadShape::Arc,
+    _ => LeadShape::Line,
+  };
+  let lead = Lead {
+    shape,
+    length: parse_f32(args, &format!("{}_length", name)).unwrap(),
+    angle: parse_f32(args, &format!("{}_angle", name)).unwrap(),
+  };
+
+  if lead.length <= 0.0 {
+    panic!("The {} length must be greater than 0.", name.replace('_', " "));
+  }
+  Some(lead)
+}
+
+// leads are only added to contours, but the pierce point and dwell can be set without them
+fn get_leads(args: &ArgMatches) -> Option<LeadOptions> {
+  let options = LeadOptions {
+    lead_in: get_lead(args, "lead_in"),
+    lead_out: get_lead(args, "lead_out"),
+    pierce: match args.value_of("pierce") {
+      Some("top") => PierceRule::Side(Side::Top),
+      Some("bottom") => PierceRule::Side(Side::Bottom),
+      Some("left") => PierceRule::Side(Side::Left),
+      Some("right") => PierceRule::Side(Side::Right),
+      _ => PierceRule::NearestCorner,
+    },
+    dwell: parse_f32(args, "pierce_dwell").unwrap_or(0.0),
+  };
+
+  if options.dwell < 0.0 {
+    panic!("The pierce dwell must not be negative.");
+  }
+  if options.lead_in.is_none() && options.lead_out.is_none() && !args.is_present("pierce") && options.dwell == 0.0 {
+    return None;
+  }
+  Some(options)
+}
+
+fn get_tabs(args: &ArgMatches) -> Option<TabOptions> {
+  let options = TabOptions {
+    count: parse_usize(args, "tabs")?,
+    width: parse_f32(args, "tab_width").unwrap(),
+    placement: match args.value_of("tab_placement") {
+      Some("straight") => TabPlacement::Straight,
+      _ => TabPlacement::Even,
+    },
+  };
+
+  if options.width <= 0.0 {
+    panic!("The tab width must be greater than 0.");
+  }
+  Some(options)
+}
+
+fn get_pocket(args: &ArgMatches) -> Option<PocketOptions> {
+  let options = PocketOptions {
+    mode: match args.value_of("pocket")? {
+      "zigzag" => PocketMode::ZigZag,
+      _ => PocketMode::Offset,
+    },
+    step_over: parse_f32(args, "step_over").unwrap(),
+    angle: parse_f32(args, "pocket_angle").unwrap(),
+  };
+
+  if options.step_over <= 0.0 {
+    panic!("The step over must be greater than 0.");
+  }
+  Some(options)
+}
+
+fn get_text(args: &ArgMatches) -> Option<TextOptions> {
+  let text = args.subcommand_matches("text")?;
+  let position = parse_position(text, "text");
+  let options = TextOptions {
+    text: text.value_of("TEXT").unwrap().to_string(),
+    font: match text.value_of("font").unwrap() {
+      "hershey" => TextFont::Hershey,
+      "sans" => TextFont::Sans,
+      path => TextFont::File(PathBuf::from(path)),
+    },
+    size: parse_f32(text, "size").unwrap(),
+    line_spacing: parse_f32(text, "line_spacing").unwrap(),
+    align: match text.value_of("align") {
+      Some("center") => TextAlign::Center,
+      Some("right") => TextAlign::Right,
+      _ => TextAlign::Left,
+    },
+    position,
+  };
+
+  if options.size <= 0.0 {
+    panic!("The text size must be greater than 0.");
+  }
+  Some(options)
+}
+
+fn get_barcode(args: &ArgMatches) -> Option<BarcodeOptions> {
+  let barcode = args.subcommand_matches("barcode")?;
+  let options = BarcodeOptions {
+    kind: match barcode.value_of("KIND") {
+      Some("code128") => BarcodeKind::Code128,
+      _ => BarcodeKind::Qr,
+    },
+    data: barcode.value_of("DATA").unwrap().to_string(),
+    level: match barcode.value_of("ec") {
+      Some("L") => EcLevel::L,
+      Some("Q") => EcLevel::Q,
+      Some("H") => EcLevel::H,
+      _ => EcLevel::M,
+    },
+    module: parse_f32(barcode, "module").unwrap(),
+    height: parse_f32(barcode, "height").unwrap(),
+    style: match barcode.value_of("style") {
+      Some("hatch") => BarcodeStyle::Hatch,
+      _ => BarcodeStyle::Outline,
+    },
+    spacing: parse_f32(barcode, "spacing").unwrap(),
+    tool: parse_f32(barcode, "tool").unwrap(),
+    position: parse_position(barcode, "barcode"),
+  };
+
+  if options.module <= 0.0 {
+    panic!("The module must be greater than 0.");
+  }
+  if options.height <= 0.0 {
+    panic!("The height must be greater than 0.");
+  }
+  if options.spacing <= 0.0 {
+    panic!("The spacing must be greater than 0.");
+  }
+  if options.tool < 0.0 {
+    panic!("The tool width must not be negative.");
+  }
+  Some(options)
+}
+
+fn get_arc_tolerance(args: &ArgMatches) -> Option<f32> {
+  let tolerance = parse_f32(args, "gcode_arcs")?;
+  if tolerance <= 0.0 {
+    panic!("The gcode arcs tolerance must be greater than 0.");
+  }
+  Some(tolerance)
+}
+
+fn get_flatten_tolerance(args: &ArgMatches) -> f32 {
+  let tolerance = parse_f32(args, "flatten_tolerance").unwrap();
+  if tolerance <= 0.0 {
+    panic!("The flatten tolerance must be greater than 0.");
+  }
+  tolerance
+}
+
+fn get_depth(args: &ArgMatches) -> Option<DepthOptions> {
+  let step_down = parse_f32(args, "step_down").unwrap();
+  let options = DepthOptions {
+    safe_z: parse_f32(args, "safe_z").unwrap_or(DepthOptions::default().safe_z),
+    start_depth: parse_f32(args, "start_depth").unwrap_or(step_down),
+    step_down,
+    final_depth: parse_f32(args, "final_depth")?,
+    ramp: parse_f32(args, "ramp"),
+  };
+
+  if options.step_down <= 0.0 {
+    panic!("The step down must be greater than 0.");
+  }
+  if options.final_depth <= 0.0 || options.start_depth <= 0.0 {
+    panic!("The depths must be greater than 0.");
+  }
+  Some(options)
+}
+
+fn get_raster_options(args: &ArgMatches) -> Option<RasterOptions> {
+  let raster = args.subcommand_matches("raster")?;
+  let power = |name: &str| match raster.value_of(name).unwrap().parse::<u32>() {
+    Ok(p) => p,
+    Err(_) => {
+      panic!("The {} provided is not a valid positive integer.", name.replace('_', " "));
+    }
+  };
+  let options = RasterOptions {
+    line_interval: parse_f32(raster, "line_interval").unwrap(),
+    overscan: parse_f32(raster, "overscan").unwrap(),
+    min_power: power("min_power"),
+    max_power: power("max_power"),
+    gamma: parse_f32(raster, "gamma").unwrap(),
+    skip_blank_rows: !raster.is_present("keep_blank_rows"),
+  };
+
+  if options.line_interval <= 0.0 {
+    panic!("The line interval must be greater than 0.");
+  }
+  if options.min_power > options.max_power {
+    panic!("The min power must not be greater than the max power.");
+  }
+  Some(options)
+}
+
 fn get_export_options(args: &ArgMatches) -> ExportOptions {
   if let Some(export) = args.subcommand_matches("export") {
     let point_precision = if let Some(point_precision) = export.value_of("point_precision") {
@@ -179,6 +1096,28 @@ fn get_export_options(args: &ArgMatches) -> ExportOptions {
       debug_preview,
       exclude_individual_edges,
       exclude_cnc,
+      gcode: export.is_present("gcode"),
+    }
+  }
+  else if let Some(generated) = args.subcommand_matches("text").or_else(|| args.subcommand_matches("barcode")) {
+    ExportOptions {
+      point_precision: parse_f32(generated, "point_precision"),
+      image: false,
+      debug_preview: None,
+      exclude_individual_edges: true,
+      exclude_cnc: false,
+      gcode: generated.is_present("gcode"),
+    }
+  }
+  else if let Some(send) = args.subcommand_matches("send") {
+    // sending needs the points, every other export is skipped
+    ExportOptions {
+      point_precision: parse_f32(send, "point_precision"),
+      image: false,
+      debug_preview: None,
+      exclude_individual_edges: true,
+      exclude_cnc: false,
+      gcode: true,
     }
   }
   else {
@@ -188,31 +1127,143 @@ fn get_export_options(args: &ArgMatches) -> ExportOptions {
       debug_preview: None,
       exclude_individual_edges: false,
       exclude_cnc: false,
+      gcode: false,
     }
   }
 }
 
-pub fn get() -> Config {
-  let args = get_raw();
+fn get_send_options(args: &ArgMatches) -> Option<SendOptions> {
+  args.subcommand_matches("send").map(|send| SendOptions {
+    port: PathBuf::from(send.value_of("port").unwrap()),
+    baud: match send.value_of("baud").unwrap().parse::<u32>() {
+      Ok(b) => b,
+      Err(_) => {
+        panic!("The baud rate provided is not a valid integer.");
+      }
+    },
+    protocol: match send.value_of("protocol") {
+      Some("ack") => Protocol::SendResponse,
+      _ => Protocol::CharacterCounting,
+    },
+  })
+}
+
+fn parse_f32(args: &ArgMatches, name: &str) -> Option<f32> {
+  args.value_of(name).map(|value| match value.parse::<f32>() {
+    Ok(v) => v,
+    Err(_) => {
+      panic!("The {} provided is not a valid float32.", name.replace('_', " "));
+    }
+  })
+}
 
-  let input_file = PathBuf::from(args.value_of("INPUT").unwrap());
-  if !input_file.is_file() {
-    panic!("The input provided does not point to a file or does not exist.");
+// the X,Y value of --at, in mm
+fn parse_position(args: &ArgMatches, what: &str) -> (f32, f32) {
+  let position = args
+    .value_of("at")
+    .unwrap()
+    .split(',')
+    .map(|value| match value.trim().parse::<f32>() {
+      Ok(v) => v,
+      Err(_) => {
+        panic!("The {} position provided is not a valid X,Y pair.", what);
+      }
+    })
+    .collect::<Vec<f32>>();
+  if position.len() != 2 {
+    panic!("The {} position must be given as X,Y.", what);
+  }
+  (position[0], position[1])
+}
+
+pub fn parse_envelope(value: &str) -> Envelope {
+  let limits = value
+    .split(',')
+    .map(|limit| match limit.trim().parse::<f32>() {
+      Ok(l) => l,
+      Err(_) => {
+        panic!("The envelope limits provided are not valid float32 values.");
+      }
+    })
+    .collect::<Vec<f32>>();
+
+  if limits.len() != 4 || limits[0] >= limits[2] || limits[1] >= limits[3] {
+    panic!("The envelope must be given as X_MIN,Y_MIN,X_MAX,Y_MAX.");
+  }
+
+  Envelope {
+    min_x: limits[0],
+    min_y: limits[1],
+    max_x: limits[2],
+    max_y: limits[3],
   }
-  
-  if check_input_extension(&input_file) == false {
-    panic!("The input file does not have a valid extension.");
+}
+
+fn get_machine(args: &ArgMatches) -> MachineModel {
+  let default = MachineModel::default();
+  MachineModel {
+    max_feed: parse_f32(args, "max_feed").unwrap_or(default.max_feed),
+    travel_rate: parse_f32(args, "travel_rate").unwrap_or(default.travel_rate),
+    acceleration: parse_f32(args, "acceleration").unwrap_or(default.acceleration),
+    pen_delay: parse_f32(args, "pen_delay").unwrap_or(default.pen_delay),
+    unit_size: parse_f32(args, "unit_size").unwrap_or(default.unit_size),
+    envelope: args.value_of("envelope").map(parse_envelope),
   }
+}
+
+pub fn get() -> Config {
+  let args = get_raw();
+
+  let resume = get_resume_point(&args);
+  let text = get_text(&args);
+  let barcode = get_barcode(&args);
+  // a text or a barcode is engraved on its own when there is no input
+  let (input_file, file_name, file_extension) = match args.value_of("INPUT") {
+    Some(input) => {
+      let input_file = PathBuf::from(input);
+      if !input_file.is_file() {
+        panic!("The input provided does not point to a file or does not exist.");
+      }
+
+      let valid_extension = match resume {
+        Some(_) => check_program_extension(&input_file),
+        None => check_input_extension(&input_file),
+      };
+      if !valid_extension {
+        panic!("The input file does not have a valid extension.");
+      }
+
+      let file_name = input_file.with_extension("").file_name().unwrap().to_ascii_lowercase().to_str().unwrap().to_string();
+      let file_extension = input_file.extension().unwrap().to_ascii_lowercase().to_str().unwrap().to_string();
+      // a previous export is exported again under the name of the input it was made from
+      let suffix = match (resume, file_extension.as_str()) {
+        (None, "json") => "_points",
+        (None, "txt") => "_command",
+        _ => "",
+      };
+      let file_name = file_name.strip_suffix(suffix).map(|name| name.to_string()).unwrap_or(file_name);
+      (input_file, file_name, file_extension)
+    },
+    None if text.is_some() => (PathBuf::new(), "text".to_string(), String::new()),
+    None if barcode.is_some() => (PathBuf::new(), "barcode".to_string(), String::new()),
+    None => panic!("The input is required, except for the text and barcode subcommands."),
+  };
 
   let export_path = if let Some(export_path) = args.value_of("output") {
     PathBuf::from(export_path)
   }
   else {
-    input_file.parent().unwrap().to_path_buf()
+    input_file.parent().map(|parent| parent.to_path_buf()).unwrap_or_default()
   };
 
-  let file_name = input_file.with_extension("").file_name().unwrap().to_ascii_lowercase().to_str().unwrap().to_string();
-  let file_extension = input_file.extension().unwrap().to_ascii_lowercase().to_str().unwrap().to_string();
+  // a re-imported export would be overwritten while it is read
+  let overwritten = |name: String| match (std::fs::canonicalize(&export_path), std::fs::canonicalize(&input_file)) {
+    (Ok(folder), Ok(input)) => folder.join(name) == input,
+    _ => false,
+  };
+  if resume.is_none() && (overwritten(format!("{}_points.json", file_name)) || overwritten(format!("{}_command.txt", file_name))) {
+    panic!("The exports would overwrite the input file, write them to another folder with -o.");
+  }
 
   let export = get_export_options(&args);
 
@@ -239,6 +1290,19 @@ pub fn get() -> Config {
     },
     None => 60.0,
   };
+  let raster = get_raster_options(&args);
+  let leads = get_leads(&args);
+  let tabs = get_tabs(&args);
+  // raster jobs scan rows, there are no contours to pierce, lead into or hold with tabs
+  if raster.is_some() && (leads.is_some() || tabs.is_some()) {
+    panic!("The lead, pierce and tab options do not apply to raster engraving.");
+  }
+  // the leads of a command file can't be told apart from its cuts, new ones would be added on top of them
+  let leads_added = leads.map(|l| l.lead_in.is_some() || l.lead_out.is_some()).unwrap_or(false);
+  if file_extension == "txt" && resume.is_none() && (leads_added || tabs.is_some()) {
+    panic!("The lead-in, lead-out and tab options do not apply to command files, re-import the points file instead.");
+  }
+  let depth = get_depth(&args);
 
   Config {
     input_file,
@@ -249,5 +1313,37 @@ pub fn get() -> Config {
     high_threshold,
     skip_canny_edge_detection: args.is_present("skip_canny_edge_detection"),
     export_options: export,
+    machine: get_machine(&args),
+    gcode: GcodeOptions {
+      pen_up: args.value_of("pen_up_gcode").unwrap().to_string(),
+      pen_down: args.value_of("pen_down_gcode").unwrap().to_string(),
+      tab_height: parse_f32(&args, "tab_height"),
+      safe_z: depth.map(|d| d.safe_z).or_else(|| parse_f32(&args, "safe_z")),
+      plunge_feed: parse_f32(&args, "plunge_feed").unwrap(),
+      arc_tolerance: get_arc_tolerance(&args),
+      ..GcodeOptions::default()
+    },
+    send_options: get_send_options(&args),
+    resume,
+    separation: get_separation(&args),
+    fill: get_fill(&args),
+    shading: get_shading(&args),
+    raster,
+    contours: get_contours(&args),
+    cut_order: match args.value_of("cut_order") {
+      Some("inside-out") => CutOrder::InsideOut,
+      _ => CutOrder::Traced,
+    },
+    leads,
+    tabs,
+    depth,
+    pocket: get_pocket(&args),
+    flatten_tolerance: get_flatten_tolerance(&args),
+    layers: args
+      .value_of("layers")
+      .map(|names| names.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect())
+      .unwrap_or_default(),
+    text,
+    barcode,
   }
 }
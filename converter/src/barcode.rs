@@ -0,0 +1,253 @@
+// QR codes and Code128 barcodes as toolpaths: the outlines of the dark modules, or hatches filling them
+use crate::contour::{self, Contour};
+use crate::fill::Polyline;
+use crate::offset;
+use crate::pocket::{self, PocketMode, PocketOptions};
+use crate::qr::{self, EcLevel};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarcodeKind {
+  Qr,
+  Code128,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarcodeStyle {
+  // the outline of each group of dark modules
+  Outline,
+  // zig-zag lines filling the dark modules, followed by their outlines
+  Hatch,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BarcodeOptions {
+  pub kind: BarcodeKind,
+  pub data: String,
+  // QR codes only
+  pub level: EcLevel,
+  // width of a module (mm)
+  pub module: f32,
+  // height of the bars of a Code128 barcode (mm)
+  pub height: f32,
+  pub style: BarcodeStyle,
+  // distance between the hatch lines (mm)
+  pub spacing: f32,
+  // width of the tool (mm), the toolpaths stay half of it inside the modules
+  pub tool: f32,
+  // top left corner of the symbol, quiet zone included (mm)
+  pub position: (f32, f32),
+}
+
+impl Default for BarcodeOptions {
+  fn default() -> Self {
+    BarcodeOptions {
+      kind: BarcodeKind::Qr,
+      data: String::new(),
+      level: EcLevel::M,
+      module: 1.0,
+      height: 15.0,
+      style: BarcodeStyle::Outline,
+      spacing: 0.2,
+      tool: 0.0,
+      position: (0.0, 0.0),
+    }
+  }
+}
+
+// light modules around the symbols, as required by the standards
+const QR_QUIET_ZONE: usize = 4;
+const CODE128_QUIET_ZONE: usize = 10;
+const PIXELS_PER_MODULE: u32 = 2;
+
+// widths of the bars and spaces of each symbol value, bar first
+const CODE128_PATTERNS: [&str; 107] = [
+  "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212", "221213",
+  "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221", "223211", "221132",
+  "221231", "213212", "223112", "312131", "311222", "321122", "321221", "312212", "322112", "322211",
+  "212123", "212321", "232121", "111323", "131123", "131321", "112313", "132113", "132311", "211313",
+  "231113", "231311", "112133", "112331", "132131", "113123", "113321", "133121", "313121", "211331",
+  "231131", "213113", "213311", "213131", "311123", "311321", "331121", "312113", "312311", "332111",
+  "314111", "221411", "431111", "111224", "111422", "121124", "121421", "141122", "141221", "112214",
+  "112412", "122114", "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111",
+  "111242", "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+  "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311", "113141",
+  "114131", "311141", "411131", "211412", "211214", "211232", "2331112",
+];
+
+const CODE128_CODE_C: usize = 99;
+const CODE128_CODE_B: usize = 100;
+const CODE128_START_B: usize = 104;
+const CODE128_START_C: usize = 105;
+const CODE128_STOP: usize = 106;
+
+// symbol values of the data: code set B for printable ASCII, code set C for runs of at least four digits
+fn code128_values(data: &str) -> Result<Vec<usize>, String> {
+  if let Some(c) = data.chars().find(|c| !(' '..='~').contains(c)) {
+    return Err(format!("Code128 barcodes only hold printable ASCII characters, {:?} is not one", c));
+  }
+  let bytes = data.as_bytes();
+  let digits = |from: usize| bytes[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+  let mut values = vec![];
+  let mut code_c = false;
+  let mut i = 0;
+  while i < bytes.len() {
+    let run = digits(i);
+    if code_c && run < 2 {
+      values.push(CODE128_CODE_B);
+      code_c = false;
+    }
+    // an odd run starts in code set B
+    if !code_c && run >= 4 && run % 2 == 0 {
+      values.push(if values.is_empty() { CODE128_START_C } else { CODE128_CODE_C });
+      code_c = true;
+    }
+    if values.is_empty() {
+      values.push(CODE128_START_B);
+    }
+    if code_c {
+      values.push(((bytes[i] - b'0') * 10 + bytes[i + 1] - b'0') as usize);
+      i += 2;
+    }
+    else {
+      values.push((bytes[i] - b' ') as usize);
+      i += 1;
+    }
+  }
+  if values.is_empty() {
+    values.push(CODE128_START_B);
+  }
+
+  let checksum = values.iter().enumerate().map(|(i, v)| i.max(1) * v).sum::<usize>() % 103;
+  values.push(checksum);
+  values.push(CODE128_STOP);
+  Ok(values)
+}
+
+// the dark modules of a single row, without the quiet zone
+fn code128(data: &str) -> Result<Vec<bool>, String> {
+  let mut modules = vec![];
+  for value in code128_values(data)? {
+    for (i, width) in CODE128_PATTERNS[value].bytes().enumerate() {
+      modules.resize(modules.len() + (width - b'0') as usize, i % 2 == 0);
+    }
+  }
+  Ok(modules)
+}
+
+// toolpaths of the symbol in command units
+pub fn paths(options: &BarcodeOptions, unit_size: f32) -> Result<Vec<Polyline>, String> {
+  if options.data.is_empty() {
+    return Err("There is no data to encode".to_string());
+  }
+  // rows of modules with the quiet zone, and the height of a row (mm)
+  let (rows, quiet_zone, row_height) = match options.kind {
+    BarcodeKind::Qr => (qr::encode_text(&options.data, options.level)?, QR_QUIET_ZONE, options.module),
+    BarcodeKind::Code128 => (vec![code128(&options.data)?], CODE128_QUIET_ZONE, options.height),
+  };
+  // modules are traced as squares of pixels, as smaller outlines than a few pixels are dropped as noise
+  let width = rows[0].len() as u32 * PIXELS_PER_MODULE;
+  let height = rows.len() as u32 * PIXELS_PER_MODULE;
+  let traced = contour::trace(width, height, |x, y| rows[(y / PIXELS_PER_MODULE) as usize][(x / PIXELS_PER_MODULE) as usize], 0.0);
+
+  // pixel (x, y) covers [x - 0.5, x + 0.5], the modules are scaled into place
+  let scale = |v: f32| (v + 0.5) / PIXELS_PER_MODULE as f32;
+  let origin = (
+    options.position.0 + quiet_zone as f32 * options.module,
+    options.position.1 + if rows.len() > 1 { quiet_zone as f32 * options.module } else { 0.0 },
+  );
+  let contours = traced
+    .into_iter()
+    .map(|c| {
+      let points = c
+        .points
+        .iter()
+        .map(|(x, y)| ((origin.0 + scale(*x) * options.module) / unit_size, (origin.1 + scale(*y) * row_height) / unit_size))
+        .collect();
+      Contour::new(points)
+    })
+    .collect::<Vec<Contour>>();
+
+  let radius = options.tool / 2.0 / unit_size;
+  if options.style == BarcodeStyle::Outline {
+    return Ok(offset::inset(&contours, radius).iter().map(|c| c.closed()).collect());
+  }
+  // bars are hatched along their length
+  let angle = if options.kind == BarcodeKind::Code128 { 90.0 } else { 0.0 };
+  let hatch = PocketOptions { mode: PocketMode::ZigZag, step_over: options.spacing, angle };
+  let (mut paths, outlines) = pocket::pocket(&contours, &hatch, radius, unit_size);
+  paths.extend(outlines);
+  Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn bounds(paths: &[Polyline]) -> ((f32, f32), (f32, f32)) {
+    crate::path::bounds(&paths.concat())
+  }
+
+  #[test]
+  fn code128_code_sets_and_checksums() {
+    assert_eq!(code128_values("Wikipedia").unwrap(), vec![104, 55, 73, 75, 73, 80, 69, 68, 73, 65, 88, 106]);
+    // even runs of four digits or more are packed in code set C
+    assert_eq!(code128_values("12345678").unwrap(), vec![105, 12, 34, 56, 78, 47, 106]);
+    // an odd run starts in code set B, then switches
+    assert_eq!(code128_values("AB1234567").unwrap(), vec![104, 33, 34, 17, 99, 23, 45, 67, 64, 106]);
+    // and switches back for the other characters
+    assert_eq!(code128_values("1234A").unwrap(), vec![105, 12, 34, 100, 33, 102, 106]);
+    assert_eq!(code128_values("X9").unwrap(), vec![104, 56, 25, 4, 106]);
+    assert!(code128_values("né").is_err());
+  }
+
+  #[test]
+  fn code128_modules() {
+    // eleven modules per symbol, thirteen for the stop pattern, starting and ending on a bar
+    let modules = code128("X9").unwrap();
+    assert_eq!(modules.len(), 4 * 11 + 13);
+    assert!(modules[0] && modules[modules.len() - 1]);
+    // start B is 211214
+    assert_eq!(&modules[..11], &[true, true, false, true, false, false, true, false, false, false, false]);
+  }
+
+  #[test]
+  fn code128_outlines() {
+    let options = BarcodeOptions { kind: BarcodeKind::Code128, data: "X9".to_string(), ..BarcodeOptions::default() };
+    let paths = paths(&options, 0.5).unwrap();
+    // three bars per symbol, four in the stop pattern
+    assert_eq!(paths.len(), 4 * 3 + 4);
+    assert!(paths.iter().all(|path| path[0] == path[path.len() - 1]));
+    // in command units, after the quiet zone
+    let (min, max) = bounds(&paths);
+    assert!((min.0 - 20.0).abs() < 1e-3 && min.1.abs() < 1e-3);
+    assert!((max.0 - 134.0).abs() < 1e-3 && (max.1 - 30.0).abs() < 1e-3);
+
+    // the tool stays inside the bars
+    let options = BarcodeOptions { tool: 0.2, position: (5.0, 5.0), ..options };
+    let (min, max) = bounds(&super::paths(&options, 1.0).unwrap());
+    assert!((min.0 - 15.1).abs() < 1e-3 && (min.1 - 5.1).abs() < 1e-3);
+    assert!((max.0 - 71.9).abs() < 1e-3 && (max.1 - 19.9).abs() < 1e-3);
+  }
+
+  #[test]
+  fn qr_outlines() {
+    let options = BarcodeOptions { data: "01234567".to_string(), ..BarcodeOptions::default() };
+    let outlines = paths(&options, 1.0).unwrap();
+    assert!(outlines.iter().all(|path| path[0] == path[path.len() - 1]));
+    // the finder patterns reach the corners of the 21 modules, inside the quiet zone
+    let (min, max) = bounds(&outlines);
+    assert!((min.0 - 4.0).abs() < 1e-3 && (min.1 - 4.0).abs() < 1e-3);
+    assert!((max.0 - 25.0).abs() < 1e-3 && (max.1 - 25.0).abs() < 1e-3);
+
+    // hatches come before the outlines
+    let hatched = paths(&BarcodeOptions { style: BarcodeStyle::Hatch, ..options }, 1.0).unwrap();
+    assert!(hatched.len() > outlines.len());
+    assert_eq!(hatched[hatched.len() - outlines.len()..], outlines[..]);
+  }
+
+  #[test]
+  fn empty_data() {
+    assert!(paths(&BarcodeOptions::default(), 1.0).is_err());
+  }
+}
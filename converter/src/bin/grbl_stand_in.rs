@@ -0,0 +1,19 @@
+// Virtual GRBL device on a pseudo-terminal, to be used in place of real hardware
+// cargo run --bin grbl_stand_in, then point the send subcommand to the printed port
+use converter::grbl::{self, Emulator, Interpreter};
+
+#[cfg(unix)]
+fn main() {
+  let (mut master, _slave, path) = grbl::open_pty().expect("Could not open a pseudo-terminal");
+  println!("GRBL stand-in listening on {}", path.display());
+
+  let mut emulator = Emulator::new(Interpreter::new(1.0, None));
+  if let Err(e) = emulator.serve(&mut master) {
+    println!("Stand-in stopped: {:?}", e);
+  }
+}
+
+#[cfg(not(unix))]
+fn main() {
+  println!("Pseudo-terminals are only available on unix platforms");
+}
@@ -7,20 +7,44 @@ use serde::{Deserialize, Serialize};
 // Serializable PixelIndex and its edge equivalents
 #[derive(Serialize, Deserialize, Copy, Clone)]
 pub struct SPixelIndex<T> {
-    x: T,
-    y: T,
+    pub x: T,
+    pub y: T,
 }
 pub type SEdge<T> = Vec<SPixelIndex<T>>;
 pub type SEdges<T> = Vec<SEdge<T>>;
 
+// point coordinates the writers accept: pixel indices, or sub pixel positions
+pub trait Coordinate: std::fmt::Display + Copy {
+  fn to_f32(self) -> f32;
+}
+
+impl Coordinate for usize {
+  fn to_f32(self) -> f32 {
+    self as f32
+  }
+}
+
+impl Coordinate for f32 {
+  fn to_f32(self) -> f32 {
+    self
+  }
+}
+
+// edges are pixel indices, or sub pixel positions (f32) for shading strokes and contours
 #[derive(Serialize, Deserialize, Clone)]
-pub struct SerializebleComputation {
-  pub edges: SEdges<usize>, // Vec<Vec<Point>>
+pub struct SerializebleComputation<T = usize> {
+  pub edges: SEdges<T>, // Vec<Vec<Point>>
   pub width: usize, // image width (max x)
   pub height: usize, // image height (max y)
   pub dx_skip: usize, // pixel skip on Ox
   pub dy_skip: usize, // pixel skip on Oy
   pub px_skip: usize, // pixel skip that's taken into account
+  // segments of each edge crossing a holding tab, segment i goes from point i to point i + 1
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub tabs: Vec<Vec<usize>>,
+  // points of each edge before and after its closed contour, added as lead-in and lead-out moves
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub leads: Vec<(usize, usize)>,
 }
 
 // PixelIndex and edge equivalents, used to comply with the image crate
@@ -154,24 +178,34 @@ pub fn to_points(image: &DynamicImage, point_precision: f32) -> Edges<usize> {
   edges
 }
 
-pub fn to_serializable_points(image: &DynamicImage, point_precision: f32) -> SerializebleComputation {
+// a computation without edges, for the given image size and point precision
+pub fn empty_computation<T>(image_width: u32, image_height: u32, point_precision: f32) -> SerializebleComputation<T> {
   // explained in to_points()
-  let width = (image.width() as f32 * point_precision).abs() as usize;
-  let height = (image.height() as f32 * point_precision).abs() as usize;
-  let dx_skip = (image.width() as f32 / width as f32) as usize;
-  let dy_skip = (image.height() as f32 / height as f32) as usize;
+  let width = (image_width as f32 * point_precision).abs() as usize;
+  let height = (image_height as f32 * point_precision).abs() as usize;
+  let dx_skip = (image_width as f32 / width as f32) as usize;
+  let dy_skip = (image_height as f32 / height as f32) as usize;
   let cost_skip = dx_skip + dy_skip;
 
+  SerializebleComputation {
+    edges: SEdges::<T>::new(),
+    width: image_width as usize,
+    height: image_height as usize,
+    dx_skip,
+    dy_skip,
+    px_skip: cost_skip,
+    tabs: vec![],
+    leads: vec![],
+  }
+}
+
+pub fn to_serializable_points(image: &DynamicImage, point_precision: f32) -> SerializebleComputation {
   SerializebleComputation {
     edges: to_points(image, point_precision)
       .into_iter()
       .map(|edge| edge.into_iter().map(|p| SPixelIndex { x: p.x, y: p.y }).collect())
       .collect(),
-    width: image.width() as usize,
-    height: image.height() as usize,
-    dx_skip,
-    dy_skip,
-    px_skip: cost_skip,
+    ..empty_computation(image.width(), image.height(), point_precision)
   }
 }
 
@@ -326,11 +360,16 @@ where
   copy
 }
 
-const RESET_CMD: &str = "RESET";
-const PEN_UP: &str = "PEN UP";
-const PEN_DOWN: &str = "PEN DOWN";
-const MOVE_CMD: &str = "MOVE";
-const END_CMD: &str = "END";
+pub(crate) const RESET_CMD: &str = "RESET";
+pub(crate) const PEN_UP: &str = "PEN UP";
+pub(crate) const PEN_DOWN: &str = "PEN DOWN";
+pub(crate) const MOVE_CMD: &str = "MOVE";
+pub(crate) const END_CMD: &str = "END";
+pub(crate) const TOOL_CMD: &str = "TOOL";
+pub(crate) const DWELL_CMD: &str = "DWELL";
+pub(crate) const TAB_CMD: &str = "TAB";
+pub(crate) const DEPTH_CMD: &str = "DEPTH";
+pub(crate) const RAMP_CMD: &str = "RAMP";
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PenDirection {
@@ -360,21 +399,74 @@ fn end_cmd() -> String {
   END_CMD.to_string()
 }
 
-pub fn to_cnc(computation: &SerializebleComputation) -> String {
-  let mut commands: Vec<String> = vec![
-    pen_cmd(PenDirection::UP),
-    reset_cmd()
-  ];
+fn tool_cmd(index: usize, color: [u8; 3]) -> String {
+  format!("{} {} #{:02x}{:02x}{:02x}", TOOL_CMD, index, color[0], color[1], color[2])
+}
 
-  for edge in &computation.edges {
-    commands.push(move_cmd(edge[0].x, edge[0].y));
+fn dwell_cmd(seconds: f32) -> String {
+  format!("{} {}", DWELL_CMD, seconds)
+}
+
+fn tab_cmd<T>(x: T, y: T) -> String
+where
+  T: std::fmt::Display,
+{
+  format!("{} {} {}", TAB_CMD, x, y)
+}
+
+// a dwell after lowering the pen lets a cutting torch pierce the sheet before moving
+fn edge_cmds<T: std::fmt::Display>(computation: &SerializebleComputation<T>, dwell: f32, commands: &mut Vec<String>) {
+  for (index, edge) in computation.edges.iter().enumerate() {
+    let tabs = computation.tabs.get(index).map(|tabs| tabs.as_slice()).unwrap_or(&[]);
+    commands.push(move_cmd(&edge[0].x, &edge[0].y));
     commands.push(pen_cmd(PenDirection::DOWN));
-    for point in edge {
-      commands.push(move_cmd(point.x, point.y));
+    if dwell > 0.0 {
+      commands.push(dwell_cmd(dwell));
+    }
+    for (at, point) in edge.iter().enumerate() {
+      // the cut is interrupted over the holding tabs, crossed along the edge
+      if at > 0 && tabs.contains(&(at - 1)) {
+        commands.push(tab_cmd(&point.x, &point.y));
+      }
+      else {
+        commands.push(move_cmd(&point.x, &point.y));
+      }
     }
     commands.push(pen_cmd(PenDirection::UP));
     // commands.push(reset_cmd()); // optional
   }
+}
+
+pub fn to_cnc<T: std::fmt::Display>(computation: &SerializebleComputation<T>) -> String {
+  to_pierced_cnc(computation, 0.0)
+}
+
+// same as to_cnc, waiting `dwell` seconds after each pierce
+pub fn to_pierced_cnc<T: std::fmt::Display>(computation: &SerializebleComputation<T>, dwell: f32) -> String {
+  let mut commands: Vec<String> = vec![
+    pen_cmd(PenDirection::UP),
+    reset_cmd()
+  ];
+
+  edge_cmds(computation, dwell, &mut commands);
+
+  commands.push(reset_cmd());
+  commands.push(end_cmd());
+  commands.join("\n")
+}
+
+// a single job where each layer is drawn with its own pen, a tool change precedes every layer
+// waits `dwell` seconds after each pierce, like to_pierced_cnc
+pub fn to_layered_cnc<T: std::fmt::Display>(layers: &[([u8; 3], SerializebleComputation<T>)], dwell: f32) -> String {
+  let mut commands: Vec<String> = vec![
+    pen_cmd(PenDirection::UP),
+    reset_cmd()
+  ];
+
+  for (index, (color, computation)) in layers.iter().enumerate() {
+    commands.push(tool_cmd(index + 1, *color));
+    edge_cmds(computation, dwell, &mut commands);
+  }
 
   commands.push(reset_cmd());
   commands.push(end_cmd());
@@ -0,0 +1,162 @@
+// Parsed representation of the commands emitted by canny::to_cnc
+use crate::canny::{PenDirection, RESET_CMD, PEN_UP, PEN_DOWN, MOVE_CMD, END_CMD, TOOL_CMD, DWELL_CMD, TAB_CMD, DEPTH_CMD, RAMP_CMD};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+  Pen(PenDirection),
+  Move(f32, f32),
+  Reset,
+  End,
+  // pen change, along with the color of the new pen when known
+  Tool(usize, Option<[u8; 3]>),
+  // wait in place (s)
+  Dwell(f32),
+  // move over a holding tab, with the tool raised above the material
+  Tab(f32, f32),
+  // Z of the tool tip while the pen is down (mm), negative below the surface of the material
+  Depth(f32),
+  // pen down move that reaches the given depth at its end, ramping into the material
+  Ramp(f32, f32, f32),
+}
+
+impl Command {
+  // returns None for empty lines and unknown commands
+  pub fn parse(line: &str) -> Option<Command> {
+    match line.trim() {
+      PEN_UP => Some(Command::Pen(PenDirection::UP)),
+      PEN_DOWN => Some(Command::Pen(PenDirection::DOWN)),
+      RESET_CMD => Some(Command::Reset),
+      END_CMD => Some(Command::End),
+      line => {
+        let mut iter = line.split_whitespace();
+        match iter.next()? {
+          MOVE_CMD => {
+            let x = iter.next()?.parse::<f32>().ok()?;
+            let y = iter.next()?.parse::<f32>().ok()?;
+            Some(Command::Move(x, y))
+          },
+          TOOL_CMD => {
+            let index = iter.next()?.parse::<usize>().ok()?;
+            Some(Command::Tool(index, iter.next().and_then(parse_hex_color)))
+          },
+          DWELL_CMD => Some(Command::Dwell(iter.next()?.parse::<f32>().ok()?)),
+          TAB_CMD => {
+            let x = iter.next()?.parse::<f32>().ok()?;
+            let y = iter.next()?.parse::<f32>().ok()?;
+            Some(Command::Tab(x, y))
+          },
+          DEPTH_CMD => Some(Command::Depth(iter.next()?.parse::<f32>().ok()?)),
+          RAMP_CMD => {
+            let x = iter.next()?.parse::<f32>().ok()?;
+            let y = iter.next()?.parse::<f32>().ok()?;
+            let z = iter.next()?.parse::<f32>().ok()?;
+            Some(Command::Ramp(x, y, z))
+          },
+          _ => None,
+        }
+      }
+    }
+  }
+}
+
+impl fmt::Display for Command {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Command::Pen(PenDirection::UP) => write!(f, "{}", PEN_UP),
+      Command::Pen(PenDirection::DOWN) => write!(f, "{}", PEN_DOWN),
+      Command::Move(x, y) => write!(f, "{} {} {}", MOVE_CMD, x, y),
+      Command::Reset => write!(f, "{}", RESET_CMD),
+      Command::End => write!(f, "{}", END_CMD),
+      Command::Tool(index, Some([r, g, b])) => write!(f, "{} {} #{:02x}{:02x}{:02x}", TOOL_CMD, index, r, g, b),
+      Command::Tool(index, None) => write!(f, "{} {}", TOOL_CMD, index),
+      Command::Dwell(seconds) => write!(f, "{} {}", DWELL_CMD, seconds),
+      Command::Tab(x, y) => write!(f, "{} {} {}", TAB_CMD, x, y),
+      Command::Depth(z) => write!(f, "{} {}", DEPTH_CMD, z),
+      Command::Ramp(x, y, z) => write!(f, "{} {} {} {}", RAMP_CMD, x, y, z),
+    }
+  }
+}
+
+// #rrggbb
+pub fn parse_hex_color(hex: &str) -> Option<[u8; 3]> {
+  let hex = hex.strip_prefix('#').unwrap_or(hex);
+  if hex.len() != 6 {
+    return None;
+  }
+  let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+  Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+// parses a whole command file, unknown lines are skipped
+pub fn parse_commands(text: &str) -> Vec<Command> {
+  text
+    .lines()
+    .filter_map(Command::parse)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_every_command() {
+    let text = "PEN UP\nPEN DOWN\nRESET\nEND\nMOVE 1.5 -2\nTOOL 3 #FF8000\nTOOL 4\nDWELL 0.25\nTAB 3 4\nDEPTH -1.5\nRAMP 5 6 -0.5";
+    assert_eq!(
+      parse_commands(text),
+      vec![
+        Command::Pen(PenDirection::UP),
+        Command::Pen(PenDirection::DOWN),
+        Command::Reset,
+        Command::End,
+        Command::Move(1.5, -2.0),
+        Command::Tool(3, Some([255, 128, 0])),
+        Command::Tool(4, None),
+        Command::Dwell(0.25),
+        Command::Tab(3.0, 4.0),
+        Command::Depth(-1.5),
+        Command::Ramp(5.0, 6.0, -0.5),
+      ]
+    );
+  }
+
+  #[test]
+  fn unknown_lines_are_skipped() {
+    for line in ["", "   ", "JUMP 1 2", "MOVE 1", "MOVE a b", "TOOL -1", "RAMP 1 2", "DWELL"] {
+      assert_eq!(Command::parse(line), None, "{:?}", line);
+    }
+    // surrounding whitespace is allowed
+    assert_eq!(Command::parse("  MOVE 1 2  "), Some(Command::Move(1.0, 2.0)));
+    // an invalid color leaves the pen without one
+    assert_eq!(Command::parse("TOOL 2 #12345"), Some(Command::Tool(2, None)));
+  }
+
+  #[test]
+  fn display_roundtrips() {
+    let commands = vec![
+      Command::Pen(PenDirection::DOWN),
+      Command::Move(0.1, 1234.5678),
+      Command::Tool(1, Some([0, 10, 255])),
+      Command::Tool(2, None),
+      Command::Dwell(1.5),
+      Command::Tab(-3.25, 7.0),
+      Command::Depth(-0.3),
+      Command::Ramp(1.0, 2.0, -0.75),
+      Command::Reset,
+      Command::End,
+    ];
+    let text = commands.iter().map(|c| c.to_string()).collect::<Vec<_>>().join("\n");
+    assert_eq!(parse_commands(&text), commands);
+    assert_eq!(Command::Tool(1, Some([0, 10, 255])).to_string(), "TOOL 1 #000aff");
+  }
+
+  #[test]
+  fn hex_colors() {
+    assert_eq!(parse_hex_color("#1a2B3c"), Some([0x1a, 0x2b, 0x3c]));
+    assert_eq!(parse_hex_color("ffffff"), Some([255, 255, 255]));
+    assert_eq!(parse_hex_color("#fff"), None);
+    assert_eq!(parse_hex_color("#gggggg"), None);
+    assert_eq!(parse_hex_color("#ééé"), None);
+  }
+}
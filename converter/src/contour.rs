@@ -0,0 +1,269 @@
+// Closed contours around the dark regions of an image, for cutting parts out of sheet
+use crate::fill::{Point, Polygon};
+use std::collections::HashMap;
+
+// contours enclosing less than this area (px^2) are noise
+const MIN_AREA: f32 = 4.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contour {
+  // closed polygon, the last point connects back to the first one
+  // outer boundaries have a positive signed area and holes a negative one, so that
+  // (dy, -dx) always points away from the material for an edge going (dx, dy)
+  pub points: Polygon,
+  // the contour bounds a hole in the material instead of a part
+  pub hole: bool,
+}
+
+impl Contour {
+  pub fn new(points: Polygon) -> Contour {
+    let hole = signed_area(&points) < 0.0;
+    Contour { points, hole }
+  }
+
+  // the polygon closed by repeating its first point, as drawn by the writers
+  pub fn closed(&self) -> Polygon {
+    let mut points = self.points.clone();
+    if let Some(first) = self.points.first() {
+      points.push(*first);
+    }
+    points
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourOptions {
+  // pixels darker than this are material
+  pub threshold: u8,
+  // maximum distance between the traced pixel outline and the simplified contour (px)
+  pub tolerance: f32,
+  // width of the cut, contours are moved away from the material by half of it (mm)
+  pub kerf: f32,
+}
+
+impl Default for ContourOptions {
+  fn default() -> Self {
+    ContourOptions {
+      threshold: 128,
+      tolerance: 1.0,
+      kerf: 0.0,
+    }
+  }
+}
+
+// shoelace formula
+pub fn signed_area(points: &[Point]) -> f32 {
+  let mut area = 0.0;
+  for (index, a) in points.iter().enumerate() {
+    let b = points[(index + 1) % points.len()];
+    area += a.0 * b.1 - b.0 * a.1;
+  }
+  area / 2.0
+}
+
+fn point_segment_distance(p: Point, a: Point, b: Point) -> f32 {
+  let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+  let length = dx * dx + dy * dy;
+  let t = if length > 0.0 { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+  ((p.0 - a.0 - t * dx).powi(2) + (p.1 - a.1 - t * dy).powi(2)).sqrt()
+}
+
+// distance from a point to the outline of a closed polygon
+pub fn distance_to(p: Point, points: &[Point]) -> f32 {
+  let mut distance = f32::MAX;
+  for (index, a) in points.iter().enumerate() {
+    distance = distance.min(point_segment_distance(p, *a, points[(index + 1) % points.len()]));
+  }
+  distance
+}
+
+// even-odd test
+pub fn contains(points: &[Point], p: Point) -> bool {
+  let mut inside = false;
+  for (index, a) in points.iter().enumerate() {
+    let b = points[(index + 1) % points.len()];
+    if (a.1 > p.1) != (b.1 > p.1) && p.0 < a.0 + (p.1 - a.1) / (b.1 - a.1) * (b.0 - a.0) {
+      inside = !inside;
+    }
+  }
+  inside
+}
+
+// Douglas-Peucker on an open polyline, keeps both ends
+fn simplify_open(points: &[Point], tolerance: f32, simplified: &mut Vec<Point>) {
+  let (first, last) = (points[0], points[points.len() - 1]);
+  let mut farthest = (0, 0.0);
+  for (index, p) in points.iter().enumerate().take(points.len() - 1).skip(1) {
+    let distance = point_segment_distance(*p, first, last);
+    if distance > farthest.1 {
+      farthest = (index, distance);
+    }
+  }
+  if farthest.1 > tolerance {
+    simplify_open(&points[..=farthest.0], tolerance, simplified);
+    simplified.pop(); // shared by both halves
+    simplify_open(&points[farthest.0..], tolerance, simplified);
+  }
+  else {
+    simplified.push(first);
+    simplified.push(last);
+  }
+}
+
+// Douglas-Peucker on a closed polygon, split at the point farthest from the first one
+pub fn simplify(points: &[Point], tolerance: f32) -> Polygon {
+  if points.len() < 4 {
+    return points.to_vec();
+  }
+  let first = points[0];
+  let (split, _) = points
+    .iter()
+    .enumerate()
+    .map(|(index, p)| (index, (p.0 - first.0).powi(2) + (p.1 - first.1).powi(2)))
+    .fold((0, 0.0), |best, current| if current.1 > best.1 { current } else { best });
+
+  let mut closed = points.to_vec();
+  closed.push(first);
+  let mut simplified = vec![];
+  simplify_open(&closed[..=split], tolerance, &mut simplified);
+  simplified.pop();
+  simplify_open(&closed[split..], tolerance, &mut simplified);
+  simplified.pop(); // the first point again
+  simplified
+}
+
+// outlines of the pixels for which `inside` holds, oriented as described in Contour
+// pixel (x, y) covers [x - 0.5, x + 0.5] x [y - 0.5, y + 0.5], so the contours match the edge coordinates
+pub fn trace<F: Fn(u32, u32) -> bool>(width: u32, height: u32, inside: F, tolerance: f32) -> Vec<Contour> {
+  let (w, h) = (width as i64, height as i64);
+  let at = |x: i64, y: i64| x >= 0 && y >= 0 && x < w && y < h && inside(x as u32, y as u32);
+
+  // unit pixel borders between material and background, as (from corner, direction)
+  // corner (i, j) is the top left corner of pixel (i, j)
+  let mut outgoing: HashMap<(i64, i64), Vec<(i64, i64)>> = HashMap::new();
+  for y in 0..h {
+    for x in 0..w {
+      if !at(x, y) {
+        continue;
+      }
+      let borders = [
+        (!at(x, y - 1), (x, y), (1, 0)),
+        (!at(x + 1, y), (x + 1, y), (0, 1)),
+        (!at(x, y + 1), (x + 1, y + 1), (-1, 0)),
+        (!at(x - 1, y), (x, y + 1), (0, -1)),
+      ];
+      for (border, from, direction) in borders {
+        if border {
+          outgoing.entry(from).or_default().push(direction);
+        }
+      }
+    }
+  }
+
+  let mut starts = outgoing.keys().copied().collect::<Vec<(i64, i64)>>();
+  starts.sort_unstable_by_key(|&(x, y)| (y, x));
+
+  let mut contours = vec![];
+  for start in starts {
+    while matches!(outgoing.get(&start), Some(directions) if !directions.is_empty()) {
+      let mut corners = vec![];
+      let mut corner = start;
+      let mut heading = (0, 0);
+      loop {
+        let directions = match outgoing.get_mut(&corner) {
+          Some(directions) if !directions.is_empty() => directions,
+          _ => break,
+        };
+        // where two regions touch diagonally, turning towards the material keeps them apart
+        let toward_material = (-heading.1, heading.0);
+        let index = directions.iter().position(|d| *d == toward_material).unwrap_or(0);
+        let direction = directions.swap_remove(index);
+
+        // only corners where the outline turns are kept
+        if direction != heading {
+          corners.push((corner.0 as f32 - 0.5, corner.1 as f32 - 0.5));
+        }
+        heading = direction;
+        corner = (corner.0 + direction.0, corner.1 + direction.1);
+        if corner == start {
+          break;
+        }
+      }
+
+      if corners.len() >= 3 {
+        let points = simplify(&corners, tolerance);
+        if points.len() >= 3 && signed_area(&points).abs() >= MIN_AREA {
+          contours.push(Contour::new(points));
+        }
+      }
+    }
+  }
+  contours
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn square(from: f32, to: f32) -> Polygon {
+    vec![(from, from), (to, from), (to, to), (from, to)]
+  }
+
+  #[test]
+  fn areas_and_distances() {
+    assert_eq!(signed_area(&square(0.0, 10.0)), 100.0);
+    let reversed = square(0.0, 10.0).into_iter().rev().collect::<Polygon>();
+    assert_eq!(signed_area(&reversed), -100.0);
+    assert!(Contour::new(reversed).hole);
+    assert_eq!(Contour::new(square(0.0, 1.0)).closed(), vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)]);
+
+    assert!(contains(&square(0.0, 10.0), (5.0, 5.0)));
+    assert!(!contains(&square(0.0, 10.0), (15.0, 5.0)));
+    assert_eq!(distance_to((5.0, 5.0), &square(0.0, 10.0)), 5.0);
+    assert_eq!(distance_to((13.0, 14.0), &square(0.0, 10.0)), 5.0);
+  }
+
+  #[test]
+  fn simplified_polygons() {
+    // points along the sides of a square are dropped, the corners are kept
+    let outline = (0..10)
+      .map(|i| (i as f32, 0.0))
+      .chain((0..10).map(|i| (10.0, i as f32)))
+      .chain((0..10).map(|i| (10.0 - i as f32, 10.0)))
+      .chain((0..10).map(|i| (0.0, 10.0 - i as f32)))
+      .collect::<Polygon>();
+    let mut simplified = simplify(&outline, 0.1);
+    simplified.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(simplified, vec![(0.0, 0.0), (0.0, 10.0), (10.0, 0.0), (10.0, 10.0)]);
+    // a bump larger than the tolerance stays
+    let mut bumped = outline.clone();
+    bumped[5] = (5.0, -2.0);
+    assert_eq!(simplify(&bumped, 1.0).len(), 7);
+    assert_eq!(simplify(&bumped, 3.0).len(), 4);
+  }
+
+  #[test]
+  fn traced_regions() {
+    // a 4x4 block
+    let contours = trace(10, 10, |x, y| (3..7).contains(&x) && (3..7).contains(&y), 0.0);
+    assert_eq!(contours.len(), 1);
+    assert!(!contours[0].hole);
+    assert_eq!(signed_area(&contours[0].points), 16.0);
+    let mut corners = contours[0].points.clone();
+    corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(corners, vec![(2.5, 2.5), (2.5, 6.5), (6.5, 2.5), (6.5, 6.5)]);
+
+    // with a hole
+    let contours = trace(10, 10, |x, y| (1..9).contains(&x) && (1..9).contains(&y) && !((4..6).contains(&x) && (4..6).contains(&y)), 0.0);
+    assert_eq!(contours.len(), 2);
+    assert_eq!(contours.iter().map(|c| (c.hole, signed_area(&c.points))).collect::<Vec<_>>(), vec![(false, 64.0), (true, -4.0)]);
+  }
+
+  #[test]
+  fn diagonal_regions_stay_apart() {
+    // two 3x3 blocks touching at a corner, and a single pixel dropped as noise
+    let contours = trace(10, 10, |x, y| (x < 3 && y < 3) || ((3..6).contains(&x) && (3..6).contains(&y)) || (x == 8 && y == 8), 0.0);
+    assert_eq!(contours.len(), 2);
+    assert!(contours.iter().all(|c| signed_area(&c.points) == 9.0 && c.points.len() == 4));
+  }
+}
@@ -0,0 +1,283 @@
+// Multi pass cutting: every pen down run is repeated at increasing depths, for routers and engravers with a Z axis
+use crate::canny::PenDirection;
+use crate::command::Command;
+use crate::path::distance;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthOptions {
+  // Z of the travel moves (mm)
+  pub safe_z: f32,
+  // depth of the first pass below the surface of the material (mm)
+  pub start_depth: f32,
+  // depth removed by each following pass (mm)
+  pub step_down: f32,
+  // depth of the last pass (mm)
+  pub final_depth: f32,
+  // length over which closed runs ramp down into the material (mm), they plunge when missing
+  pub ramp: Option<f32>,
+}
+
+impl Default for DepthOptions {
+  fn default() -> Self {
+    DepthOptions {
+      safe_z: 5.0,
+      start_depth: 1.0,
+      step_down: 1.0,
+      final_depth: 3.0,
+      ramp: None,
+    }
+  }
+}
+
+impl DepthOptions {
+  // Z of each pass, from the surface down, the last one is always at the final depth
+  pub fn depths(&self) -> Vec<f32> {
+    let mut depths = vec![];
+    let mut depth = self.start_depth;
+    if self.step_down > 0.0 {
+      while depth < self.final_depth {
+        depths.push(-depth);
+        depth += self.step_down;
+      }
+    }
+    depths.push(-self.final_depth);
+    depths
+  }
+}
+
+// commands performed with the pen down, from the point where it was lowered
+struct Run {
+  start: (f32, f32),
+  end: (f32, f32),
+  commands: Vec<Command>,
+}
+
+// moves ramping from `from` down to `to` along the first `length` units of the run, along with the index of the
+// command the run goes on with, and the moves cutting the ramped stretch again at full depth
+// a ramp is cut short by tabs, or by the end of the run, and plunges the rest of the way there
+fn ramp(run: &Run, length: f32, from: f32, to: f32) -> (Vec<Command>, usize, Vec<Command>) {
+  let (mut ramp, mut recut) = (vec![], vec![]);
+  let mut position = run.start;
+  let mut covered = 0.0;
+  for (index, command) in run.commands.iter().enumerate() {
+    match *command {
+      Command::Move(x, y) => {
+        let segment = distance(position, (x, y));
+        if segment == 0.0 {
+          continue;
+        }
+        if covered + segment >= length {
+          // the ramp ends within this segment, which is then cut to its end
+          let t = (length - covered) / segment;
+          let (x, y) = (position.0 + (x - position.0) * t, position.1 + (y - position.1) * t);
+          ramp.push(Command::Ramp(x, y, to));
+          recut.push(Command::Move(x, y));
+          return (ramp, index, recut);
+        }
+        covered += segment;
+        ramp.push(Command::Ramp(x, y, from + (to - from) * covered / length));
+        recut.push(Command::Move(x, y));
+        position = (x, y);
+      },
+      Command::Dwell(_) => {},
+      _ => {
+        ramp.push(Command::Depth(to));
+        return (ramp, index, recut);
+      },
+    }
+  }
+  ramp.push(Command::Depth(to));
+  (ramp, run.commands.len(), recut)
+}
+
+fn run_passes(run: &Run, options: &DepthOptions, unit_size: f32, passes: &mut Vec<Command>) {
+  // closed runs go down to the next pass where they end, open ones start over from their first point
+  let closed = run.start == run.end;
+  let depths = options.depths();
+  // the material is pierced once, the following passes go down the same hole
+  let repeated = run.commands.iter().filter(|command| !matches!(command, Command::Dwell(_))).copied().collect::<Vec<Command>>();
+  for (index, depth) in depths.iter().enumerate() {
+    if index > 0 && !closed {
+      passes.push(Command::Pen(PenDirection::UP));
+      passes.push(Command::Move(run.start.0, run.start.1));
+    }
+    let from = if index == 0 { 0.0 } else { depths[index - 1] };
+    let lowered = index == 0 || !closed;
+
+    match options.ramp {
+      Some(length) if closed && length > 0.0 => {
+        if lowered {
+          passes.push(Command::Depth(from));
+          passes.push(Command::Pen(PenDirection::DOWN));
+        }
+        let (ramp, rest, recut) = ramp(run, length / unit_size, from, *depth);
+        passes.extend(ramp);
+        passes.extend_from_slice(&run.commands[rest..]);
+        // each ramp goes below the previous one, only the last one is left behind
+        if index + 1 == depths.len() {
+          passes.extend(recut);
+        }
+      },
+      _ => {
+        passes.push(Command::Depth(*depth));
+        if lowered {
+          passes.push(Command::Pen(PenDirection::DOWN));
+        }
+        passes.extend_from_slice(if index == 0 { &run.commands } else { &repeated });
+      },
+    }
+  }
+}
+
+// repeats every run of pen down commands at each depth, the commands outside of the runs are kept as they are
+pub fn passes(commands: &[Command], options: &DepthOptions, unit_size: f32) -> Vec<Command> {
+  let mut passes = Vec::with_capacity(commands.len() * options.depths().len());
+  let mut position = (0.0, 0.0);
+  let mut run: Option<Run> = None;
+
+  for command in commands {
+    if let Some(current) = run.as_mut() {
+      match *command {
+        Command::Move(x, y) | Command::Tab(x, y) | Command::Ramp(x, y, _) => {
+          current.end = (x, y);
+          current.commands.push(*command);
+          continue;
+        },
+        Command::Dwell(_) => {
+          current.commands.push(*command);
+          continue;
+        },
+        // the depth is given by the passes
+        Command::Depth(_) | Command::Pen(PenDirection::DOWN) => continue,
+        _ => {
+          if let Some(finished) = run.take() {
+            run_passes(&finished, options, unit_size, &mut passes);
+            position = finished.end;
+          }
+        },
+      }
+    }
+
+    match *command {
+      Command::Pen(PenDirection::DOWN) => {
+        run = Some(Run { start: position, end: position, commands: vec![] });
+        continue;
+      },
+      Command::Move(x, y) | Command::Tab(x, y) | Command::Ramp(x, y, _) => position = (x, y),
+      Command::Reset => position = (0.0, 0.0),
+      Command::Depth(_) => continue,
+      _ => {},
+    }
+    passes.push(*command);
+  }
+  if let Some(finished) = run.take() {
+    run_passes(&finished, options, unit_size, &mut passes);
+  }
+  passes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn square() -> Vec<Command> {
+    vec![
+      Command::Pen(PenDirection::DOWN),
+      Command::Move(10.0, 0.0),
+      Command::Move(10.0, 10.0),
+      Command::Move(0.0, 0.0),
+      Command::Pen(PenDirection::UP),
+    ]
+  }
+
+  fn two_passes() -> DepthOptions {
+    DepthOptions { final_depth: 2.0, ..DepthOptions::default() }
+  }
+
+  #[test]
+  fn depths() {
+    assert_eq!(DepthOptions::default().depths(), vec![-1.0, -2.0, -3.0]);
+    assert_eq!(DepthOptions { final_depth: 2.5, ..DepthOptions::default() }.depths(), vec![-1.0, -2.0, -2.5]);
+    assert_eq!(DepthOptions { step_down: 0.0, ..DepthOptions::default() }.depths(), vec![-3.0]);
+    assert_eq!(DepthOptions { start_depth: 4.0, ..DepthOptions::default() }.depths(), vec![-3.0]);
+  }
+
+  #[test]
+  fn closed_runs_go_down_where_they_end() {
+    let mut commands = square();
+    commands.push(Command::End);
+    let cut = &square()[1..4];
+    let mut expected = vec![Command::Depth(-1.0), Command::Pen(PenDirection::DOWN)];
+    expected.extend_from_slice(cut);
+    expected.push(Command::Depth(-2.0));
+    expected.extend_from_slice(cut);
+    expected.extend([Command::Pen(PenDirection::UP), Command::End]);
+    assert_eq!(passes(&commands, &two_passes(), 1.0), expected);
+  }
+
+  #[test]
+  fn open_runs_start_over() {
+    let commands = vec![Command::Move(5.0, 5.0), Command::Depth(-7.0), Command::Pen(PenDirection::DOWN), Command::Dwell(0.5), Command::Move(10.0, 5.0), Command::Pen(PenDirection::UP)];
+    assert_eq!(
+      passes(&commands, &two_passes(), 1.0),
+      vec![
+        Command::Move(5.0, 5.0),
+        Command::Depth(-1.0),
+        Command::Pen(PenDirection::DOWN),
+        // the pierce dwell happens once
+        Command::Dwell(0.5),
+        Command::Move(10.0, 5.0),
+        Command::Pen(PenDirection::UP),
+        Command::Move(5.0, 5.0),
+        Command::Depth(-2.0),
+        Command::Pen(PenDirection::DOWN),
+        Command::Move(10.0, 5.0),
+        Command::Pen(PenDirection::UP),
+      ]
+    );
+  }
+
+  #[test]
+  fn ramps() {
+    let options = DepthOptions { ramp: Some(2.0), ..two_passes() };
+    assert_eq!(
+      passes(&square(), &options, 0.5),
+      vec![
+        Command::Depth(0.0),
+        Command::Pen(PenDirection::DOWN),
+        Command::Ramp(4.0, 0.0, -1.0),
+        Command::Move(10.0, 0.0),
+        Command::Move(10.0, 10.0),
+        Command::Move(0.0, 0.0),
+        Command::Ramp(4.0, 0.0, -2.0),
+        Command::Move(10.0, 0.0),
+        Command::Move(10.0, 10.0),
+        Command::Move(0.0, 0.0),
+        // what the last ramp left behind
+        Command::Move(4.0, 0.0),
+        Command::Pen(PenDirection::UP),
+      ]
+    );
+  }
+
+  #[test]
+  fn ramps_over_several_moves() {
+    let commands = vec![
+      Command::Pen(PenDirection::DOWN),
+      Command::Move(1.0, 0.0),
+      Command::Move(3.0, 0.0),
+      Command::Tab(4.0, 0.0),
+      Command::Move(10.0, 0.0),
+      Command::Move(0.0, 0.0),
+      Command::Pen(PenDirection::UP),
+    ];
+    let options = DepthOptions { ramp: Some(2.0), final_depth: 1.0, ..DepthOptions::default() };
+    assert_eq!(passes(&commands, &options, 1.0)[2..5], [Command::Ramp(1.0, 0.0, -0.5), Command::Ramp(2.0, 0.0, -1.0), Command::Move(3.0, 0.0)]);
+    // cut short by the tab, it plunges the rest of the way there
+    let options = DepthOptions { ramp: Some(8.0), ..options };
+    assert_eq!(
+      passes(&commands, &options, 1.0)[2..6],
+      [Command::Ramp(1.0, 0.0, -0.125), Command::Ramp(3.0, 0.0, -0.375), Command::Depth(-1.0), Command::Tab(4.0, 0.0)]
+    );
+  }
+}
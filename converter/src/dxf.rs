@@ -0,0 +1,338 @@
+// DXF input: the entities of a CAD drawing flattened into polylines, then chained into paths
+use crate::fill::{Point, Polyline};
+use crate::path;
+use crate::vector::{self, Drawing};
+
+// an entity of the ENTITIES section, as its group code / value pairs
+struct Entity<'a> {
+  kind: &'a str,
+  groups: Vec<(i32, &'a str)>,
+}
+
+impl<'a> Entity<'a> {
+  fn number(&self, code: i32) -> Option<f32> {
+    self.groups.iter().find(|(c, _)| *c == code).and_then(|(_, value)| value.parse().ok())
+  }
+
+  // every value of a repeated group code, in order
+  fn numbers(&self, code: i32) -> Vec<f32> {
+    self.groups.iter().filter(|(c, _)| *c == code).filter_map(|(_, value)| value.parse().ok()).collect()
+  }
+
+  fn point(&self, x: i32, y: i32) -> Point {
+    (self.number(x).unwrap_or(0.0), self.number(y).unwrap_or(0.0))
+  }
+
+  fn layer(&self) -> &'a str {
+    self.groups.iter().find(|(c, _)| *c == 8).map(|(_, value)| *value).unwrap_or("0")
+  }
+
+  // arcs, circles and polylines lie in their own coordinate system, which is mirrored along x
+  // when the extrusion direction points down (as CAD programs write mirrored arcs)
+  fn mirrored(&self) -> bool {
+    self.number(230).map(|z| z < 0.0).unwrap_or(false)
+  }
+}
+
+// group code / value pairs of the whole file
+fn groups(text: &str) -> Result<Vec<(i32, &str)>, String> {
+  if text.starts_with("AutoCAD Binary DXF") {
+    return Err("Binary DXF files are not supported".to_string());
+  }
+  let lines = text.lines().map(|line| line.trim()).collect::<Vec<_>>();
+  lines
+    .chunks(2)
+    .enumerate()
+    .filter(|(_, pair)| pair.len() == 2)
+    .map(|(index, pair)| match pair[0].parse::<i32>() {
+      Ok(code) => Ok((code, pair[1])),
+      Err(_) => Err(format!("Invalid group code on line {}", index * 2 + 1)),
+    })
+    .collect()
+}
+
+// entities of the ENTITIES section
+fn entities<'a>(groups: &[(i32, &'a str)]) -> Result<Vec<Entity<'a>>, String> {
+  let start = groups
+    .windows(2)
+    .position(|w| w[0] == (0, "SECTION") && w[1] == (2, "ENTITIES"))
+    .ok_or("No ENTITIES section found")?;
+
+  let mut entities: Vec<Entity> = vec![];
+  for &(code, value) in &groups[start + 2..] {
+    if code == 0 {
+      if value == "ENDSEC" {
+        break;
+      }
+      entities.push(Entity { kind: value, groups: vec![] });
+    }
+    else if let Some(entity) = entities.last_mut() {
+      entity.groups.push((code, value));
+    }
+  }
+  Ok(entities)
+}
+
+// straight segment, or arc when the bulge is set (tangent of a quarter of its angle, counterclockwise when positive)
+// the circle of the arc is added to `circles`
+fn bulge_to(from: Point, to: Point, bulge: f32, tolerance: f32, points: &mut Polyline, circles: &mut Vec<(Point, f32)>) {
+  let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+  let chord = (dx * dx + dy * dy).sqrt();
+  if bulge.abs() < 1e-6 || chord == 0.0 {
+    points.push(to);
+    return;
+  }
+  let sweep = 4.0 * bulge.atan();
+  let radius = chord / (2.0 * (sweep / 2.0).sin());
+  // the center lies on the bisector of the chord, on its left for counterclockwise arcs under half a turn
+  let offset = chord / 2.0 / (sweep / 2.0).tan();
+  let center = ((from.0 + to.0) / 2.0 - dy / chord * offset, (from.1 + to.1) / 2.0 + dx / chord * offset);
+  let start = (from.1 - center.1).atan2(from.0 - center.0);
+  vector::arc(center, (radius.abs(), radius.abs()), 0.0, start, sweep, tolerance, points);
+  let last = points.len() - 1;
+  points[last] = to;
+  circles.push((center, radius.abs()));
+}
+
+fn lwpolyline(entity: &Entity, tolerance: f32, circles: &mut Vec<(Point, f32)>) -> Polyline {
+  // (x, y, bulge) of each vertex, a vertex starts at its x
+  let mut vertices: Vec<(f32, f32, f32)> = vec![];
+  for &(code, value) in &entity.groups {
+    let value = value.parse::<f32>().unwrap_or(0.0);
+    match (code, vertices.last_mut()) {
+      (10, _) => vertices.push((value, 0.0, 0.0)),
+      (20, Some(vertex)) => vertex.1 = value,
+      (42, Some(vertex)) => vertex.2 = value,
+      _ => {},
+    }
+  }
+  let closed = entity.number(70).map(|flags| flags as i32 & 1 == 1).unwrap_or(false);
+
+  let mut points = vec![];
+  if let Some(first) = vertices.first() {
+    points.push((first.0, first.1));
+  }
+  let segments = if closed { vertices.len() } else { vertices.len().saturating_sub(1) };
+  for index in 0..segments {
+    let (from, to) = (vertices[index], vertices[(index + 1) % vertices.len()]);
+    bulge_to((from.0, from.1), (to.0, to.1), from.2, tolerance, &mut points, circles);
+  }
+  points
+}
+
+fn spline(entity: &Entity, tolerance: f32) -> Polyline {
+  let degree = entity.number(71).unwrap_or(3.0) as usize;
+  let knots = entity.numbers(40);
+  let controls = entity.numbers(10).into_iter().zip(entity.numbers(20)).collect::<Vec<Point>>();
+  let fit = entity.numbers(11).into_iter().zip(entity.numbers(21)).collect::<Vec<Point>>();
+  // splines given only by fit points are followed through them
+  if controls.is_empty() {
+    return fit;
+  }
+  vector::nurbs(degree, &knots, &controls, &entity.numbers(41), tolerance)
+}
+
+// polyline of a single entity, None for the entities that are not read
+// the circles of its arcs are added to `circles`
+fn flatten(entity: &Entity, tolerance: f32, circles: &mut Vec<(Point, f32)>) -> Option<Polyline> {
+  let known = circles.len();
+  let mut points = match entity.kind {
+    "LINE" => vec![entity.point(10, 20), entity.point(11, 21)],
+    "CIRCLE" => {
+      let (center, radius) = (entity.point(10, 20), entity.number(40)?);
+      circles.push((center, radius));
+      vector::ellipse(center, (radius, radius), tolerance)
+    },
+    "ARC" => {
+      let (center, radius) = (entity.point(10, 20), entity.number(40)?);
+      let start = entity.number(50).unwrap_or(0.0);
+      let mut sweep = entity.number(51).unwrap_or(360.0) - start;
+      // arcs always go counterclockwise from their start angle
+      while sweep <= 0.0 {
+        sweep += 360.0;
+      }
+      let (sin, cos) = start.to_radians().sin_cos();
+      let mut points = vec![(center.0 + radius * cos, center.1 + radius * sin)];
+      vector::arc(center, (radius, radius), 0.0, start.to_radians(), sweep.to_radians(), tolerance, &mut points);
+      circles.push((center, radius));
+      points
+    },
+    "LWPOLYLINE" => lwpolyline(entity, tolerance, circles),
+    "SPLINE" => spline(entity, tolerance),
+    _ => return None,
+  };
+  if entity.mirrored() {
+    points.iter_mut().for_each(|p| p.0 = -p.0);
+    circles[known..].iter_mut().for_each(|(center, _)| center.0 = -center.0);
+  }
+  Some(points)
+}
+
+// the LINE, ARC, CIRCLE, LWPOLYLINE and SPLINE entities of the drawing, on the given layers (every layer when empty)
+// entities sharing an end within `tolerance` are chained into a single path
+// the y axis of the drawing points up, it is flipped so the drawing lands on the image coordinates of the pipeline
+// the circles of the arcs are kept along the paths, so that G-code follows them exactly
+pub fn parse(text: &str, tolerance: f32, layers: &[String]) -> Result<Drawing, String> {
+  let groups = groups(text)?;
+  let entities = entities(&groups)?;
+
+  let wanted = |layer: &str| layers.is_empty() || layers.iter().any(|l| l.eq_ignore_ascii_case(layer));
+  let mut circles = vec![];
+  let pieces = entities
+    .iter()
+    .filter(|entity| wanted(entity.layer()))
+    .filter_map(|entity| flatten(entity, tolerance, &mut circles))
+    .collect::<Vec<_>>();
+  if pieces.is_empty() && !layers.is_empty() {
+    let mut found = entities.iter().map(|entity| entity.layer()).collect::<Vec<_>>();
+    found.sort_unstable();
+    found.dedup();
+    return Err(format!("No entities found on the layers {}, the drawing has {}", layers.join(", "), found.join(", ")));
+  }
+
+  let paths = vector::chain(pieces, tolerance);
+  let (min, max) = path::bounds(&paths.concat());
+  if paths.is_empty() {
+    return Ok(Drawing { width: 0.0, height: 0.0, paths, circles });
+  }
+  let paths = paths
+    .into_iter()
+    .map(|path| path.into_iter().map(|(x, y)| (x - min.0, max.1 - y)).collect())
+    .collect();
+  let circles = circles.into_iter().map(|((x, y), radius)| ((x - min.0, max.1 - y), radius)).collect();
+  Ok(Drawing { width: max.0 - min.0, height: max.1 - min.1, paths, circles })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entity(kind: &str, layer: &str, groups: &[(i32, f32)]) -> String {
+    let mut text = format!("0\n{}\n8\n{}\n", kind, layer);
+    for (code, value) in groups {
+      text += &format!("{}\n{}\n", code, value);
+    }
+    text
+  }
+
+  fn dxf(entities: &[String]) -> String {
+    format!("0\nSECTION\n2\nENTITIES\n{}0\nENDSEC\n0\nEOF\n", entities.concat())
+  }
+
+  fn on_circle(points: &[Point], center: Point, radius: f32) -> bool {
+    points.iter().all(|p| (((p.0 - center.0).powi(2) + (p.1 - center.1).powi(2)).sqrt() - radius).abs() < 1e-3)
+  }
+
+  #[test]
+  fn invalid_files() {
+    assert!(parse("AutoCAD Binary DXF\r\n", 0.1, &[]).is_err());
+    assert_eq!(parse("0\nSECTION\n2\nHEADER\n0\nENDSEC\n", 0.1, &[]).err().unwrap(), "No ENTITIES section found");
+    assert_eq!(parse("0\nSECTION\nname\nENTITIES\n", 0.1, &[]).err().unwrap(), "Invalid group code on line 3");
+  }
+
+  #[test]
+  fn lines_are_chained_and_flipped() {
+    let text = dxf(&[
+      entity("LINE", "0", &[(10, 10.0), (20, 0.0), (11, 10.0), (21, 10.0)]),
+      entity("LINE", "0", &[(10, 20.0), (20, 10.0), (11, 20.0), (21, 0.0)]),
+      // drawn backwards, it is reversed to join the others
+      entity("LINE", "0", &[(10, 20.0), (20, 10.0), (11, 10.0), (21, 10.0)]),
+    ]);
+    let drawing = parse(&text, 0.1, &[]).unwrap();
+    assert_eq!((drawing.width, drawing.height), (10.0, 10.0));
+    assert_eq!(drawing.paths, vec![vec![(0.0, 10.0), (0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]]);
+    assert!(drawing.circles.is_empty());
+  }
+
+  #[test]
+  fn pieces_meeting_their_start_are_closed() {
+    let text = dxf(&[
+      entity("LINE", "0", &[(10, 0.0), (20, 0.0), (11, 10.0), (21, 0.0)]),
+      entity("LINE", "0", &[(10, 0.0), (20, 10.0), (11, 0.05), (21, 0.0)]),
+      entity("LINE", "0", &[(10, 10.0), (20, 0.0), (11, 0.0), (21, 10.0)]),
+    ]);
+    let paths = parse(&text, 0.1, &[]).unwrap().paths;
+    assert_eq!(paths.len(), 1);
+    assert_eq!(paths[0].len(), 4);
+    assert_eq!(paths[0][0], paths[0][3]);
+  }
+
+  #[test]
+  fn circles_and_arcs() {
+    let text = dxf(&[
+      entity("CIRCLE", "0", &[(10, 10.0), (20, 10.0), (40, 5.0)]),
+      // a quarter turn from the top of the circle to its left
+      entity("ARC", "0", &[(10, 10.0), (20, 10.0), (40, 10.0), (50, 90.0), (51, 180.0)]),
+    ]);
+    let drawing = parse(&text, 0.01, &[]).unwrap();
+    // the arc spans x from 0 to 10 and y from 10 to 20, the circle reaches down to y 5 give or take the flattening
+    assert_eq!(drawing.width, 15.0);
+    assert!(drawing.height <= 15.0 && drawing.height > 14.95);
+    assert_eq!(drawing.circles, vec![((10.0, 10.0), 5.0), ((10.0, 10.0), 10.0)]);
+
+    let circle = &drawing.paths[0];
+    assert_eq!(circle[0], circle[circle.len() - 1]);
+    assert!(on_circle(circle, (10.0, 10.0), 5.0));
+    let arc = &drawing.paths[1];
+    assert!(on_circle(arc, (10.0, 10.0), 10.0));
+    assert!((arc[0].0 - 10.0).abs() < 1e-4 && arc[0].1.abs() < 1e-4);
+    assert!(arc[arc.len() - 1].0.abs() < 1e-4 && (arc[arc.len() - 1].1 - 10.0).abs() < 1e-4);
+  }
+
+  #[test]
+  fn bulges() {
+    // two half turns make a closed circle of radius 5 around (5, 0)
+    let text = dxf(&[entity(
+      "LWPOLYLINE",
+      "0",
+      &[(90, 2.0), (70, 1.0), (10, 0.0), (20, 0.0), (42, 1.0), (10, 10.0), (20, 0.0), (42, 1.0)],
+    )]);
+    let drawing = parse(&text, 0.01, &[]).unwrap();
+    let path = &drawing.paths[0];
+    assert_eq!(path[0], path[path.len() - 1]);
+    assert_eq!(drawing.circles.len(), 2);
+    assert_eq!(drawing.circles[0], drawing.circles[1]);
+    let (center, radius) = drawing.circles[0];
+    assert!((center.0 - 5.0).abs() < 1e-4 && radius == 5.0);
+    assert!(on_circle(path, center, radius));
+    assert!(path.iter().any(|p| p.1 > center.1 + 4.9) && path.iter().any(|p| p.1 < center.1 - 4.9));
+
+    // without bulges the vertices are joined by straight segments
+    let text = dxf(&[entity("LWPOLYLINE", "0", &[(90, 3.0), (10, 0.0), (20, 0.0), (10, 4.0), (20, 0.0), (10, 4.0), (20, 3.0)])]);
+    let drawing = parse(&text, 0.01, &[]).unwrap();
+    assert_eq!(drawing.paths, vec![vec![(0.0, 3.0), (4.0, 3.0), (4.0, 0.0)]]);
+  }
+
+  #[test]
+  fn mirrored_entities() {
+    // the line stays in place, the arc and the spline are mirrored along x by their extrusion direction
+    let text = dxf(&[
+      entity("LINE", "0", &[(10, 0.0), (20, 0.0), (11, 0.0), (21, 5.0)]),
+      entity("SPLINE", "0", &[(71, 1.0), (40, 0.0), (40, 0.0), (40, 1.0), (40, 1.0), (10, 1.0), (20, 0.0), (10, 2.0), (20, 1.0), (230, -1.0)]),
+      entity("ARC", "0", &[(10, 3.0), (20, 3.0), (40, 1.0), (50, 0.0), (51, 90.0), (230, -1.0)]),
+    ]);
+    let drawing = parse(&text, 0.01, &[]).unwrap();
+    // the spline runs from (-1, 0) to (-2, 1), the arc from (-4, 3) to (-3, 4) around (-3, 3)
+    // then the drawing is flipped and moved by (4, 5)
+    assert!(drawing.paths.contains(&vec![(4.0, 5.0), (4.0, 0.0)]));
+    assert!(drawing.paths.contains(&vec![(3.0, 5.0), (2.0, 4.0)]));
+    assert_eq!(drawing.circles, vec![((1.0, 2.0), 1.0)]);
+    let arc = drawing.paths.iter().find(|p| p.len() > 2).unwrap();
+    assert!(on_circle(arc, (1.0, 2.0), 1.0));
+    assert!(arc[0].0.abs() < 1e-4 && (arc[0].1 - 2.0).abs() < 1e-4);
+  }
+
+  #[test]
+  fn layers() {
+    let text = dxf(&[
+      entity("LINE", "CUT", &[(10, 0.0), (20, 0.0), (11, 1.0), (21, 0.0)]),
+      entity("LINE", "marks", &[(10, 5.0), (20, 5.0), (11, 6.0), (21, 5.0)]),
+    ]);
+    assert_eq!(parse(&text, 0.1, &[]).unwrap().paths.len(), 2);
+    assert_eq!(parse(&text, 0.1, &["cut".to_string()]).unwrap().paths, vec![vec![(0.0, 0.0), (1.0, 0.0)]]);
+    assert_eq!(
+      parse(&text, 0.1, &["holes".to_string()]).err().unwrap(),
+      "No entities found on the layers holes, the drawing has CUT, marks"
+    );
+  }
+}
@@ -0,0 +1,383 @@
+// Hatch fill: parallel lines clipped to closed regions, joined into zig-zag strokes
+use crate::canny::{SEdges, SPixelIndex};
+use image::GrayImage;
+
+pub type Point = (f32, f32);
+pub type Polygon = Vec<Point>;
+pub type Polyline = Vec<Point>;
+pub type Segment = (Point, Point);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FillOptions {
+  // distance between hatch lines (px)
+  pub spacing: f32,
+  // direction of the hatch lines, measured from the x axis towards the y axis (degrees)
+  pub angle: f32,
+  // adds a second set of lines, perpendicular to the first one
+  pub cross_hatch: bool,
+  // pixels darker than this are filled
+  pub threshold: u8,
+}
+
+impl Default for FillOptions {
+  fn default() -> Self {
+    FillOptions {
+      spacing: 4.0,
+      angle: 45.0,
+      cross_hatch: false,
+      threshold: 128,
+    }
+  }
+}
+
+// orientation values this close to zero are considered collinear
+const EPSILON: f32 = 1e-3;
+
+fn rotate(p: Point, angle: f32) -> Point {
+  let (sin, cos) = angle.sin_cos();
+  (p.0 * cos - p.1 * sin, p.0 * sin + p.1 * cos)
+}
+
+fn orientation(a: Point, b: Point, c: Point) -> f32 {
+  (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+// true only when the segments cross each other, touching does not count
+fn crosses(p: Point, q: Point, a: Point, b: Point) -> bool {
+  let sides = |o1: f32, o2: f32| (o1 > EPSILON && o2 < -EPSILON) || (o1 < -EPSILON && o2 > EPSILON);
+  sides(orientation(p, q, a), orientation(p, q, b)) && sides(orientation(a, b, p), orientation(a, b, q))
+}
+
+// edges of closed polygons, holes are polygons as well (even-odd rule)
+pub fn polygon_boundary(polygons: &[Polygon]) -> Vec<Segment> {
+  let mut segments = vec![];
+  for polygon in polygons.iter().filter(|polygon| polygon.len() > 2) {
+    for (index, point) in polygon.iter().enumerate() {
+      segments.push((*point, polygon[(index + 1) % polygon.len()]));
+    }
+  }
+  segments
+}
+
+// outline of the pixels for which `inside` holds, following pixel borders
+// pixel (x, y) covers [x - 0.5, x + 0.5] x [y - 0.5, y + 0.5], so the outline matches the edge coordinates
+pub fn region_boundary<F: Fn(u32, u32) -> bool>(width: u32, height: u32, inside: F) -> Vec<Segment> {
+  let (w, h) = (width as i64, height as i64);
+  let at = |x: i64, y: i64| x >= 0 && y >= 0 && x < w && y < h && inside(x as u32, y as u32);
+  let mut segments = vec![];
+
+  // horizontal borders between the rows y - 1 and y, merged into runs
+  for y in 0..=h {
+    let mut start: Option<i64> = None;
+    for x in 0..=w {
+      let border = x < w && at(x, y - 1) != at(x, y);
+      match (border, start) {
+        (true, None) => start = Some(x),
+        (false, Some(s)) => {
+          segments.push(((s as f32 - 0.5, y as f32 - 0.5), (x as f32 - 0.5, y as f32 - 0.5)));
+          start = None;
+        },
+        _ => {},
+      }
+    }
+  }
+  // vertical borders between the columns x - 1 and x
+  for x in 0..=w {
+    let mut start: Option<i64> = None;
+    for y in 0..=h {
+      let border = y < h && at(x - 1, y) != at(x, y);
+      match (border, start) {
+        (true, None) => start = Some(y),
+        (false, Some(s)) => {
+          segments.push(((x as f32 - 0.5, s as f32 - 0.5), (x as f32 - 0.5, y as f32 - 0.5)));
+          start = None;
+        },
+        _ => {},
+      }
+    }
+  }
+  segments
+}
+
+// intervals of a hatch line inside the area, given its crossings with the segments reaching above and below it
+// both agree unless the line lies along a border, then only the parts with the area on both sides are kept
+fn interior(mut above: Vec<f32>, mut below: Vec<f32>) -> Vec<(f32, f32)> {
+  above.sort_by(|a, b| a.partial_cmp(b).unwrap());
+  below.sort_by(|a, b| a.partial_cmp(b).unwrap());
+  let (above, below) = (above.chunks_exact(2).collect::<Vec<_>>(), below.chunks_exact(2).collect::<Vec<_>>());
+
+  let mut intervals = vec![];
+  let (mut i, mut j) = (0, 0);
+  while i < above.len() && j < below.len() {
+    let (start, end) = (above[i][0].max(below[j][0]), above[i][1].min(below[j][1]));
+    if start < end {
+      intervals.push((start, end));
+    }
+    if above[i][1] < below[j][1] {
+      i += 1;
+    }
+    else {
+      j += 1;
+    }
+  }
+  intervals
+}
+
+// a zig-zag stroke in the rotated frame, where hatch lines are horizontal
+struct Stroke {
+  points: Vec<Point>,
+  // last hatch line and interval the stroke went through
+  line: usize,
+  interval: (f32, f32),
+  forward: bool,
+}
+
+// hatch lines at the given spacing and angle (degrees), clipped to the area enclosed by the boundary
+// consecutive lines are joined when the connecting move stays inside the area
+// lines are placed at multiples of the spacing from the origin, a line lying along a border (such as the pixel
+// borders of a region_boundary()) is not drawn along it, on either side of the area
+pub fn hatch(boundary: &[Segment], spacing: f32, angle: f32) -> Vec<Polyline> {
+  hatch_with_phase(boundary, spacing, angle, 0.0)
+}
+
+// same as hatch(), lines are placed at (n + phase) * spacing from the origin, so that
+// hatches with different spacings and phases can be interleaved without overlapping
+pub fn hatch_with_phase(boundary: &[Segment], spacing: f32, angle: f32, phase: f32) -> Vec<Polyline> {
+  if boundary.is_empty() || spacing <= 0.0 {
+    return vec![];
+  }
+  let angle = angle.to_radians();
+  let rotated = boundary
+    .iter()
+    .map(|(a, b)| (rotate(*a, -angle), rotate(*b, -angle)))
+    .collect::<Vec<Segment>>();
+
+  let min_v = rotated.iter().map(|(a, b)| a.1.min(b.1)).fold(f32::MAX, f32::min);
+  let max_v = rotated.iter().map(|(a, b)| a.1.max(b.1)).fold(f32::MIN, f32::max);
+  let first_line = (min_v / spacing - phase).ceil();
+  let last_line = (max_v / spacing - phase).floor();
+  if last_line < first_line {
+    return vec![];
+  }
+  let count = (last_line - first_line) as usize + 1;
+  let line_v = |line: usize| (first_line + line as f32 + phase) * spacing;
+  // index of the last line at or before v
+  let line_at = |v: f32| ((v / spacing - phase).floor() - first_line).max(0.0) as usize;
+
+  // crossings of each hatch line with the segments reaching past it, and the segments found between a line and the next one
+  let mut above: Vec<Vec<f32>> = vec![vec![]; count];
+  let mut below: Vec<Vec<f32>> = vec![vec![]; count];
+  let mut bands: Vec<Vec<usize>> = vec![vec![]; count];
+  for (index, (a, b)) in rotated.iter().enumerate() {
+    let (low, high) = if a.1 <= b.1 { (a, b) } else { (b, a) };
+    let first = line_at(low.1).min(count - 1);
+    let last = line_at(high.1).min(count - 1);
+    for line in first..=last {
+      bands[line].push(index);
+      // half open, so that lines going through a vertex count it once
+      let v = line_v(line);
+      let x = || low.0 + (v - low.1) / (high.1 - low.1) * (high.0 - low.0);
+      if low.1 <= v && v < high.1 {
+        above[line].push(x());
+      }
+      if low.1 < v && v <= high.1 {
+        below[line].push(x());
+      }
+    }
+  }
+
+  let mut done: Vec<Stroke> = vec![];
+  let mut open: Vec<Stroke> = vec![];
+  for (line, (upper, lower)) in above.into_iter().zip(below).enumerate() {
+    let v = line_v(line);
+    let mut extended: Vec<Stroke> = vec![];
+
+    for (x0, x1) in interior(upper, lower) {
+      if x1 - x0 < EPSILON {
+        continue;
+      }
+
+      // try to continue a stroke from the previous line, going the other way
+      let candidate = open.iter().position(|stroke| {
+        if stroke.line + 1 != line || stroke.interval.1 < x0 || stroke.interval.0 > x1 {
+          return false;
+        }
+        let from = *stroke.points.last().unwrap();
+        let to = (if stroke.forward { x1 } else { x0 }, v);
+        !bands[stroke.line]
+          .iter()
+          .any(|&segment| crosses(from, to, rotated[segment].0, rotated[segment].1))
+      });
+
+      match candidate {
+        Some(index) => {
+          let mut stroke = open.remove(index);
+          stroke.forward = !stroke.forward;
+          if stroke.forward {
+            stroke.points.extend([(x0, v), (x1, v)]);
+          }
+          else {
+            stroke.points.extend([(x1, v), (x0, v)]);
+          }
+          stroke.line = line;
+          stroke.interval = (x0, x1);
+          extended.push(stroke);
+        },
+        None => extended.push(Stroke {
+          points: vec![(x0, v), (x1, v)],
+          line,
+          interval: (x0, x1),
+          forward: true,
+        }),
+      }
+    }
+
+    // strokes that could not be continued on this line are complete
+    done.append(&mut open);
+    open = extended;
+  }
+  done.append(&mut open);
+
+  done
+    .into_iter()
+    .map(|stroke| stroke.points.into_iter().map(|p| rotate(p, angle)).collect())
+    .collect()
+}
+
+pub fn fill(boundary: &[Segment], options: &FillOptions) -> Vec<Polyline> {
+  let mut strokes = hatch(boundary, options.spacing, options.angle);
+  if options.cross_hatch {
+    strokes.extend(hatch(boundary, options.spacing, options.angle + 90.0));
+  }
+  strokes
+}
+
+// fills the dark areas of a grayscale image
+pub fn fill_image(image: &GrayImage, options: &FillOptions) -> Vec<Polyline> {
+  let boundary = region_boundary(image.width(), image.height(), |x, y| {
+    image.get_pixel(x, y)[0] < options.threshold
+  });
+  fill(&boundary, options)
+}
+
+// rounds the strokes to pixel indices, so they can be exported along with traced edges
+pub fn to_edges(strokes: &[Polyline]) -> SEdges<usize> {
+  strokes
+    .iter()
+    .filter(|stroke| !stroke.is_empty())
+    .map(|stroke| {
+      stroke
+        .iter()
+        .map(|p| SPixelIndex {
+          x: p.0.round().max(0.0) as usize,
+          y: p.1.round().max(0.0) as usize,
+        })
+        .collect()
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::Luma;
+
+  fn square(size: f32) -> Vec<Segment> {
+    polygon_boundary(&[vec![(0.0, 0.0), (size, 0.0), (size, size), (0.0, size)]])
+  }
+
+  #[test]
+  fn boundaries() {
+    let segments = polygon_boundary(&[vec![(0.0, 0.0), (4.0, 0.0), (0.0, 3.0)], vec![(1.0, 1.0), (2.0, 2.0)]]);
+    assert_eq!(segments, vec![((0.0, 0.0), (4.0, 0.0)), ((4.0, 0.0), (0.0, 3.0)), ((0.0, 3.0), (0.0, 0.0))]);
+
+    // a 2x2 block of pixels gives one merged border per side
+    let segments = region_boundary(4, 4, |x, y| (1..3).contains(&x) && (1..3).contains(&y));
+    assert_eq!(segments.len(), 4);
+    assert!(segments.contains(&((0.5, 0.5), (2.5, 0.5))));
+    assert!(segments.contains(&((2.5, 0.5), (2.5, 2.5))));
+    // the image edges close the regions touching them
+    assert_eq!(region_boundary(3, 2, |_, _| true).len(), 4);
+  }
+
+  #[test]
+  fn zig_zag() {
+    let strokes = hatch(&square(10.0), 2.0, 0.0);
+    assert_eq!(
+      strokes,
+      vec![vec![
+        (0.0, 2.0), (10.0, 2.0), (10.0, 4.0), (0.0, 4.0), (0.0, 6.0), (10.0, 6.0), (10.0, 8.0), (0.0, 8.0),
+      ]]
+    );
+    // lines shifted by half the spacing
+    let shifted = hatch_with_phase(&square(10.0), 2.0, 0.0, 0.5);
+    assert_eq!(shifted[0].iter().map(|p| p.1).collect::<Vec<_>>(), vec![1.0, 1.0, 3.0, 3.0, 5.0, 5.0, 7.0, 7.0, 9.0, 9.0]);
+
+    // vertical lines
+    let strokes = hatch(&square(10.0), 2.0, 90.0);
+    assert_eq!(strokes.len(), 1);
+    assert!(strokes[0].iter().all(|p| (p.0 / 2.0 - (p.0 / 2.0).round()).abs() < 1e-4));
+    assert!(hatch(&square(10.0), 0.0, 0.0).is_empty());
+    assert!(hatch(&[], 2.0, 0.0).is_empty());
+  }
+
+  #[test]
+  fn strokes_stay_inside() {
+    // a U shape, lines across both arms can't be joined
+    let boundary = polygon_boundary(&[vec![(0.0, 0.0), (30.0, 0.0), (30.0, 30.0), (20.0, 30.0), (20.0, 10.0), (10.0, 10.0), (10.0, 30.0), (0.0, 30.0)]]);
+    let strokes = hatch(&boundary, 2.0, 0.0);
+    assert_eq!(strokes.len(), 2);
+    for stroke in &strokes {
+      for pair in stroke.windows(2) {
+        assert!(!boundary.iter().any(|(a, b)| crosses(pair[0], pair[1], *a, *b)));
+      }
+    }
+    // every line is there
+    assert_eq!(strokes.iter().map(|stroke| stroke.len()).sum::<usize>(), 2 * (4 + 2 * 10));
+  }
+
+  #[test]
+  fn lines_along_borders() {
+    // rows 5 to 7 cover 4.5..7.5, the lines at 4.5 and 7.5 lie along the top and bottom borders
+    let boundary = region_boundary(10, 12, |_, y| (5..=7).contains(&y));
+    assert_eq!(hatch(&boundary, 1.5, 0.0), vec![vec![(-0.5, 6.0), (9.5, 6.0)]]);
+    // the same region upside down
+    let flipped = boundary.iter().map(|(a, b)| ((a.0, 12.0 - a.1), (b.0, 12.0 - b.1))).collect::<Vec<Segment>>();
+    assert_eq!(hatch(&flipped, 1.5, 0.0), vec![vec![(-0.5, 6.0), (9.5, 6.0)]]);
+
+    // the line still goes through an area it does not lie along
+    let boundary = region_boundary(10, 12, |x, y| (5..=7).contains(&y) || (x >= 5 && (3..=9).contains(&y)));
+    let lines = hatch(&boundary, 1.5, 0.0).concat();
+    let on = |v: f32| {
+      let mut xs = lines.iter().filter(|p| p.1 == v).map(|p| p.0).collect::<Vec<f32>>();
+      xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+      xs
+    };
+    assert_eq!((on(4.5), on(7.5)), (vec![4.5, 9.5], vec![4.5, 9.5]));
+    assert_eq!(on(6.0), vec![-0.5, 9.5]);
+  }
+
+  #[test]
+  fn cross_hatch() {
+    let options = FillOptions { spacing: 2.0, angle: 0.0, cross_hatch: true, ..FillOptions::default() };
+    let strokes = fill(&square(10.0), &options);
+    assert_eq!(strokes.len(), 2);
+    assert!(strokes[1].iter().all(|p| (p.0 / 2.0 - (p.0 / 2.0).round()).abs() < 1e-4));
+  }
+
+  #[test]
+  fn dark_pixels_are_filled() {
+    let image = GrayImage::from_fn(20, 20, |x, y| Luma([if (5..15).contains(&x) && (5..15).contains(&y) { 0 } else { 255 }]));
+    let strokes = fill_image(&image, &FillOptions { spacing: 2.0, angle: 0.0, ..FillOptions::default() });
+    assert_eq!(strokes.len(), 1);
+    assert!(strokes[0].iter().all(|p| p.0 >= 4.5 && p.0 <= 14.5 && p.1 >= 4.5 && p.1 <= 14.5));
+    assert!(fill_image(&image, &FillOptions { threshold: 0, ..FillOptions::default() }).is_empty());
+  }
+
+  #[test]
+  fn edges_of_strokes() {
+    let edges = to_edges(&[vec![(1.4, 2.6), (-0.7, 3.0)], vec![]]);
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0].iter().map(|p| (p.x, p.y)).collect::<Vec<_>>(), vec![(1, 3), (0, 3)]);
+  }
+}
@@ -0,0 +1,480 @@
+// G-code writer for GRBL-like controllers
+use crate::canny::PenDirection;
+use crate::command::Command;
+use crate::fill::Point;
+use crate::machine::MachineModel;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GcodeOptions {
+  // blocks that raise and lower the pen (ie: servo or spindle/laser control)
+  pub pen_up: String,
+  pub pen_down: String,
+  // dwell after each pen change, so the pen settles before moving
+  pub dwell_on_pen_change: bool,
+  // holding tabs are crossed with the tool raised to this Z (mm), instead of lifting the pen
+  pub tab_height: Option<f32>,
+  // the pen is the Z axis when set: it is raised to this Z (mm) and lowered to the depth of the cut
+  // pen_down and pen_up then start and stop the spindle around the whole job
+  pub safe_z: Option<f32>,
+  // feed rate of the Z axis when lowering the tool (mm/min)
+  pub plunge_feed: f32,
+  // pen down moves following a circle are joined into G2 / G3 arcs, straying at most this far from the moves (mm)
+  pub arc_tolerance: Option<f32>,
+  // circles the moves were flattened from, as (center, radius) in command units
+  // moves along them become arcs around their exact centers, within `arc_tolerance`
+  pub circles: Vec<(Point, f32)>,
+}
+
+impl Default for GcodeOptions {
+  fn default() -> GcodeOptions {
+    GcodeOptions {
+      pen_up: "M5".to_string(),
+      pen_down: "M3 S1000".to_string(),
+      dwell_on_pen_change: true,
+      tab_height: None,
+      safe_z: None,
+      plunge_feed: 300.0,
+      arc_tolerance: None,
+      circles: vec![],
+    }
+  }
+}
+
+// formats a coordinate given in command units as mm
+pub(crate) fn mm(value: f32, machine: &MachineModel) -> String {
+  let value = format!("{:.3}", value * machine.unit_size);
+  // avoid "-0.000"
+  if value == "-0.000" { "0.000".to_string() } else { value }
+}
+
+// formats a Z given in mm
+pub(crate) fn z(value: f32) -> String {
+  let value = format!("{:.3}", value);
+  if value == "-0.000" { "0.000".to_string() } else { value }
+}
+
+// `depth` is the Z the tool is lowered to, when the pen is the Z axis
+pub(crate) fn pen_gcode(direction: PenDirection, depth: f32, machine: &MachineModel, options: &GcodeOptions, gcode: &mut Vec<String>) {
+  if let Some(safe_z) = options.safe_z {
+    gcode.push(match direction {
+      PenDirection::UP => format!("G0 Z{}", z(safe_z)),
+      PenDirection::DOWN => format!("G1 Z{} F{}", z(depth), options.plunge_feed),
+    });
+    return;
+  }
+  gcode.push(match direction {
+    PenDirection::UP => options.pen_up.clone(),
+    PenDirection::DOWN => options.pen_down.clone(),
+  });
+  if options.dwell_on_pen_change && machine.pen_delay > 0.0 {
+    gcode.push(format!("G4 P{:.3}", machine.pen_delay));
+  }
+}
+
+// circle through three points, None when they are in line
+fn circle(a: Point, b: Point, c: Point) -> Option<(Point, f32)> {
+  let d = 2.0 * (a.0 * (b.1 - c.1) + b.0 * (c.1 - a.1) + c.0 * (a.1 - b.1));
+  if d.abs() < 1e-9 {
+    return None;
+  }
+  let (a2, b2, c2) = (a.0 * a.0 + a.1 * a.1, b.0 * b.0 + b.1 * b.1, c.0 * c.0 + c.1 * c.1);
+  let center = (
+    (a2 * (b.1 - c.1) + b2 * (c.1 - a.1) + c2 * (a.1 - b.1)) / d,
+    (a2 * (c.0 - b.0) + b2 * (a.0 - c.0) + c2 * (b.0 - a.0)) / d,
+  );
+  Some((center, ((a.0 - center.0).powi(2) + (a.1 - center.1).powi(2)).sqrt()))
+}
+
+// center and direction (clockwise or not) of the arc running through `points` within `tolerance`
+// the arc has to turn one way, under a full turn, and stray from the straight line between its ends
+fn fit_arc(points: &[Point], tolerance: f32) -> Option<(Point, bool)> {
+  let (first, last) = (points[0], points[points.len() - 1]);
+  let (center, radius) = circle(first, points[points.len() / 2], last)?;
+  let mut sweep = 0.0;
+  for pair in points.windows(2) {
+    let (a, b) = ((pair[0].0 - center.0, pair[0].1 - center.1), (pair[1].0 - center.0, pair[1].1 - center.1));
+    let step = (a.0 * b.1 - a.1 * b.0).atan2(a.0 * b.0 + a.1 * b.1);
+    // each chord of the moves bows away from the arc by its sagitta
+    let chord = ((pair[1].0 - pair[0].0).powi(2) + (pair[1].1 - pair[0].1).powi(2)).sqrt();
+    let sagitta = radius - (radius * radius - chord * chord / 4.0).max(0.0).sqrt();
+    let off = ((b.0 * b.0 + b.1 * b.1).sqrt() - radius).abs();
+    if step == 0.0 || (sweep != 0.0 && step.signum() != f32::signum(sweep)) || sagitta > tolerance || off > tolerance {
+      return None;
+    }
+    sweep += step;
+  }
+  if sweep.abs() >= 2.0 * std::f32::consts::PI - 1e-3 {
+    return None;
+  }
+  let (dx, dy) = (last.0 - first.0, last.1 - first.1);
+  let length = (dx * dx + dy * dy).sqrt();
+  let bow = points.iter().map(|p| ((p.0 - first.0) * dy - (p.1 - first.1) * dx).abs() / length).fold(0.0, f32::max);
+  if length == 0.0 || bow <= tolerance {
+    return None;
+  }
+  Some((center, sweep < 0.0))
+}
+
+// `from` followed by the points of the moves leading `commands`
+fn moves_from(from: Point, commands: &[Command]) -> Vec<Point> {
+  let mut points = vec![from];
+  for command in commands.iter().take(1000) {
+    match *command {
+      Command::Move(x, y) => points.push((x, y)),
+      _ => break,
+    }
+  }
+  points
+}
+
+// the longest arc (of at least 2 moves) along one of the known `circles`, starting at `from` along the moves leading `commands`
+// the points have to lie on the circle and the moves to turn one way, a whole turn at most
+// chords bowing away from the circle by more than `tolerance` are kept as straight moves
+fn known_arc_from(from: Point, commands: &[Command], circles: &[(Point, f32)], tolerance: f32) -> Option<(usize, Point, Point, bool)> {
+  let on = |p: Point, center: Point, radius: f32| (((p.0 - center.0).powi(2) + (p.1 - center.1).powi(2)).sqrt() - radius).abs() <= tolerance;
+  let mut candidates = circles.iter().filter(|(center, radius)| on(from, *center, *radius)).peekable();
+  candidates.peek()?;
+  let points = moves_from(from, commands);
+
+  let mut arc: Option<(usize, Point, Point, bool)> = None;
+  for &(center, radius) in candidates {
+    let mut sweep = 0.0;
+    let mut count = 0;
+    for pair in points.windows(2) {
+      if !on(pair[1], center, radius) {
+        break;
+      }
+      let (a, b) = ((pair[0].0 - center.0, pair[0].1 - center.1), (pair[1].0 - center.0, pair[1].1 - center.1));
+      let step = (a.0 * b.1 - a.1 * b.0).atan2(a.0 * b.0 + a.1 * b.1);
+      let half_chord = ((pair[1].0 - pair[0].0).powi(2) + (pair[1].1 - pair[0].1).powi(2)) / 4.0;
+      let sagitta = half_chord / (radius + (radius * radius - half_chord).max(0.0).sqrt());
+      // the flattened arcs stay within the tolerance, give or take the rounding
+      if step == 0.0 || (sweep != 0.0 && step.signum() != f32::signum(sweep)) || sagitta > tolerance * 1.01 {
+        break;
+      }
+      if (sweep + step).abs() > 2.0 * std::f32::consts::PI + 1e-3 {
+        break;
+      }
+      sweep += step;
+      count += 1;
+    }
+    if count >= 2 && count > arc.map(|(longest, ..)| longest).unwrap_or(1) {
+      arc = Some((count, points[count], center, sweep < 0.0));
+    }
+  }
+  arc
+}
+
+// the longest arc (of at least 3 moves) starting at `from` along the moves leading `commands`
+// returns the number of moves it replaces, its end, its center and its direction
+fn arc_from(from: Point, commands: &[Command], tolerance: f32) -> Option<(usize, Point, Point, bool)> {
+  let points = moves_from(from, commands);
+  let mut arc = None;
+  for count in 3..points.len() {
+    match fit_arc(&points[..=count], tolerance) {
+      Some((center, clockwise)) => arc = Some((count, points[count], center, clockwise)),
+      None => break,
+    }
+  }
+  arc
+}
+
+// converts parsed commands into G-code, coordinates are scaled to mm by the machine unit size
+pub fn to_gcode(commands: &[Command], machine: &MachineModel, options: &GcodeOptions) -> String {
+  let mut gcode: Vec<String> = vec![
+    "G21".to_string(), // millimeters
+    "G90".to_string(), // absolute positioning
+  ];
+  let mut pen = PenDirection::UP;
+  let mut depth = 0.0;
+  let mut position = (0.0, 0.0);
+  // the tool was raised over a tab, it stays there across the following tab moves
+  let mut raised = false;
+  // moves already written as part of an arc
+  let mut skip = 0;
+  pen_gcode(pen, depth, machine, options, &mut gcode);
+  if options.safe_z.is_some() {
+    gcode.push(options.pen_down.clone()); // spindle on
+  }
+
+  for (index, command) in commands.iter().enumerate() {
+    if skip > 0 {
+      skip -= 1;
+      continue;
+    }
+    // the tool goes back down once the tab is crossed, unless it is lifted anyway
+    if raised && !matches!(command, Command::Tab(..)) {
+      raised = false;
+      if matches!(command, Command::Move(..) | Command::Ramp(..) | Command::Depth(_)) {
+        match options.tab_height {
+          Some(_) => gcode.push(format!("G1 Z{} F{}", z(depth), options.plunge_feed)),
+          None => pen_gcode(PenDirection::DOWN, depth, machine, options, &mut gcode),
+        }
+      }
+    }
+    match *command {
+      Command::Pen(direction) => {
+        if direction != pen {
+          pen = direction;
+          pen_gcode(pen, depth, machine, options, &mut gcode);
+        }
+      },
+      Command::Move(x, y) => {
+        let arc = match (pen, options.arc_tolerance) {
+          (PenDirection::DOWN, Some(tolerance)) => {
+            let tolerance = tolerance / machine.unit_size;
+            known_arc_from(position, &commands[index..], &options.circles, tolerance)
+              .or_else(|| arc_from(position, &commands[index..], tolerance))
+          },
+          _ => None,
+        };
+        if let Some((count, (x, y), center, clockwise)) = arc {
+          gcode.push(format!(
+            "{} X{} Y{} I{} J{} F{}",
+            if clockwise { "G2" } else { "G3" },
+            mm(x, machine),
+            mm(y, machine),
+            mm(center.0 - position.0, machine),
+            mm(center.1 - position.1, machine),
+            machine.max_feed
+          ));
+          position = (x, y);
+          skip = count - 1;
+          continue;
+        }
+        position = (x, y);
+        gcode.push(match pen {
+          PenDirection::UP => format!("G0 X{} Y{}", mm(x, machine), mm(y, machine)),
+          PenDirection::DOWN => format!("G1 X{} Y{} F{}", mm(x, machine), mm(y, machine), machine.max_feed),
+        });
+      },
+      Command::Reset => {
+        if pen != PenDirection::UP {
+          pen = PenDirection::UP;
+          pen_gcode(pen, depth, machine, options, &mut gcode);
+        }
+        gcode.push("G0 X0 Y0".to_string());
+        position = (0.0, 0.0);
+      },
+      Command::Tool(index, color) => {
+        if pen != PenDirection::UP {
+          pen = PenDirection::UP;
+          pen_gcode(pen, depth, machine, options, &mut gcode);
+        }
+        if options.safe_z.is_some() {
+          gcode.push(options.pen_up.clone()); // spindle off
+        }
+        // GRBL has no tool changer, pause the program until the pen is swapped
+        gcode.push(match color {
+          Some([r, g, b]) => format!("M0 (pen {} #{:02x}{:02x}{:02x})", index, r, g, b),
+          None => format!("M0 (pen {})", index),
+        });
+        if options.safe_z.is_some() {
+          gcode.push(options.pen_down.clone());
+        }
+      },
+      Command::Dwell(seconds) => gcode.push(format!("G4 P{:.3}", seconds)),
+      Command::Tab(x, y) => {
+        position = (x, y);
+        match (pen, options.tab_height) {
+          (PenDirection::UP, _) => gcode.push(format!("G0 X{} Y{}", mm(x, machine), mm(y, machine))),
+          // passes above the tab cut through as usual
+          (PenDirection::DOWN, Some(height)) if depth >= height => {
+            gcode.push(format!("G1 X{} Y{} F{}", mm(x, machine), mm(y, machine), machine.max_feed));
+          },
+          // Z raised segments, the spindle keeps running
+          (PenDirection::DOWN, Some(height)) => {
+            if !raised {
+              gcode.push(format!("G0 Z{}", z(height)));
+              raised = true;
+            }
+            gcode.push(format!("G1 X{} Y{} F{}", mm(x, machine), mm(y, machine), machine.max_feed));
+          },
+          (PenDirection::DOWN, None) => {
+            if !raised {
+              pen_gcode(PenDirection::UP, depth, machine, options, &mut gcode);
+              raised = true;
+            }
+            gcode.push(format!("G0 X{} Y{}", mm(x, machine), mm(y, machine)));
+          },
+        }
+      },
+      Command::Depth(to) => {
+        depth = to;
+        if pen == PenDirection::DOWN && options.safe_z.is_some() {
+          gcode.push(format!("G1 Z{} F{}", z(depth), options.plunge_feed));
+        }
+      },
+      Command::Ramp(x, y, to) => {
+        depth = to;
+        position = (x, y);
+        gcode.push(match (pen, options.safe_z) {
+          (PenDirection::UP, _) => format!("G0 X{} Y{}", mm(x, machine), mm(y, machine)),
+          (PenDirection::DOWN, Some(_)) => format!("G1 X{} Y{} Z{} F{}", mm(x, machine), mm(y, machine), z(depth), machine.max_feed),
+          // without a Z axis the depth is ignored
+          (PenDirection::DOWN, None) => format!("G1 X{} Y{} F{}", mm(x, machine), mm(y, machine), machine.max_feed),
+        });
+      },
+      Command::End => break,
+    }
+  }
+
+  if pen != PenDirection::UP {
+    pen_gcode(PenDirection::UP, depth, machine, options, &mut gcode);
+  }
+  if options.safe_z.is_some() {
+    gcode.push(options.pen_up.clone()); // spindle off
+  }
+  gcode.push("M2".to_string()); // end of program
+  gcode.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::f32::consts::PI;
+
+  fn lines(commands: &[Command], machine: &MachineModel, options: &GcodeOptions) -> Vec<String> {
+    to_gcode(commands, machine, options).lines().map(|line| line.to_string()).collect()
+  }
+
+  // pen down moves along the circle, `steps` per turn, for `count` steps
+  fn around(center: Point, radius: f32, steps: usize, count: usize) -> Vec<Command> {
+    let at = |i: usize| {
+      let angle = 2.0 * PI * i as f32 / steps as f32;
+      (center.0 + radius * angle.cos(), center.1 + radius * angle.sin())
+    };
+    let mut commands = vec![Command::Move(at(0).0, at(0).1), Command::Pen(PenDirection::DOWN)];
+    commands.extend((1..=count).map(|i| Command::Move(at(i).0, at(i).1)));
+    commands.push(Command::Pen(PenDirection::UP));
+    commands
+  }
+
+  #[test]
+  fn moves_and_pen_changes() {
+    let machine = MachineModel { unit_size: 0.5, ..MachineModel::default() };
+    let commands = vec![
+      Command::Move(10.0, 20.0),
+      Command::Pen(PenDirection::DOWN),
+      Command::Move(20.0, -0.0001),
+      Command::Dwell(1.0),
+      Command::Tool(2, Some([255, 0, 0])),
+      Command::Move(0.0, 0.0),
+      Command::Reset,
+      Command::End,
+      Command::Move(5.0, 5.0),
+    ];
+    assert_eq!(
+      lines(&commands, &machine, &GcodeOptions::default()),
+      vec![
+        "G21", "G90", "M5", "G4 P0.150", "G0 X5.000 Y10.000", "M3 S1000", "G4 P0.150", "G1 X10.000 Y0.000 F3000", "G4 P1.000", "M5",
+        "G4 P0.150", "M0 (pen 2 #ff0000)", "G0 X0.000 Y0.000", "G0 X0 Y0", "M2",
+      ]
+    );
+  }
+
+  #[test]
+  fn z_axis_and_ramps() {
+    let machine = MachineModel::default();
+    let options = GcodeOptions { safe_z: Some(5.0), ..GcodeOptions::default() };
+    let commands = vec![
+      Command::Depth(0.0),
+      Command::Pen(PenDirection::DOWN),
+      Command::Ramp(10.0, 0.0, -1.0),
+      Command::Depth(-2.0),
+      Command::Move(0.0, 0.0),
+      Command::Pen(PenDirection::UP),
+      Command::Tool(2, None),
+    ];
+    assert_eq!(
+      lines(&commands, &machine, &options),
+      vec![
+        "G21", "G90", "G0 Z5.000", "M3 S1000", "G1 Z0.000 F300", "G1 X10.000 Y0.000 Z-1.000 F3000", "G1 Z-2.000 F300",
+        "G1 X0.000 Y0.000 F3000", "G0 Z5.000", "M5", "M0 (pen 2)", "M3 S1000", "M5", "M2",
+      ]
+    );
+  }
+
+  #[test]
+  fn tabs_raise_the_tool_once() {
+    let machine = MachineModel::default();
+    let cut = |depth: f32| {
+      vec![
+        Command::Depth(depth),
+        Command::Pen(PenDirection::DOWN),
+        Command::Move(10.0, 0.0),
+        Command::Tab(20.0, 0.0),
+        Command::Tab(30.0, 0.0),
+        Command::Move(40.0, 0.0),
+        Command::Pen(PenDirection::UP),
+      ]
+    };
+    let options = GcodeOptions { safe_z: Some(5.0), tab_height: Some(-1.0), ..GcodeOptions::default() };
+    assert_eq!(
+      lines(&cut(-2.0), &machine, &options)[4..],
+      [
+        "G1 Z-2.000 F300", "G1 X10.000 Y0.000 F3000", "G0 Z-1.000", "G1 X20.000 Y0.000 F3000", "G1 X30.000 Y0.000 F3000", "G1 Z-2.000 F300",
+        "G1 X40.000 Y0.000 F3000", "G0 Z5.000", "M5", "M2",
+      ]
+    );
+    // passes above the tabs cut through them
+    assert!(!lines(&cut(-0.5), &machine, &options).contains(&"G0 Z-1.000".to_string()));
+
+    // without a tab height, the pen is lifted over them
+    let options = GcodeOptions { dwell_on_pen_change: false, ..GcodeOptions::default() };
+    assert_eq!(
+      lines(&cut(0.0), &machine, &options)[3..],
+      ["M3 S1000", "G1 X10.000 Y0.000 F3000", "M5", "G0 X20.000 Y0.000", "G0 X30.000 Y0.000", "M3 S1000", "G1 X40.000 Y0.000 F3000", "M5", "M2"]
+    );
+  }
+
+  #[test]
+  fn fitted_arcs() {
+    let machine = MachineModel::default();
+    let options = GcodeOptions { arc_tolerance: Some(0.25), ..GcodeOptions::default() };
+    // a half turn counterclockwise
+    let gcode = lines(&around((0.0, 0.0), 10.0, 16, 8), &machine, &options);
+    assert!(gcode.contains(&"G3 X-10.000 Y0.000 I-10.000 J0.000 F3000".to_string()));
+    assert!(!gcode.iter().any(|line| line.starts_with("G1 X")));
+
+    // clockwise
+    let mut reversed = around((0.0, 0.0), 10.0, 16, 8);
+    for command in reversed.iter_mut() {
+      if let Command::Move(_, y) = command {
+        *y = -*y;
+      }
+    }
+    assert!(lines(&reversed, &machine, &options).contains(&"G2 X-10.000 Y0.000 I-10.000 J0.000 F3000".to_string()));
+
+    // chords bowing out more than the tolerance stay straight
+    let strict = GcodeOptions { arc_tolerance: Some(0.1), ..options.clone() };
+    assert!(!lines(&around((0.0, 0.0), 10.0, 16, 8), &machine, &strict).iter().any(|line| line.starts_with("G3 ")));
+    // so do moves in line
+    let straight = vec![Command::Pen(PenDirection::DOWN), Command::Move(1.0, 0.0), Command::Move(2.0, 0.0), Command::Move(3.0, 0.0), Command::Move(4.0, 0.0)];
+    assert!(!lines(&straight, &machine, &options).iter().any(|line| line.starts_with("G2 ") || line.starts_with("G3 ")));
+    // and all moves without a tolerance
+    assert!(!lines(&around((0.0, 0.0), 10.0, 16, 8), &machine, &GcodeOptions::default()).iter().any(|line| line.starts_with("G3 ")));
+  }
+
+  #[test]
+  fn arcs_along_known_circles() {
+    let machine = MachineModel { unit_size: 0.5, ..MachineModel::default() };
+    let options = GcodeOptions { arc_tolerance: Some(0.25), circles: vec![((30.0, 40.0), 20.0)], ..GcodeOptions::default() };
+    // a whole turn can't be fitted, it follows the known circle to its start
+    let gcode = lines(&around((30.0, 40.0), 20.0, 32, 32), &machine, &options);
+    assert!(gcode.contains(&"G3 X25.000 Y20.000 I-10.000 J0.000 F3000".to_string()));
+    assert!(!gcode.iter().any(|line| line.starts_with("G1 X")));
+
+    // moves off the circle are left alone
+    let elsewhere = GcodeOptions { circles: vec![((31.0, 40.0), 20.0)], ..options.clone() };
+    let gcode = lines(&around((30.0, 40.0), 20.0, 32, 32), &machine, &elsewhere);
+    assert!(!gcode.contains(&"G3 X25.000 Y20.000 I-10.000 J0.000 F3000".to_string()));
+  }
+
+  #[test]
+  fn circles_through_points() {
+    let (center, radius) = circle((1.0, 0.0), (0.0, 1.0), (-1.0, 0.0)).unwrap();
+    assert!(center.0.abs() < 1e-6 && center.1.abs() < 1e-6 && (radius - 1.0).abs() < 1e-6);
+    assert_eq!(circle((0.0, 0.0), (1.0, 1.0), (2.0, 2.0)), None);
+  }
+}
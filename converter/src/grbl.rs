@@ -0,0 +1,887 @@
+// GRBL streaming protocol: a sender for real controllers and an emulator to stand in for them
+use crate::canny::PenDirection;
+use crate::command::Command;
+use crate::machine::Envelope;
+use crate::vector;
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+// size of the serial receive buffer of GRBL
+pub const RX_BUFFER_SIZE: usize = 128;
+
+const WELCOME: &str = "Grbl 1.1h ['$' for help]";
+
+// largest distance between a G2 / G3 arc and the moves it is read back as (mm)
+const ARC_TOLERANCE: f32 = 0.01;
+
+// realtime commands, these bypass the receive buffer
+const STATUS_REPORT: u8 = b'?';
+const FEED_HOLD: u8 = b'!';
+const CYCLE_START: u8 = b'~';
+const SOFT_RESET: u8 = 0x18;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+  // keep the receive buffer full by counting the characters of unacknowledged lines
+  CharacterCounting,
+  // send one line and wait for its acknowledgement
+  SendResponse,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+  Ok,
+  Error(String),
+  Alarm(String),
+  // anything else: status reports, feedback messages, welcome message
+  Message(String),
+}
+
+impl Response {
+  pub fn parse(line: &str) -> Response {
+    let line = line.trim();
+    if line == "ok" {
+      Response::Ok
+    }
+    else if let Some(code) = line.strip_prefix("error:") {
+      Response::Error(code.to_string())
+    }
+    else if let Some(code) = line.strip_prefix("ALARM:") {
+      Response::Alarm(code.to_string())
+    }
+    else {
+      Response::Message(line.to_string())
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+  Pause,
+  Resume,
+  Abort,
+}
+
+#[derive(Debug)]
+pub enum StreamError {
+  Io(io::Error),
+  // 1 based line number of the G-code program and the error code
+  Rejected(usize, String),
+  // 1 based line number of a block longer than the receive buffer of the controller
+  TooLong(usize),
+  Alarm(String),
+  Aborted,
+  Disconnected,
+}
+
+impl From<io::Error> for StreamError {
+  fn from(error: io::Error) -> StreamError {
+    StreamError::Io(error)
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct SendOptions {
+  pub port: PathBuf,
+  pub baud: u32,
+  pub protocol: Protocol,
+}
+
+// opens a serial device (or a pseudo-terminal) for reading and writing
+pub fn open_port(port: &Path, baud: u32) -> io::Result<File> {
+  let file = OpenOptions::new().read(true).write(true).open(port)?;
+  #[cfg(unix)]
+  configure_port(&file, baud)?;
+  #[cfg(not(unix))]
+  let _ = baud;
+  Ok(file)
+}
+
+// raw 8N1 mode at the given baud rate
+#[cfg(unix)]
+fn configure_port(file: &File, baud: u32) -> io::Result<()> {
+  use std::os::unix::io::AsRawFd;
+
+  let speed = match baud {
+    9600 => libc::B9600,
+    19200 => libc::B19200,
+    38400 => libc::B38400,
+    57600 => libc::B57600,
+    115200 => libc::B115200,
+    230400 => libc::B230400,
+    _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unsupported baud rate {}", baud))),
+  };
+
+  unsafe {
+    let mut termios: libc::termios = std::mem::zeroed();
+    if libc::tcgetattr(file.as_raw_fd(), &mut termios) != 0 {
+      return Err(io::Error::last_os_error());
+    }
+    libc::cfmakeraw(&mut termios);
+    libc::cfsetispeed(&mut termios, speed);
+    libc::cfsetospeed(&mut termios, speed);
+    if libc::tcsetattr(file.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+      return Err(io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+// reads responses on a separate thread so they can be waited upon with a timeout
+fn spawn_reader(port: File) -> Receiver<Response> {
+  let (tx, rx) = mpsc::channel();
+  thread::spawn(move || {
+    for line in BufReader::new(port).lines() {
+      match line {
+        Ok(line) if line.trim().is_empty() => continue,
+        Ok(line) => {
+          if tx.send(Response::parse(&line)).is_err() {
+            break;
+          }
+        },
+        Err(_) => break,
+      }
+    }
+  });
+  rx
+}
+
+// p(ause), r(esume) and a(bort) can be typed on stdin while streaming
+pub fn spawn_stdin_control() -> Receiver<Control> {
+  let (tx, rx) = mpsc::channel();
+  thread::spawn(move || {
+    for line in io::stdin().lock().lines().map_while(Result::ok) {
+      let control = match line.trim() {
+        "p" | "pause" => Control::Pause,
+        "r" | "resume" => Control::Resume,
+        "a" | "abort" => Control::Abort,
+        _ => {
+          println!("Unknown control, use p(ause), r(esume) or a(bort)");
+          continue;
+        },
+      };
+      if tx.send(control).is_err() {
+        break;
+      }
+    }
+  });
+  rx
+}
+
+pub struct Sender {
+  port: File,
+  responses: Receiver<Response>,
+  controls: Receiver<Control>,
+  protocol: Protocol,
+  paused: bool,
+  // line index and length of every line not yet acknowledged
+  in_flight: VecDeque<(usize, usize)>,
+}
+
+impl Sender {
+  pub fn new(port: File, protocol: Protocol, controls: Receiver<Control>) -> io::Result<Sender> {
+    let responses = spawn_reader(port.try_clone()?);
+    Ok(Sender {
+      port,
+      responses,
+      controls,
+      protocol,
+      paused: false,
+      in_flight: VecDeque::new(),
+    })
+  }
+
+  fn realtime(&mut self, byte: u8) -> io::Result<()> {
+    self.port.write_all(&[byte])?;
+    self.port.flush()
+  }
+
+  fn handle_controls(&mut self) -> Result<(), StreamError> {
+    while let Ok(control) = self.controls.try_recv() {
+      match control {
+        Control::Pause => {
+          self.realtime(FEED_HOLD)?;
+          self.paused = true;
+          println!("Feed hold");
+        },
+        Control::Resume => {
+          self.realtime(CYCLE_START)?;
+          self.paused = false;
+          println!("Resumed");
+        },
+        Control::Abort => {
+          self.realtime(SOFT_RESET)?;
+          return Err(StreamError::Aborted);
+        },
+      }
+    }
+    Ok(())
+  }
+
+  // waits for a single ok/error, while still reacting to controls and alarms
+  fn wait_for_ack(&mut self) -> Result<(), StreamError> {
+    loop {
+      self.handle_controls()?;
+      match self.responses.recv_timeout(Duration::from_millis(50)) {
+        Ok(Response::Ok) => {
+          self.in_flight.pop_front();
+          return Ok(());
+        },
+        Ok(Response::Error(code)) => {
+          let line = self.in_flight.pop_front().map(|(line, _)| line + 1).unwrap_or(0);
+          self.realtime(SOFT_RESET)?;
+          return Err(StreamError::Rejected(line, code));
+        },
+        Ok(Response::Alarm(code)) => return Err(StreamError::Alarm(code)),
+        Ok(Response::Message(message)) => println!("{}", message),
+        Err(RecvTimeoutError::Timeout) => {},
+        Err(RecvTimeoutError::Disconnected) => return Err(StreamError::Disconnected),
+      }
+    }
+  }
+
+  fn buffered(&self) -> usize {
+    self.in_flight.iter().map(|(_, length)| length).sum()
+  }
+
+  // wakes the controller up and discards whatever it printed on startup
+  fn wake_up(&mut self) -> Result<(), StreamError> {
+    self.port.write_all(b"\r\n\r\n")?;
+    self.port.flush()?;
+    thread::sleep(Duration::from_secs(2));
+    while let Ok(response) = self.responses.try_recv() {
+      if let Response::Alarm(code) = response {
+        return Err(StreamError::Alarm(code));
+      }
+    }
+    Ok(())
+  }
+
+  // streams the whole program, comments and empty lines are skipped
+  pub fn stream(&mut self, gcode: &str) -> Result<(), StreamError> {
+    self.wake_up()?;
+
+    let lines = gcode.lines().collect::<Vec<&str>>();
+    for (index, line) in lines.iter().enumerate() {
+      let block = strip_comment(line);
+      if block.is_empty() {
+        continue;
+      }
+      let length = block.len() + 1; // newline included
+      // the controller could never take it in, waiting for room would hang forever
+      if self.protocol == Protocol::CharacterCounting && length > RX_BUFFER_SIZE {
+        return Err(StreamError::TooLong(index + 1));
+      }
+
+      loop {
+        self.handle_controls()?;
+        let ready = !self.paused && match self.protocol {
+          Protocol::CharacterCounting => self.buffered() + length <= RX_BUFFER_SIZE,
+          Protocol::SendResponse => self.in_flight.is_empty(),
+        };
+        if ready {
+          break;
+        }
+        if self.in_flight.is_empty() {
+          // paused with nothing left to acknowledge
+          thread::sleep(Duration::from_millis(50));
+        }
+        else {
+          self.wait_for_ack()?;
+        }
+      }
+
+      self.port.write_all(format!("{}\n", block).as_bytes())?;
+      self.port.flush()?;
+      self.in_flight.push_back((index, length));
+      print!("\rSent {}/{}", index + 1, lines.len());
+      io::stdout().flush()?;
+    }
+
+    while !self.in_flight.is_empty() {
+      self.wait_for_ack()?;
+    }
+    println!();
+    Ok(())
+  }
+}
+
+fn strip_comment(line: &str) -> String {
+  let line = match line.find(';') {
+    Some(index) => &line[..index],
+    None => line,
+  };
+  // parenthesis comments can appear anywhere in a block
+  let mut block = String::new();
+  let mut in_comment = false;
+  for c in line.chars() {
+    match c {
+      '(' => in_comment = true,
+      ')' => in_comment = false,
+      c if !in_comment => block.push(c),
+      _ => {},
+    }
+  }
+  block.trim().to_string()
+}
+
+pub fn send(gcode: &str, options: &SendOptions) -> Result<(), StreamError> {
+  let port = open_port(&options.port, options.baud)?;
+  println!("Streaming to {} (type p, r or a followed by enter to pause, resume or abort)", options.port.display());
+  let mut sender = Sender::new(port, options.protocol, spawn_stdin_control())?;
+  sender.stream(gcode)
+}
+
+// the machine behind the emulated serial protocol
+pub trait Controller {
+  // executes a single G-code block, Err holds a GRBL error code
+  fn execute(&mut self, block: &str) -> Result<(), u8>;
+  // machine position (mm)
+  fn position(&self) -> (f32, f32, f32);
+  // set when a block triggered an alarm (ie: soft limits)
+  fn alarm(&self) -> Option<u8> {
+    None
+  }
+  fn unlock(&mut self) {}
+  fn reset(&mut self) {}
+  // notified on feed hold (true) and cycle start (false)
+  fn feed_hold(&mut self, _hold: bool) {}
+}
+
+// G-code interpreter for the subset produced by gcode::to_gcode and raster::to_raster
+#[derive(Debug, Clone)]
+pub struct Interpreter {
+  pub x: f32,
+  pub y: f32,
+  pub z: f32,
+  pub pen: PenDirection,
+  pub absolute: bool,
+  // size of one command unit (mm), used to translate blocks back into commands
+  pub unit_size: f32,
+  pub envelope: Option<Envelope>,
+  alarm: Option<u8>,
+  // spindle (or laser) state, the power is unknown until an S word is received
+  spindle: bool,
+  power: Option<f32>,
+  // moves follow a clockwise (G2) or counterclockwise (G3) arc when set, straight lines otherwise
+  arc: Option<bool>,
+  // commands equivalent to the blocks executed so far, not yet taken
+  commands: Vec<Command>,
+}
+
+impl Interpreter {
+  pub fn new(unit_size: f32, envelope: Option<Envelope>) -> Interpreter {
+    Interpreter {
+      x: 0.0,
+      y: 0.0,
+      z: 0.0,
+      pen: PenDirection::UP,
+      absolute: true,
+      unit_size,
+      envelope,
+      alarm: None,
+      spindle: false,
+      power: None,
+      arc: None,
+      commands: vec![],
+    }
+  }
+
+  // commands produced since the last call
+  pub fn take_commands(&mut self) -> Vec<Command> {
+    std::mem::take(&mut self.commands)
+  }
+
+  fn set_pen(&mut self, pen: PenDirection) {
+    if self.pen != pen {
+      self.pen = pen;
+      self.commands.push(Command::Pen(pen));
+    }
+  }
+}
+
+// splits a block into (letter, value) words
+fn words(block: &str) -> Result<Vec<(char, f32)>, u8> {
+  let block = block.to_ascii_uppercase().replace(' ', "");
+  let mut words = vec![];
+  let mut chars = block.chars().peekable();
+  while let Some(letter) = chars.next() {
+    if !letter.is_ascii_alphabetic() {
+      return Err(1); // expected command letter
+    }
+    let mut number = String::new();
+    while let Some(&c) = chars.peek() {
+      if c.is_ascii_digit() || c == '.' || c == '-' || c == '+' {
+        number.push(c);
+        chars.next();
+      }
+      else {
+        break;
+      }
+    }
+    let value = number.parse::<f32>().map_err(|_| 2u8)?; // bad number format
+    words.push((letter, value));
+  }
+  Ok(words)
+}
+
+impl Controller for Interpreter {
+  fn execute(&mut self, block: &str) -> Result<(), u8> {
+    if self.alarm.is_some() {
+      return Err(9); // G-code locked out during alarm
+    }
+
+    let words = words(&strip_comment(block))?;
+    let (mut x, mut y, mut z) = (None, None, None);
+    let (mut i, mut j) = (0.0, 0.0);
+    let mut spindle = None;
+    let mut power = None;
+    for &(letter, value) in &words {
+      match letter {
+        'G' => match value as i32 {
+          0 | 1 => self.arc = None,
+          2 => self.arc = Some(true),
+          3 => self.arc = Some(false),
+          4 | 21 => {},
+          90 => self.absolute = true,
+          91 => self.absolute = false,
+          _ => return Err(20), // unsupported command
+        },
+        'M' => match value as i32 {
+          3 | 4 => spindle = Some(true),
+          5 => spindle = Some(false),
+          2 | 30 => spindle = Some(false),
+          0 | 1 => {}, // program pause, nothing to wait for
+          _ => return Err(20),
+        },
+        'X' => x = Some(value),
+        'Y' => y = Some(value),
+        'Z' => z = Some(value),
+        'I' => i = value,
+        'J' => j = value,
+        'S' => power = Some(value),
+        'F' | 'P' => {},
+        _ => return Err(20),
+      }
+    }
+
+    // a laser or servo pen is down while the spindle runs with some power
+    // lasers change the power along a raster without switching the spindle, so S words count too
+    if spindle.is_some() || power.is_some() {
+      self.spindle = spindle.unwrap_or(self.spindle);
+      self.power = power.or(self.power);
+      // a spindle started with the tool raised above the material does not cut yet
+      let down = self.spindle && self.power.map(|s| s > 0.0).unwrap_or(true) && self.z <= 0.0;
+      self.set_pen(if down { PenDirection::DOWN } else { PenDirection::UP });
+    }
+
+    if x.is_some() || y.is_some() || z.is_some() {
+      let (mut target_x, mut target_y, mut target_z) = (self.x, self.y, self.z);
+      if self.absolute {
+        target_x = x.unwrap_or(target_x);
+        target_y = y.unwrap_or(target_y);
+        target_z = z.unwrap_or(target_z);
+      }
+      else {
+        target_x += x.unwrap_or(0.0);
+        target_y += y.unwrap_or(0.0);
+        target_z += z.unwrap_or(0.0);
+      }
+
+      if let Some(envelope) = self.envelope {
+        if !envelope.contains(target_x, target_y) {
+          self.alarm = Some(2); // soft limit
+          return Ok(());
+        }
+      }
+
+      if z.is_some() {
+        // pen plotters without a servo use the Z axis, anything below 0 draws
+        self.set_pen(if target_z < 0.0 { PenDirection::DOWN } else { PenDirection::UP });
+      }
+      let (x, y) = (target_x / self.unit_size, target_y / self.unit_size);
+      let moved = target_x != self.x || target_y != self.y;
+      if z.is_some() && target_z < 0.0 && target_z != self.z {
+        // the tool goes deeper or back up within the material
+        self.commands.push(if moved { Command::Ramp(x, y, target_z) } else { Command::Depth(target_z) });
+      }
+      else if let (true, Some(clockwise)) = (moved, self.arc) {
+        // arcs are flattened into moves, their center is given relative to the start
+        let mut points = vec![];
+        vector::arc_between((self.x, self.y), (target_x, target_y), (self.x + i, self.y + j), clockwise, ARC_TOLERANCE, &mut points);
+        points.pop();
+        for (px, py) in points {
+          self.commands.push(Command::Move(px / self.unit_size, py / self.unit_size));
+        }
+        self.commands.push(Command::Move(x, y));
+      }
+      else if moved {
+        self.commands.push(Command::Move(x, y));
+      }
+      self.x = target_x;
+      self.y = target_y;
+      self.z = target_z;
+    }
+    Ok(())
+  }
+
+  fn position(&self) -> (f32, f32, f32) {
+    (self.x, self.y, self.z)
+  }
+
+  fn alarm(&self) -> Option<u8> {
+    self.alarm
+  }
+
+  fn unlock(&mut self) {
+    self.alarm = None;
+  }
+
+  fn reset(&mut self) {
+    // position is kept, just like on a real machine
+    self.pen = PenDirection::UP;
+    self.absolute = true;
+    self.spindle = false;
+    self.commands.push(Command::Pen(PenDirection::UP));
+  }
+}
+
+// serial side of a virtual GRBL device
+pub struct Emulator<C: Controller> {
+  pub controller: C,
+  line: Vec<u8>,
+  // complete lines waiting for execution, held back during a feed hold
+  pending: VecDeque<String>,
+  hold: bool,
+  reported_alarm: bool,
+}
+
+impl<C: Controller> Emulator<C> {
+  pub fn new(controller: C) -> Emulator<C> {
+    Emulator {
+      controller,
+      line: vec![],
+      pending: VecDeque::new(),
+      hold: false,
+      reported_alarm: false,
+    }
+  }
+
+  pub fn welcome() -> &'static str {
+    WELCOME
+  }
+
+  pub fn status(&self) -> String {
+    let state = if self.controller.alarm().is_some() {
+      "Alarm"
+    }
+    else if self.hold {
+      "Hold:0"
+    }
+    else if !self.pending.is_empty() {
+      "Run"
+    }
+    else {
+      "Idle"
+    };
+    let (x, y, z) = self.controller.position();
+    format!("<{}|MPos:{:.3},{:.3},{:.3}|FS:0,0>", state, x, y, z)
+  }
+
+  // handles received bytes, returns the responses to be written back
+  pub fn receive(&mut self, bytes: &[u8]) -> Vec<String> {
+    let mut responses = vec![];
+    for &byte in bytes {
+      match byte {
+        STATUS_REPORT => responses.push(self.status()),
+        FEED_HOLD => {
+          self.hold = true;
+          self.controller.feed_hold(true);
+        },
+        CYCLE_START => {
+          self.hold = false;
+          self.controller.feed_hold(false);
+        },
+        SOFT_RESET => {
+          self.line.clear();
+          self.pending.clear();
+          self.hold = false;
+          self.controller.reset();
+          responses.push(String::new());
+          responses.push(WELCOME.to_string());
+        },
+        b'\n' | b'\r' => {
+          if !self.line.is_empty() {
+            self.pending.push_back(String::from_utf8_lossy(&self.line).to_string());
+            self.line.clear();
+          }
+        },
+        _ => self.line.push(byte),
+      }
+    }
+    responses.extend(self.process());
+    responses
+  }
+
+  // executes pending lines unless a feed hold is active
+  pub fn process(&mut self) -> Vec<String> {
+    let mut responses = vec![];
+    while !self.hold {
+      let line = match self.pending.pop_front() {
+        Some(line) => line,
+        None => break,
+      };
+      let line = line.trim();
+
+      if line.starts_with('$') {
+        if line == "$X" {
+          self.controller.unlock();
+          self.reported_alarm = false;
+          responses.push("[MSG:Caution: Unlocked]".to_string());
+        }
+        responses.push("ok".to_string());
+        continue;
+      }
+
+      match self.controller.execute(line) {
+        Ok(()) => responses.push("ok".to_string()),
+        Err(code) => responses.push(format!("error:{}", code)),
+      }
+      if let Some(alarm) = self.controller.alarm() {
+        if !self.reported_alarm {
+          self.reported_alarm = true;
+          responses.push(format!("ALARM:{}", alarm));
+        }
+      }
+    }
+    responses
+  }
+
+  // answers everything received on `port` until it is closed
+  pub fn serve<P: Read + Write>(&mut self, port: &mut P) -> io::Result<()> {
+    port.write_all(format!("\r\n{}\r\n", WELCOME).as_bytes())?;
+    let mut buffer = [0u8; RX_BUFFER_SIZE];
+    loop {
+      let read = port.read(&mut buffer)?;
+      if read == 0 {
+        return Ok(());
+      }
+      for response in self.receive(&buffer[..read]) {
+        port.write_all(format!("{}\r\n", response).as_bytes())?;
+      }
+      port.flush()?;
+    }
+  }
+}
+
+// creates a pseudo-terminal pair, returning the master side, the (raw mode) slave side and the slave path
+// the slave side has to be kept open, otherwise reading the master fails once the sender disconnects
+#[cfg(unix)]
+pub fn open_pty() -> io::Result<(File, File, PathBuf)> {
+  use std::ffi::CStr;
+  use std::os::unix::io::{AsRawFd, FromRawFd};
+
+  unsafe {
+    let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+    if master < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    let master_file = File::from_raw_fd(master);
+    if libc::grantpt(master) != 0 || libc::unlockpt(master) != 0 {
+      return Err(io::Error::last_os_error());
+    }
+    let name = libc::ptsname(master);
+    if name.is_null() {
+      return Err(io::Error::last_os_error());
+    }
+    let path = PathBuf::from(CStr::from_ptr(name).to_string_lossy().into_owned());
+
+    let slave = OpenOptions::new().read(true).write(true).open(&path)?;
+    let mut termios: libc::termios = std::mem::zeroed();
+    if libc::tcgetattr(slave.as_raw_fd(), &mut termios) == 0 {
+      libc::cfmakeraw(&mut termios);
+      libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios);
+    }
+
+    Ok((master_file, slave, path))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::gcode::{self, GcodeOptions};
+  use crate::machine::MachineModel;
+
+  fn run(interpreter: &mut Interpreter, gcode: &str) -> Vec<Command> {
+    for line in gcode.lines() {
+      interpreter.execute(line).unwrap();
+    }
+    interpreter.take_commands()
+  }
+
+  #[test]
+  fn responses() {
+    assert_eq!(Response::parse("ok\r"), Response::Ok);
+    assert_eq!(Response::parse("error:20"), Response::Error("20".to_string()));
+    assert_eq!(Response::parse("ALARM:2"), Response::Alarm("2".to_string()));
+    assert_eq!(Response::parse("<Idle|MPos:0.000,0.000,0.000|FS:0,0>"), Response::Message("<Idle|MPos:0.000,0.000,0.000|FS:0,0>".to_string()));
+  }
+
+  #[test]
+  fn comments_and_words() {
+    assert_eq!(strip_comment("G1 X1 (to the right) Y2 ; done"), "G1 X1  Y2");
+    assert_eq!(strip_comment("(only a comment)"), "");
+    assert_eq!(words("g1 x-1.5 Y+2").unwrap(), vec![('G', 1.0), ('X', -1.5), ('Y', 2.0)]);
+    assert_eq!(words("1X"), Err(1));
+    assert_eq!(words("X1..2"), Err(2));
+    let mut interpreter = Interpreter::new(1.0, None);
+    assert_eq!(interpreter.execute("G38.2 X1"), Err(20));
+    assert_eq!(interpreter.execute("T1"), Err(20));
+  }
+
+  #[test]
+  fn generated_gcode_reads_back() {
+    let machine = MachineModel { unit_size: 0.5, ..MachineModel::default() };
+    let commands = vec![
+      Command::Move(10.0, 20.0),
+      Command::Pen(PenDirection::DOWN),
+      Command::Move(30.0, 20.0),
+      Command::Move(30.0, 40.0),
+      Command::Pen(PenDirection::UP),
+      Command::Move(0.0, 0.0),
+    ];
+    let gcode = gcode::to_gcode(&commands, &machine, &GcodeOptions::default());
+    assert_eq!(run(&mut Interpreter::new(0.5, None), &gcode), commands);
+
+    // with the Z axis as the pen, depths come back too
+    let commands = vec![
+      Command::Move(10.0, 0.0),
+      Command::Depth(-1.0),
+      Command::Pen(PenDirection::DOWN),
+      Command::Move(20.0, 0.0),
+      Command::Ramp(20.0, 10.0, -2.0),
+      Command::Pen(PenDirection::UP),
+    ];
+    let options = GcodeOptions { safe_z: Some(5.0), ..GcodeOptions::default() };
+    let gcode = gcode::to_gcode(&commands, &MachineModel::default(), &options);
+    assert_eq!(
+      run(&mut Interpreter::new(1.0, None), &gcode),
+      vec![
+        Command::Move(10.0, 0.0),
+        Command::Pen(PenDirection::DOWN),
+        Command::Depth(-1.0),
+        Command::Move(20.0, 0.0),
+        Command::Ramp(20.0, 10.0, -2.0),
+        Command::Pen(PenDirection::UP),
+      ]
+    );
+  }
+
+  #[test]
+  fn arcs_are_flattened() {
+    let mut interpreter = Interpreter::new(1.0, None);
+    let commands = run(&mut interpreter, "G0 X10 Y0\nM3 S1000\nG3 X-10 Y0 I-10 J0\nX-20");
+    assert_eq!(commands[..2], [Command::Move(10.0, 0.0), Command::Pen(PenDirection::DOWN)]);
+    let arc = &commands[2..commands.len() - 1];
+    assert!(arc.len() > 10);
+    for command in arc {
+      let Command::Move(x, y) = *command else { panic!("{:?}", command) };
+      assert!(((x * x + y * y).sqrt() - 10.0).abs() < ARC_TOLERANCE && y >= -1e-4);
+    }
+    assert_eq!(arc[arc.len() - 1], Command::Move(-10.0, 0.0));
+    // G0 and G1 end the arc mode, the modal motion goes on in a straight line otherwise
+    assert_eq!(commands[commands.len() - 1], Command::Move(-20.0, 0.0));
+    assert_eq!(run(&mut interpreter, "G1 X-30"), vec![Command::Move(-30.0, 0.0)]);
+  }
+
+  #[test]
+  fn emulated_protocol() {
+    let mut emulator = Emulator::new(Interpreter::new(1.0, None));
+    // status reports are answered right away, before the pending lines
+    assert_eq!(emulator.receive(b"G1 X5\n?"), vec!["<Run|MPos:0.000,0.000,0.000|FS:0,0>", "ok"]);
+    assert_eq!(emulator.receive(b"?"), vec!["<Idle|MPos:5.000,0.000,0.000|FS:0,0>"]);
+
+    // feed hold
+    assert!(emulator.receive(b"!G0 Y1\n").is_empty());
+    assert_eq!(emulator.status(), "<Hold:0|MPos:5.000,0.000,0.000|FS:0,0>");
+    assert_eq!(emulator.receive(b"~"), vec!["ok"]);
+
+    // lines split across reads
+    assert!(emulator.receive(b"G0 X").is_empty());
+    assert_eq!(emulator.receive(b"7\r\n$$\n"), vec!["ok", "ok"]);
+    assert_eq!(emulator.controller.position(), (7.0, 1.0, 0.0));
+
+    assert_eq!(emulator.receive(&[SOFT_RESET]), vec!["", WELCOME]);
+  }
+
+  #[test]
+  fn soft_limits() {
+    let envelope = Envelope { min_x: 0.0, min_y: 0.0, max_x: 100.0, max_y: 100.0 };
+    let mut emulator = Emulator::new(Interpreter::new(1.0, Some(envelope)));
+    assert_eq!(emulator.receive(b"G0 X200\n"), vec!["ok", "ALARM:2"]);
+    assert_eq!(emulator.receive(b"G0 X1\n"), vec!["error:9"]);
+    assert_eq!(emulator.status(), "<Alarm|MPos:0.000,0.000,0.000|FS:0,0>");
+    assert_eq!(emulator.receive(b"$X\nG0 X1\n"), vec!["[MSG:Caution: Unlocked]", "ok", "ok"]);
+    assert_eq!(emulator.controller.take_commands(), vec![Command::Move(1.0, 0.0)]);
+  }
+
+  // forwards the commands of each executed block
+  struct Recorder {
+    interpreter: Interpreter,
+    commands: mpsc::Sender<Vec<Command>>,
+  }
+
+  impl Controller for Recorder {
+    fn execute(&mut self, block: &str) -> Result<(), u8> {
+      let result = self.interpreter.execute(block);
+      self.commands.send(self.interpreter.take_commands()).unwrap();
+      result
+    }
+
+    fn position(&self) -> (f32, f32, f32) {
+      self.interpreter.position()
+    }
+  }
+
+  // streams `gcode` to an emulator behind a pseudo-terminal, returning the commands it executed
+  fn stream(gcode: &str, protocol: Protocol) -> (Result<(), StreamError>, Vec<Command>) {
+    let (mut master, _slave, path) = open_pty().unwrap();
+    let (commands, received) = mpsc::channel();
+    thread::spawn(move || {
+      let mut emulator = Emulator::new(Recorder { interpreter: Interpreter::new(1.0, None), commands });
+      emulator.serve(&mut master)
+    });
+    let (_controls, control_receiver) = mpsc::channel();
+    let mut sender = Sender::new(open_port(&path, 115200).unwrap(), protocol, control_receiver).unwrap();
+    let result = sender.stream(gcode);
+    (result, received.try_iter().flatten().collect())
+  }
+
+  #[test]
+  fn streamed_programs() {
+    let commands = (1..=100).map(|i| Command::Move(i as f32, (i % 7) as f32)).collect::<Vec<Command>>();
+    let gcode = gcode::to_gcode(&commands, &MachineModel::default(), &GcodeOptions::default());
+    for protocol in [Protocol::CharacterCounting, Protocol::SendResponse] {
+      let (result, executed) = stream(&gcode, protocol);
+      assert!(result.is_ok());
+      assert_eq!(executed, commands);
+    }
+  }
+
+  #[test]
+  fn rejected_blocks() {
+    let (result, _) = stream("G0 X1\n\n(comment)\nG38.2 X2\nG0 X3", Protocol::SendResponse);
+    assert!(matches!(result, Err(StreamError::Rejected(4, code)) if code == "20"));
+    // the controller could never hold the block
+    let long = format!("G0 X1\nG1 X2 ({})\nG1 {}", "c".repeat(200), "X1 ".repeat(50));
+    let (result, _) = stream(&long, Protocol::CharacterCounting);
+    assert!(matches!(result, Err(StreamError::TooLong(3))));
+  }
+}
@@ -0,0 +1,224 @@
+// Re-import of previous exports: the paths of a points JSON or of a command file, so that ordering, leads, tabs,
+// depth passes and the writers can run again with other settings, without tracing the image again
+use crate::canny::{PenDirection, SerializebleComputation};
+use crate::command::{self, Command};
+use crate::fill::{Point, Polyline};
+use crate::vector::Drawing;
+
+// the middle one of three points lies on the straight segment between the others
+fn straight(a: Point, b: Point, c: Point) -> bool {
+  let (u, v) = ((b.0 - a.0, b.1 - a.1), (c.0 - b.0, c.1 - b.1));
+  let (cross, dot) = (u.0 * v.1 - u.1 * v.0, u.0 * v.0 + u.1 * v.1);
+  dot > 0.0 && cross.abs() <= 1e-4 * (u.0.hypot(u.1) * v.0.hypot(v.1))
+}
+
+// the edge as it was before its leads and tabs were added: the leads are left out, and so are the vertices
+// added at the ends of the tabs, the segments crossing the tabs are cut again
+fn untabbed(mut points: Polyline, tabs: &[usize], leads: (usize, usize)) -> Polyline {
+  let (lead_in, lead_out) = leads;
+  if lead_in + lead_out >= points.len() {
+    return points;
+  }
+  points.truncate(points.len() - lead_out);
+  points.drain(..lead_in);
+
+  // tab segments are numbered along the whole edge
+  let tab = |segment: usize| tabs.contains(&(segment + lead_in));
+  (0..points.len())
+    .filter(|&index| {
+      let end = index > 0 && index + 1 < points.len() && tab(index - 1) != tab(index);
+      !(end && straight(points[index - 1], points[index], points[index + 1]))
+    })
+    .map(|index| points[index])
+    .collect()
+}
+
+// the edges of a `<name>_points.json`, on the canvas of the image they were traced from
+pub fn points(text: &str) -> Result<Drawing, String> {
+  let computation: SerializebleComputation<f32> = serde_json::from_str(text).map_err(|e| format!("Invalid points file: {}", e))?;
+  let mut paths = vec![];
+  for (index, edge) in computation.edges.into_iter().enumerate() {
+    let points = edge.into_iter().map(|p| (p.x, p.y)).collect::<Polyline>();
+    let tabs = computation.tabs.get(index).map(|tabs| tabs.as_slice()).unwrap_or(&[]);
+    let leads = computation.leads.get(index).copied().unwrap_or((0, 0));
+    paths.push(untabbed(points, tabs, leads));
+  }
+  Ok(Drawing { width: computation.width as f32, height: computation.height as f32, paths, circles: vec![] })
+}
+
+// the paths drawn with a pen of a command file, along with its color when the file tells it
+pub type Pen = (Option<[u8; 3]>, Drawing);
+
+// ends the current path, kept when it has a segment
+fn finish(paths: &mut Vec<Polyline>, current: &mut Polyline) {
+  if current.len() > 1 {
+    paths.push(std::mem::take(current));
+  }
+  current.clear();
+}
+
+// the pen down runs of a `<name>_command.txt`, in the order of the file, grouped by pen: a tool change starts a new group,
+// given with the color of its pen when the file tells it
+// tabs are cut like the rest of the runs, and only the last pass of a multi pass job is read, at the deepest depth of the file
+pub fn commands(text: &str) -> Result<Vec<Pen>, String> {
+  let commands = command::parse_commands(text);
+  if commands.is_empty() {
+    return Err("No commands found".to_string());
+  }
+  let deepest = commands
+    .iter()
+    .filter_map(|command| match *command {
+      Command::Depth(z) | Command::Ramp(_, _, z) => Some(z),
+      _ => None,
+    })
+    .fold(0.0, f32::min);
+
+  // the paths of the previous pens, and those of the current one
+  let mut pens: Vec<(Option<[u8; 3]>, Vec<Polyline>)> = vec![];
+  let (mut color, mut paths) = (None, vec![]);
+  let mut current: Polyline = vec![];
+  let mut position = (0.0, 0.0);
+  let mut down = false;
+  let mut depth = 0.0;
+  for command in commands {
+    match command {
+      Command::Pen(direction) => down = direction == PenDirection::DOWN,
+      Command::Move(x, y) | Command::Tab(x, y) => {
+        let from = std::mem::replace(&mut position, (x, y));
+        if !down || depth > deepest || from == position {
+          continue;
+        }
+        if current.last() != Some(&from) {
+          finish(&mut paths, &mut current);
+          current.push(from);
+        }
+        current.push(position);
+      },
+      // the last ramp is cut again at full depth once the run is done
+      Command::Ramp(x, y, z) => {
+        position = (x, y);
+        depth = z;
+      },
+      Command::Depth(z) => depth = z,
+      Command::Tool(_, next) => {
+        finish(&mut paths, &mut current);
+        pens.push((std::mem::replace(&mut color, next), std::mem::take(&mut paths)));
+        down = false;
+      },
+      Command::Reset => {
+        down = false;
+        position = (0.0, 0.0);
+      },
+      Command::End => break,
+      Command::Dwell(_) => {},
+    }
+  }
+  finish(&mut paths, &mut current);
+  pens.push((color, paths));
+
+  // every pen is drawn on the canvas of the whole file
+  let (width, height) = pens
+    .iter()
+    .flat_map(|(_, paths)| paths.iter().flatten())
+    .fold((0.0f32, 0.0f32), |(w, h), p| (w.max(p.0), h.max(p.1)));
+  let mut pens = pens
+    .into_iter()
+    .filter(|(_, paths)| !paths.is_empty())
+    .map(|(color, paths)| (color, Drawing { width, height, paths, circles: vec![] }))
+    .collect::<Vec<_>>();
+  if pens.is_empty() {
+    pens.push((None, Drawing::fitted(vec![])));
+  }
+  Ok(pens)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::canny::{self, SPixelIndex};
+  use crate::depth::{self, DepthOptions};
+
+  fn points_file(edges: Vec<Polyline>, tabs: Vec<Vec<usize>>, leads: Vec<(usize, usize)>) -> String {
+    let computation = SerializebleComputation::<f32> {
+      edges: edges.into_iter().map(|edge| edge.into_iter().map(|(x, y)| SPixelIndex { x, y }).collect()).collect(),
+      tabs,
+      leads,
+      ..canny::empty_computation(40, 30, 1.0)
+    };
+    serde_json::to_string(&computation).unwrap()
+  }
+
+  fn square() -> Polyline {
+    vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)]
+  }
+
+  #[test]
+  fn points_without_leads_and_tabs() {
+    // a lead-in point, the square with a tab from (4, 0) to (6, 0), and two lead-out points
+    let cut = vec![(-2.0, -2.0), (0.0, 0.0), (4.0, 0.0), (6.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0), (1.0, -1.0), (2.0, -2.0)];
+    let text = points_file(vec![cut, vec![(20.0, 20.0), (30.0, 20.0)]], vec![vec![2], vec![]], vec![(1, 2), (0, 0)]);
+    let drawing = points(&text).unwrap();
+    assert_eq!(drawing.paths, vec![square(), vec![(20.0, 20.0), (30.0, 20.0)]]);
+    assert_eq!((drawing.width, drawing.height), (40.0, 30.0));
+  }
+
+  #[test]
+  fn tabs_across_corners() {
+    // the tab goes around the corner at (10, 0), which is kept
+    let cut = vec![(0.0, 0.0), (9.0, 0.0), (10.0, 0.0), (10.0, 1.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)];
+    let drawing = points(&points_file(vec![cut], vec![vec![1, 2]], vec![])).unwrap();
+    assert_eq!(drawing.paths, vec![square()]);
+  }
+
+  #[test]
+  fn points_of_older_files() {
+    // files written before the leads were saved, tabs are optional too
+    let text = r#"{"edges":[[{"x":1.0,"y":2.0},{"x":3.0,"y":4.0}]],"width":5,"height":6,"dx_skip":1,"dy_skip":1,"px_skip":2}"#;
+    let drawing = points(text).unwrap();
+    assert_eq!(drawing.paths, vec![vec![(1.0, 2.0), (3.0, 4.0)]]);
+    assert!(points("{}").is_err());
+  }
+
+  #[test]
+  fn retraced_segments_and_tool_changes() {
+    let text = "PEN UP\nRESET\nMOVE 0 0\nPEN DOWN\nMOVE 10 0\nMOVE 0 0\nMOVE 0 10\nPEN UP\nTOOL 2 #ff0000\nMOVE 5 5\nPEN DOWN\nMOVE 10 0\nPEN UP\nRESET\nEND";
+    let pens = commands(text).unwrap();
+    assert_eq!(pens.len(), 2);
+    assert_eq!(pens[0].0, None);
+    assert_eq!(pens[0].1.paths, vec![vec![(0.0, 0.0), (10.0, 0.0), (0.0, 0.0), (0.0, 10.0)]]);
+    assert_eq!(pens[1].0, Some([255, 0, 0]));
+    assert_eq!(pens[1].1.paths, vec![vec![(5.0, 5.0), (10.0, 0.0)]]);
+    // the pens share the canvas of the whole file
+    assert_eq!((pens[1].1.width, pens[1].1.height), (10.0, 10.0));
+  }
+
+  #[test]
+  fn travels_and_tabs() {
+    // the tab is cut like the rest, the travel is not
+    let text = "MOVE 0 0\nPEN DOWN\nMOVE 4 0\nTAB 6 0\nMOVE 10 0\nPEN UP\nMOVE 20 20\nPEN DOWN\nMOVE 20 30\nPEN UP\nEND";
+    let pens = commands(text).unwrap();
+    assert_eq!(pens.len(), 1);
+    assert_eq!(pens[0].1.paths, vec![vec![(0.0, 0.0), (4.0, 0.0), (6.0, 0.0), (10.0, 0.0)], vec![(20.0, 20.0), (20.0, 30.0)]]);
+    assert!(commands("").is_err());
+  }
+
+  #[test]
+  fn deepest_pass() {
+    let job = "MOVE 0 0\nPEN DOWN\nMOVE 10 0\nMOVE 10 10\nMOVE 0 10\nMOVE 0 0\nPEN UP\nMOVE 20 0\nPEN DOWN\nMOVE 30 0\nPEN UP\nRESET\nEND";
+    let single = commands(job).unwrap()[0].1.paths.clone();
+    assert_eq!(single.len(), 2);
+
+    // plunged passes go over the same paths
+    let passes = |options: &DepthOptions| {
+      depth::passes(&command::parse_commands(job), options, 1.0).iter().map(|c| c.to_string()).collect::<Vec<_>>().join("\n")
+    };
+    let plunged = commands(&passes(&DepthOptions::default())).unwrap();
+    assert_eq!(plunged[0].1.paths, single);
+
+    // the square ramps down over its first 4 units, which are cut again at the end of the last pass
+    let ramped = commands(&passes(&DepthOptions { ramp: Some(4.0), ..DepthOptions::default() })).unwrap();
+    let paths = &ramped[0].1.paths;
+    assert_eq!(paths[0], vec![(4.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0), (4.0, 0.0)]);
+    assert_eq!(paths[1], single[1]);
+  }
+}
@@ -0,0 +1,186 @@
+// Job files: a cutting trajectory given as a sequence of line segments and arcs of circle
+//
+// one instruction per line, coordinates in command units, `#` starts a comment
+//   START x y              starts a new path at (x, y)
+//   LINE x y               straight segment to (x, y)
+//   ARC x y cx cy [CW|CCW] arc to (x, y) around (cx, cy), counterclockwise unless CW is given
+//   CIRCLE cx cy r         whole circle, as a path of its own starting on its right
+use crate::fill::{Point, Polyline};
+use crate::vector::{self, Drawing};
+
+pub const START_CMD: &str = "START";
+pub const LINE_CMD: &str = "LINE";
+pub const ARC_CMD: &str = "ARC";
+pub const CIRCLE_CMD: &str = "CIRCLE";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Primitive {
+  Start(f32, f32),
+  Line(f32, f32),
+  // end, center and direction (clockwise or not)
+  Arc(f32, f32, f32, f32, bool),
+  // center and radius
+  Circle(f32, f32, f32),
+}
+
+impl Primitive {
+  // returns None for empty lines and comments, an error for anything else that is not an instruction
+  pub fn parse(line: &str) -> Result<Option<Primitive>, String> {
+    let line = line.split('#').next().unwrap_or("").trim();
+    let mut words = line.split_whitespace();
+    let name = match words.next() {
+      Some(name) => name.to_ascii_uppercase(),
+      None => return Ok(None),
+    };
+    let words = words.collect::<Vec<_>>();
+    let numbers = words
+      .iter()
+      .map_while(|word| word.parse::<f32>().ok())
+      .collect::<Vec<_>>();
+    let rest = &words[numbers.len()..];
+
+    let primitive = match (name.as_str(), numbers.as_slice(), rest) {
+      (START_CMD, &[x, y], []) => Primitive::Start(x, y),
+      (LINE_CMD, &[x, y], []) => Primitive::Line(x, y),
+      (ARC_CMD, &[x, y, cx, cy], []) => Primitive::Arc(x, y, cx, cy, false),
+      (ARC_CMD, &[x, y, cx, cy], [direction]) if direction.eq_ignore_ascii_case("CCW") => Primitive::Arc(x, y, cx, cy, false),
+      (ARC_CMD, &[x, y, cx, cy], [direction]) if direction.eq_ignore_ascii_case("CW") => Primitive::Arc(x, y, cx, cy, true),
+      (CIRCLE_CMD, &[cx, cy, r], []) if r > 0.0 => Primitive::Circle(cx, cy, r),
+      (START_CMD | LINE_CMD | ARC_CMD | CIRCLE_CMD, _, _) => return Err(format!("Invalid {} instruction", name)),
+      _ => return Err(format!("Unknown instruction {}", name)),
+    };
+    Ok(Some(primitive))
+  }
+}
+
+// paths of the trajectory, in the order of the file, arcs are flattened so they stray at most `tolerance` from the circles
+// the circles are kept along the paths, so that G-code follows them with native arcs
+// a path without START begins where the previous one ended, at the origin for the first one
+pub fn parse(text: &str, tolerance: f32) -> Result<Drawing, String> {
+  let mut paths: Vec<Polyline> = vec![];
+  let mut circles = vec![];
+  let mut current: Polyline = vec![];
+  let mut position: Point = (0.0, 0.0);
+
+  for (index, line) in text.lines().enumerate() {
+    let primitive = match Primitive::parse(line) {
+      Ok(Some(primitive)) => primitive,
+      Ok(None) => continue,
+      Err(e) => return Err(format!("{} on line {}", e, index + 1)),
+    };
+    match primitive {
+      Primitive::Start(x, y) => {
+        if current.len() > 1 {
+          paths.push(std::mem::take(&mut current));
+        }
+        position = (x, y);
+        current = vec![position];
+      },
+      Primitive::Line(x, y) => {
+        if current.is_empty() {
+          current.push(position);
+        }
+        position = (x, y);
+        current.push(position);
+      },
+      Primitive::Arc(x, y, cx, cy, clockwise) => {
+        if current.is_empty() {
+          current.push(position);
+        }
+        let start = ((position.0 - cx).powi(2) + (position.1 - cy).powi(2)).sqrt();
+        let end = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
+        // the same check as GRBL, which refuses arcs whose ends are not on the same circle
+        if (start - end).abs() > tolerance.max(0.001 * start) {
+          return Err(format!("The end of the arc on line {} is not on its circle", index + 1));
+        }
+        vector::arc_between(position, (x, y), (cx, cy), clockwise, tolerance, &mut current);
+        circles.push(((cx, cy), start));
+        position = (x, y);
+      },
+      Primitive::Circle(cx, cy, r) => {
+        if current.len() > 1 {
+          paths.push(std::mem::take(&mut current));
+        }
+        current.clear();
+        paths.push(vector::ellipse((cx, cy), (r, r), tolerance));
+        circles.push(((cx, cy), r));
+        position = (cx + r, cy);
+      },
+    }
+  }
+  if current.len() > 1 {
+    paths.push(current);
+  }
+  Ok(Drawing { circles, ..Drawing::fitted(paths) })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn on_circle(points: &[Point], center: Point, radius: f32) -> bool {
+    points.iter().all(|p| (((p.0 - center.0).powi(2) + (p.1 - center.1).powi(2)).sqrt() - radius).abs() < 1e-3)
+  }
+
+  #[test]
+  fn instructions() {
+    assert_eq!(Primitive::parse("  # a comment"), Ok(None));
+    assert_eq!(Primitive::parse(""), Ok(None));
+    assert_eq!(Primitive::parse("start 1 2 # origin"), Ok(Some(Primitive::Start(1.0, 2.0))));
+    assert_eq!(Primitive::parse("LINE -1.5 2"), Ok(Some(Primitive::Line(-1.5, 2.0))));
+    assert_eq!(Primitive::parse("ARC 1 2 3 4"), Ok(Some(Primitive::Arc(1.0, 2.0, 3.0, 4.0, false))));
+    assert_eq!(Primitive::parse("ARC 1 2 3 4 cw"), Ok(Some(Primitive::Arc(1.0, 2.0, 3.0, 4.0, true))));
+    assert_eq!(Primitive::parse("CIRCLE 1 2 3"), Ok(Some(Primitive::Circle(1.0, 2.0, 3.0))));
+
+    assert_eq!(Primitive::parse("LINE 1"), Err("Invalid LINE instruction".to_string()));
+    assert_eq!(Primitive::parse("ARC 1 2 3 4 UP"), Err("Invalid ARC instruction".to_string()));
+    assert_eq!(Primitive::parse("CIRCLE 1 2 0"), Err("Invalid CIRCLE instruction".to_string()));
+    assert_eq!(Primitive::parse("JUMP 1 2"), Err("Unknown instruction JUMP".to_string()));
+  }
+
+  #[test]
+  fn paths_follow_the_file() {
+    let drawing = parse("LINE 10 0\nLINE 10 5\nSTART 20 20\nLINE 30 20\nSTART 0 0\n", 0.1).unwrap();
+    // the first path starts at the origin, the last START does not make a path
+    assert_eq!(drawing.paths, vec![vec![(0.0, 0.0), (10.0, 0.0), (10.0, 5.0)], vec![(20.0, 20.0), (30.0, 20.0)]]);
+    assert_eq!((drawing.width, drawing.height), (30.0, 20.0));
+    assert!(drawing.circles.is_empty());
+  }
+
+  #[test]
+  fn arcs() {
+    let drawing = parse("START 10 0\nARC 0 10 0 0\nLINE 0 20\nSTART 10 0\nARC 0 10 0 0 CW\n", 0.01).unwrap();
+    assert_eq!(drawing.circles, vec![((0.0, 0.0), 10.0), ((0.0, 0.0), 10.0)]);
+
+    // a quarter turn, then the line goes on from its end
+    let path = &drawing.paths[0];
+    assert_eq!(path[path.len() - 2], (0.0, 10.0));
+    assert_eq!(path[path.len() - 1], (0.0, 20.0));
+    assert!(on_circle(&path[..path.len() - 1], (0.0, 0.0), 10.0));
+    assert!(path.iter().all(|p| p.0 >= -1e-3 && p.1 >= -1e-3));
+
+    // three quarters the other way around
+    let path = &drawing.paths[1];
+    assert!(on_circle(path, (0.0, 0.0), 10.0));
+    assert!(path.iter().any(|p| p.0 < -9.9) && path.iter().any(|p| p.1 < -9.9));
+  }
+
+  #[test]
+  fn circles() {
+    let drawing = parse("START 0 0\nLINE 5 0\nCIRCLE 20 20 5\nLINE 30 20\n", 0.01).unwrap();
+    assert_eq!(drawing.circles, vec![((20.0, 20.0), 5.0)]);
+    assert_eq!(drawing.paths.len(), 3);
+    let circle = &drawing.paths[1];
+    assert_eq!(circle[0], (25.0, 20.0));
+    assert_eq!(circle[0], circle[circle.len() - 1]);
+    assert!(on_circle(circle, (20.0, 20.0), 5.0));
+    // the next path begins on the right of the circle
+    assert_eq!(drawing.paths[2], vec![(25.0, 20.0), (30.0, 20.0)]);
+  }
+
+  #[test]
+  fn errors_name_their_line() {
+    assert_eq!(parse("START 0 0\n\nLINE 1\n", 0.1).err().unwrap(), "Invalid LINE instruction on line 3");
+    assert_eq!(parse("START 10 0\nARC 0 12 0 0\n", 0.1).err().unwrap(), "The end of the arc on line 2 is not on its circle");
+  }
+}
@@ -0,0 +1,293 @@
+// Lead-in and lead-out moves, so thermal cuts pierce the sheet and stop on the scrap side of the contours
+use crate::contour;
+use crate::fill::{Point, Polyline};
+use crate::ordering::ContainmentTree;
+use crate::path::distance;
+use std::f32::consts::PI;
+
+// vertices where the contour turns by more than this are corners (radians)
+const CORNER_ANGLE: f32 = 0.5;
+// arcs are approximated by segments spanning at most this angle (radians)
+const ARC_STEP: f32 = PI / 16.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LeadShape {
+  Line,
+  // tangent to the contour
+  Arc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lead {
+  pub shape: LeadShape,
+  // length of the lead (mm)
+  pub length: f32,
+  // angle between a line and the contour, or angle spanned by an arc (degrees)
+  pub angle: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+  Top,
+  Bottom,
+  Left,
+  Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PierceRule {
+  // the corner closest to the head, where the mark of the pierce is the least visible
+  NearestCorner,
+  // the vertex furthest towards the given side of the image
+  Side(Side),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LeadOptions {
+  pub lead_in: Option<Lead>,
+  pub lead_out: Option<Lead>,
+  pub pierce: PierceRule,
+  // wait after piercing, before moving (s)
+  pub dwell: f32,
+}
+
+impl Default for LeadOptions {
+  fn default() -> Self {
+    LeadOptions {
+      lead_in: None,
+      lead_out: None,
+      pierce: PierceRule::NearestCorner,
+      dwell: 0.0,
+    }
+  }
+}
+
+fn unit(from: Point, to: Point) -> Point {
+  let length = distance(from, to).max(1e-6);
+  ((to.0 - from.0) / length, (to.1 - from.1) / length)
+}
+
+fn rotate(v: Point, angle: f32) -> Point {
+  let (sin, cos) = angle.sin_cos();
+  (v.0 * cos - v.1 * sin, v.0 * sin + v.1 * cos)
+}
+
+// signed turn at b, positive towards (dy, -dx)
+fn turn(a: Point, b: Point, c: Point) -> f32 {
+  let (d1, d2) = ((b.0 - a.0, b.1 - a.1), (c.0 - b.0, c.1 - b.1));
+  (d1.0 * d2.1 - d1.1 * d2.0).atan2(d1.0 * d2.0 + d1.1 * d2.1)
+}
+
+// vertex of a closed polygon where the cut starts
+// scrap is 1 when the scrap lies towards (dy, -dx) of the edges, -1 otherwise
+fn pierce_vertex(points: &[Point], scrap: f32, rule: PierceRule, head: Point) -> usize {
+  let n = points.len();
+  let nearest = |candidates: &mut dyn Iterator<Item = usize>| {
+    candidates
+      .map(|index| (index, distance(head, points[index])))
+      .fold(None, |best: Option<(usize, f32)>, current| match best {
+        Some(best) if best.1 <= current.1 => Some(best),
+        _ => Some(current),
+      })
+      .map(|(index, _)| index)
+  };
+
+  match rule {
+    PierceRule::NearestCorner => {
+      // corners pointing into the scrap, so the lead does not cross the part
+      let corner = |index: &usize| scrap * turn(points[(index + n - 1) % n], points[*index], points[(index + 1) % n]) > CORNER_ANGLE;
+      nearest(&mut (0..n).filter(corner)).or_else(|| nearest(&mut (0..n))).unwrap_or(0)
+    },
+    PierceRule::Side(side) => {
+      let key = |p: Point| match side {
+        Side::Top => p.1,
+        Side::Bottom => -p.1,
+        Side::Left => p.0,
+        Side::Right => -p.0,
+      };
+      let extreme = points.iter().map(|p| key(*p)).fold(f32::MAX, f32::min);
+      // vertices within half a pixel of the side are all as good
+      nearest(&mut (0..n).filter(|index| key(points[*index]) <= extreme + 0.5)).unwrap_or(0)
+    },
+  }
+}
+
+// points of a lead touching the contour at p, where the contour goes along `tangent` and the scrap lies towards `normal`
+// the points are ordered going away from p, which is not included
+fn lead(lead: &Lead, p: Point, tangent: Point, normal: Point, unit_size: f32) -> Polyline {
+  let length = lead.length / unit_size;
+  let angle = lead.angle.to_radians();
+  match lead.shape {
+    LeadShape::Line => {
+      let (cos, sin) = (angle.cos(), angle.sin());
+      vec![(p.0 + (tangent.0 * cos + normal.0 * sin) * length, p.1 + (tangent.1 * cos + normal.1 * sin) * length)]
+    },
+    LeadShape::Arc => {
+      let angle = angle.max(ARC_STEP);
+      let radius = length / angle;
+      let center = (p.0 + normal.0 * radius, p.1 + normal.1 * radius);
+      let v = (p.0 - center.0, p.1 - center.1);
+      // direction of rotation moving along the tangent
+      let sign = if -v.1 * tangent.0 + v.0 * tangent.1 >= 0.0 { 1.0 } else { -1.0 };
+      let steps = (angle / ARC_STEP).ceil() as usize;
+      (1..=steps)
+        .map(|step| {
+          let r = rotate(v, sign * angle * step as f32 / steps as f32);
+          (center.0 + r.0, center.1 + r.1)
+        })
+        .collect()
+    },
+  }
+}
+
+// closed edges are started at their pierce point and get the lead moves, open edges are left as they are
+// the scrap is outside of parts and inside of holes, as given by the containment tree of the edges
+// returns the cuts, and the number of lead-in and lead-out points around the contour of each cut
+pub fn add_leads(edges: Vec<Polyline>, options: &LeadOptions, unit_size: f32) -> (Vec<Polyline>, Vec<(usize, usize)>) {
+  let tree = ContainmentTree::new(&edges);
+  let mut head = (0.0, 0.0);
+  let mut cuts = Vec::with_capacity(edges.len());
+  let mut leads = Vec::with_capacity(edges.len());
+
+  for (index, edge) in edges.into_iter().enumerate() {
+    let closed = edge.len() >= 4 && edge[0] == edge[edge.len() - 1];
+    if !closed {
+      if let Some(last) = edge.last() {
+        head = *last;
+      }
+      cuts.push(edge);
+      leads.push((0, 0));
+      continue;
+    }
+
+    let points = &edge[..edge.len() - 1];
+    let n = points.len();
+    let outside = contour::signed_area(points).signum();
+    let scrap = if tree.is_hole(index) { -outside } else { outside };
+
+    let start = pierce_vertex(points, scrap, options.pierce, head);
+    let p = points[start];
+    let (previous, next) = (points[(start + n - 1) % n], points[(start + 1) % n]);
+    // where the contour turns away from the scrap, its edges go on into the part: the leads follow the other edge
+    let away = scrap * turn(previous, p, next) < 0.0;
+    let mut cut: Polyline = vec![];
+    let mut lead_out_points = 0;
+
+    if let Some(lead_in) = &options.lead_in {
+      // built going backwards from p, against the direction of the cut
+      let tangent = if away { unit(previous, p) } else { unit(p, next) };
+      let normal = (scrap * tangent.1, -scrap * tangent.0);
+      let mut points = lead(lead_in, p, (-tangent.0, -tangent.1), normal, unit_size);
+      points.reverse();
+      cut.extend(points);
+    }
+    let lead_in_points = cut.len();
+    cut.extend((0..=n).map(|offset| points[(start + offset) % n]));
+    if let Some(lead_out) = &options.lead_out {
+      let tangent = if away { unit(p, next) } else { unit(previous, p) };
+      let normal = (scrap * tangent.1, -scrap * tangent.0);
+      let points = lead(lead_out, p, tangent, normal, unit_size);
+      lead_out_points = points.len();
+      cut.extend(points);
+    }
+
+    head = cut[cut.len() - 1];
+    cuts.push(cut);
+    leads.push((lead_in_points, lead_out_points));
+  }
+  (cuts, leads)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn square(from: f32, to: f32) -> Polyline {
+    vec![(from, from), (to, from), (to, to), (from, to), (from, from)]
+  }
+
+  fn close(a: Point, b: Point) -> bool {
+    distance(a, b) < 1e-4
+  }
+
+  fn line(length: f32, angle: f32) -> Option<Lead> {
+    Some(Lead { shape: LeadShape::Line, length, angle })
+  }
+
+  #[test]
+  fn pierce_points() {
+    let points = &square(10.0, 20.0)[..4];
+    assert_eq!(pierce_vertex(points, 1.0, PierceRule::NearestCorner, (0.0, 0.0)), 0);
+    assert_eq!(pierce_vertex(points, 1.0, PierceRule::NearestCorner, (30.0, 30.0)), 2);
+    assert_eq!(pierce_vertex(points, 1.0, PierceRule::Side(Side::Bottom), (0.0, 0.0)), 3);
+    assert_eq!(pierce_vertex(points, 1.0, PierceRule::Side(Side::Right), (0.0, 0.0)), 1);
+    // an L shape, its inner corner points into the part
+    let l = [(0.0, 0.0), (20.0, 0.0), (20.0, 10.0), (10.0, 10.0), (10.0, 20.0), (0.0, 20.0)];
+    assert_eq!(pierce_vertex(&l, 1.0, PierceRule::NearestCorner, (12.0, 12.0)), 2);
+    // unless it is a hole, where the scrap is on the other side
+    assert_eq!(pierce_vertex(&l, -1.0, PierceRule::NearestCorner, (12.0, 12.0)), 3);
+  }
+
+  #[test]
+  fn line_leads() {
+    let options = LeadOptions { lead_in: line(2.0, 90.0), ..LeadOptions::default() };
+    let (cuts, leads) = add_leads(vec![square(10.0, 20.0)], &options, 1.0);
+    assert_eq!(leads, vec![(1, 0)]);
+    assert!(close(cuts[0][0], (10.0, 8.0)));
+    assert_eq!(cuts[0][1..], square(10.0, 20.0)[..]);
+
+    // lead-out going on with the last edge, bent away from the part, in command units
+    let options = LeadOptions { lead_out: line(1.0, 45.0), ..LeadOptions::default() };
+    let (cuts, leads) = add_leads(vec![square(10.0, 20.0)], &options, 0.5);
+    assert_eq!(leads, vec![(0, 1)]);
+    assert!(close(cuts[0][5], (10.0 - 2f32.sqrt(), 10.0 - 2f32.sqrt())));
+  }
+
+  #[test]
+  fn leads_stay_in_the_scrap() {
+    // a part with a hole, and an open edge
+    let hole = square(20.0, 40.0).into_iter().rev().collect::<Polyline>();
+    let edges = vec![hole, square(0.0, 60.0), vec![(70.0, 0.0), (80.0, 0.0)]];
+    for (shape, angle) in [(LeadShape::Line, 45.0), (LeadShape::Arc, 90.0)] {
+      let lead = Some(Lead { shape, length: 3.0, angle });
+      let options = LeadOptions { lead_in: lead, lead_out: lead, ..LeadOptions::default() };
+      let (cuts, leads) = add_leads(edges.clone(), &options, 1.0);
+      assert_eq!(cuts[2], edges[2]);
+      assert_eq!(leads[2], (0, 0));
+
+      let (lead_in, lead_out) = leads[0];
+      assert!(lead_in > 0 && lead_out > 0);
+      let inside_hole = |p: &Point| p.0 > 20.0 && p.0 < 40.0 && p.1 > 20.0 && p.1 < 40.0;
+      assert!(cuts[0][..lead_in].iter().chain(&cuts[0][cuts[0].len() - lead_out..]).all(inside_hole));
+      let (lead_in, lead_out) = leads[1];
+      let outside_part = |p: &Point| p.0 < 0.0 || p.1 < 0.0 || p.0 > 60.0 || p.1 > 60.0;
+      assert!(cuts[1][..lead_in].iter().chain(&cuts[1][cuts[1].len() - lead_out..]).all(outside_part));
+    }
+  }
+
+  #[test]
+  fn leads_in_round_holes() {
+    let hole = (0..=32).map(|i| {
+      let angle = -2.0 * PI * i as f32 / 32.0;
+      (30.0 + 10.0 * angle.cos(), 30.0 + 10.0 * angle.sin())
+    });
+    let edges = vec![hole.collect(), square(0.0, 60.0)];
+    let lead = Some(Lead { shape: LeadShape::Arc, length: 4.0, angle: 90.0 });
+    let options = LeadOptions { lead_in: lead, lead_out: lead, ..LeadOptions::default() };
+    let (cuts, leads) = add_leads(edges, &options, 1.0);
+    let (lead_in, lead_out) = leads[0];
+    // the contour is a little further in than 10 between its vertices
+    let inside = |p: &Point| distance(*p, (30.0, 30.0)) < 10.0 * (PI / 32.0).cos();
+    assert!(cuts[0][..lead_in].iter().chain(&cuts[0][cuts[0].len() - lead_out..]).all(inside));
+  }
+
+  #[test]
+  fn arc_leads() {
+    let arc = Lead { shape: LeadShape::Arc, length: PI, angle: 90.0 };
+    // tangent to the x axis at the origin, curving towards y
+    let points = lead(&arc, (0.0, 0.0), (1.0, 0.0), (0.0, 1.0), 1.0);
+    assert_eq!(points.len(), 8);
+    assert!(points.iter().all(|p| (distance(*p, (0.0, 2.0)) - 2.0).abs() < 1e-4));
+    assert!(close(points[7], (2.0, 2.0)));
+  }
+}
@@ -1,10 +1,53 @@
 pub mod args_parse;
 pub mod canny;
+pub mod command;
+pub mod machine;
+pub mod gcode;
+pub mod grbl;
+pub mod resume;
+pub mod separation;
+pub mod fill;
+pub mod path;
+pub mod shading;
+pub mod tsp;
+pub mod raster;
+pub mod contour;
+pub mod offset;
+pub mod ordering;
+pub mod leads;
+pub mod tabs;
+pub mod depth;
+pub mod pocket;
+pub mod vector;
+pub mod svg;
+pub mod dxf;
+pub mod job;
+pub mod hershey;
+pub mod text;
+pub mod qr;
+pub mod barcode;
+pub mod import;
 
 use std::io::prelude::*;
 use std::fs::{File};
+use std::path::PathBuf;
 
 pub fn execute(config: &args_parse::Config) {
+  if let Some(point) = config.resume {
+      resume_program(config, point);
+      return;
+  }
+
+  if config.input_file.as_os_str().is_empty() {
+      export_generated(config);
+      return;
+  }
+
+  if ["svg", "dxf", "job", "json", "txt"].contains(&config.input_extension.as_str()) {
+      export_drawing(config);
+      return;
+  }
+
   // construct the edges image from the grayscaled input
   let original = image::open(&config.input_file)
       .expect("No image found at input_file path")
@@ -14,6 +57,11 @@ pub fn execute(config: &args_parse::Config) {
       .grayscale()
       .to_luma8();
 
+  if let Some(raster_options) = &config.raster {
+      export_raster(config, &gray_image, raster_options);
+      return;
+  }
+
   let edges_image = image::DynamicImage::ImageLuma8(
       if config.skip_canny_edge_detection {
           let mut black_white = canny::copy_image(&gray_image);
@@ -38,45 +86,27 @@ pub fn execute(config: &args_parse::Config) {
 
   // if point precision is enabled, convert the edges to a JSON file
   if let Some(point_precision) = config.export_options.point_precision {
-      // convert points to json
-      let computation = canny::to_serializable_points(&edges_image, point_precision);
-      let points_json = serde_json::to_string(
-          &computation
-      ).unwrap();
-      // save points to file
-      let handle = File::create(config.export_path.join(format!("{}_points.json", config.input_name)));
-      match handle {
-          Ok(mut file) => {
-              let saved_points = file.write_all(points_json.as_bytes());
-              if saved_points.is_err() {
-                  println!("Error saving points: {:?}", saved_points.err());
-              }
-          },
-          Err(e) => {
-              println!("Error creating json file: {:?}", e);
-          }
+      if let Some(separation) = &config.separation {
+          export_layers(config, &original, separation, point_precision);
       }
-
-      if !config.export_options.exclude_cnc {
-          let command_fh = File::create(config.export_path.join(format!("{}_command.txt", config.input_name)));
-          match command_fh {
-              Ok(mut file) => {
-                  let saved_cmd = file.write_all(canny::to_cnc(&computation).as_bytes());
-                  if saved_cmd.is_err() {
-                      println!("Error saving points: {:?}", saved_cmd.err());
-                  }
-              },
-              Err(e) => {
-                  println!("Error creating json file: {:?}", e);
-              }
-          }
+      else if let Some(contour_options) = &config.contours {
+          export_contours(config, &gray_image, contour_options, point_precision);
+      }
+      else if let Some(shading_options) = &config.shading {
+          export_shading(config, &gray_image, shading_options, point_precision);
+      }
+      else {
+          // convert points to json
+          let computation = trace(config, &edges_image, &gray_image, point_precision);
+          let paths = edge_paths(&computation.edges);
+          export_paths(config, &config.gcode, vec![], with_generated(config, paths), gray_image.width(), gray_image.height(), point_precision);
       }
   }
 
   // if debug point precision is enabled, draw the edges on the input image and save it
   if let Some(point_precision) = config.export_options.debug_preview {
       let mut draw_image = image::DynamicImage::ImageRgb8(canny::copy_image(&original));
-      let computation = canny::to_serializable_points(&edges_image, point_precision);
+      let computation = trace(config, &edges_image, &gray_image, point_precision);
 
       if config.export_options.exclude_individual_edges {
           canny::draw_edges_on(
@@ -105,3 +135,379 @@ pub fn execute(config: &args_parse::Config) {
       }
   }
 }
+
+// edges of the edge detected image, followed by the hatch fill of the dark areas when enabled
+// shading replaces the edges with the tone of the grayscale image
+fn trace(config: &args_parse::Config, edges_image: &image::DynamicImage, gray_image: &image::GrayImage, point_precision: f32) -> canny::SerializebleComputation {
+  if let Some(shading_options) = &config.shading {
+      let strokes = path::order_strokes(shading::shade(gray_image, shading_options));
+      return canny::SerializebleComputation {
+          edges: fill::to_edges(&strokes),
+          ..canny::empty_computation(gray_image.width(), gray_image.height(), point_precision)
+      };
+  }
+
+  if let Some(contour_options) = &config.contours {
+      let polylines = contours(config, gray_image, contour_options).iter().map(|c| c.closed()).collect::<Vec<fill::Polyline>>();
+      return canny::SerializebleComputation {
+          edges: fill::to_edges(&polylines),
+          ..canny::empty_computation(gray_image.width(), gray_image.height(), point_precision)
+      };
+  }
+
+  let mut computation = canny::to_serializable_points(edges_image, point_precision);
+  if let Some(fill_options) = &config.fill {
+      let strokes = path::order_strokes(fill::fill_image(gray_image, fill_options));
+      computation.edges.extend(fill::to_edges(&strokes));
+  }
+  computation
+}
+
+fn save_text(path: PathBuf, contents: &str) {
+  match File::create(&path) {
+      Ok(mut file) => {
+          if let Err(e) = file.write_all(contents.as_bytes()) {
+              println!("Error saving {}: {:?}", path.display(), e);
+          }
+      },
+      Err(e) => {
+          println!("Error creating {}: {:?}", path.display(), e);
+      }
+  }
+}
+
+// traces every color layer, exporting them as one job with tool changes or as one job per pen
+fn export_layers(config: &args_parse::Config, original: &image::RgbImage, options: &separation::SeparationOptions, point_precision: f32) {
+  let layers = separation::separate(original, options, config.fill.as_ref(), point_precision)
+      .into_iter()
+      .map(|(color, computation)| {
+          let paths = edge_paths(&computation.edges);
+          (color, cut_paths(config, vec![], paths, computation.width as u32, computation.height as u32, point_precision))
+      })
+      .collect::<Vec<([u8; 3], canny::SerializebleComputation<f32>)>>();
+  save_layers(config, &layers, options.split_files);
+}
+
+// saves the points of each layer, then the commands of every layer with tool changes, or of each layer on its own
+fn save_layers(config: &args_parse::Config, layers: &[([u8; 3], canny::SerializebleComputation<f32>)], split_files: bool) {
+  for (index, (color, computation)) in layers.iter().enumerate() {
+      println!("Pen {}: #{:02x}{:02x}{:02x}, {} edges", index + 1, color[0], color[1], color[2], computation.edges.len());
+      save_text(
+          config.export_path.join(format!("{}_pen{}_points.json", config.input_name, index + 1)),
+          &serde_json::to_string(computation).unwrap(),
+      );
+  }
+
+  if config.export_options.exclude_cnc {
+      return;
+  }
+  if split_files {
+      for (index, (_, computation)) in layers.iter().enumerate() {
+          export_commands(config, &config.gcode, &format!("{}_pen{}", config.input_name, index + 1), &canny::to_pierced_cnc(computation, pierce_dwell(config)));
+      }
+  }
+  else {
+      export_commands(config, &config.gcode, &config.input_name, &canny::to_layered_cnc(layers, pierce_dwell(config)));
+  }
+}
+
+// closed contours of the dark areas, as traced
+fn traced_contours(gray_image: &image::GrayImage, options: &contour::ContourOptions) -> Vec<contour::Contour> {
+  contour::trace(
+      gray_image.width(),
+      gray_image.height(),
+      |x, y| gray_image.get_pixel(x, y)[0] < options.threshold,
+      options.tolerance,
+  )
+}
+
+// closed contours of the dark areas, moved away from the material by half of the kerf
+fn contours(config: &args_parse::Config, gray_image: &image::GrayImage, options: &contour::ContourOptions) -> Vec<contour::Contour> {
+  let traced = traced_contours(gray_image, options);
+  // the kerf is given in mm, contours are in command units
+  let distance = options.kerf / 2.0 / config.machine.unit_size;
+  traced.iter().flat_map(|c| offset::offset(c, distance)).collect()
+}
+
+// contours keep their sub pixel positions, instead of being rounded to pixel indices
+// pockets clear the dark areas instead, the kerf being the width of the tool
+fn export_contours(config: &args_parse::Config, gray_image: &image::GrayImage, options: &contour::ContourOptions, point_precision: f32) {
+  let (clearing, paths) = match &config.pocket {
+      Some(pocket_options) => {
+          let radius = options.kerf / 2.0 / config.machine.unit_size;
+          let (clearing, profile) = pocket::pocket(&traced_contours(gray_image, options), pocket_options, radius, config.machine.unit_size);
+          println!("{} pocket paths", clearing.len() + profile.len());
+          (clearing, profile)
+      },
+      None => {
+          let contours = contours(config, gray_image, options);
+          let holes = contours.iter().filter(|c| c.hole).count();
+          println!("{} contours ({} holes)", contours.len(), holes);
+          (vec![], contours.iter().map(|c| c.closed()).collect())
+      },
+  };
+  export_paths(config, &config.gcode, clearing, with_generated(config, paths), gray_image.width(), gray_image.height(), point_precision);
+}
+
+// reads the paths of an svg or dxf drawing, of a job file or of a previous export, in place of the edges traced from an image
+fn export_drawing(config: &args_parse::Config) {
+  let text = match std::fs::read_to_string(&config.input_file) {
+      Ok(text) => text,
+      Err(e) => {
+          println!("Error reading {}: {}", config.input_file.display(), e);
+          return;
+      },
+  };
+  let drawing = match config.input_extension.as_str() {
+      "dxf" => dxf::parse(&text, config.flatten_tolerance, &config.layers),
+      "job" => job::parse(&text, config.flatten_tolerance),
+      "json" => import::points(&text),
+      "txt" => match import::commands(&text) {
+          // a job drawn with several pens keeps its tool changes
+          Ok(pens) if pens.len() > 1 => return export_pens(config, pens),
+          pens => pens.map(|mut pens| pens.remove(0).1),
+      },
+      _ => svg::parse(&text, config.flatten_tolerance),
+  };
+  let drawing = match drawing {
+      Ok(drawing) => drawing,
+      Err(e) => {
+          println!("Error reading {}: {}", config.input_file.display(), e);
+          return;
+      },
+  };
+  println!("{} paths", drawing.paths.len());
+
+  if let Some(point_precision) = config.export_options.point_precision {
+      // a job file gives the trajectory in its order
+      let paths = if config.input_extension == "job" { drawing.paths } else { path::order_strokes(drawing.paths) };
+      // the arcs of the drawing are written as G2 / G3 around their exact centers
+      let gcode_options = if drawing.circles.is_empty() {
+          config.gcode.clone()
+      } else {
+          gcode::GcodeOptions {
+              arc_tolerance: config.gcode.arc_tolerance.or(Some(config.flatten_tolerance * config.machine.unit_size)),
+              circles: drawing.circles,
+              ..config.gcode.clone()
+          }
+      };
+      export_paths(config, &gcode_options, vec![], with_generated(config, paths), drawing.width.ceil() as u32, drawing.height.ceil() as u32, point_precision);
+  }
+}
+
+// the pens of a command file, exported as one job with a tool change before each of them
+// the text or the barcode is drawn with the last pen
+fn export_pens(config: &args_parse::Config, pens: Vec<import::Pen>) {
+  println!("{} paths, {} pens", pens.iter().map(|(_, drawing)| drawing.paths.len()).sum::<usize>(), pens.len());
+
+  if let Some(point_precision) = config.export_options.point_precision {
+      let count = pens.len();
+      let layers = pens
+          .into_iter()
+          .enumerate()
+          .map(|(index, (color, drawing))| {
+              let paths = path::order_strokes(drawing.paths);
+              let paths = if index + 1 == count { with_generated(config, paths) } else { paths };
+              // pens without a color are drawn in black
+              (color.unwrap_or([0, 0, 0]), cut_paths(config, vec![], paths, drawing.width.ceil() as u32, drawing.height.ceil() as u32, point_precision))
+          })
+          .collect::<Vec<([u8; 3], canny::SerializebleComputation<f32>)>>();
+      save_layers(config, &layers, false);
+  }
+}
+
+// the paths followed by those of the text or of the barcode, when there is one
+fn with_generated(config: &args_parse::Config, mut paths: Vec<fill::Polyline>) -> Vec<fill::Polyline> {
+  if let Some(text_options) = &config.text {
+      match text::paths(text_options, config.flatten_tolerance, config.machine.unit_size) {
+          Ok(text_paths) => {
+              println!("{} text strokes", text_paths.len());
+              paths.extend(text_paths);
+          },
+          Err(e) => println!("{}", e),
+      }
+  }
+  if let Some(barcode_options) = &config.barcode {
+      match barcode::paths(barcode_options, config.machine.unit_size) {
+          Ok(barcode_paths) => {
+              println!("{} barcode paths", barcode_paths.len());
+              paths.extend(barcode_paths);
+          },
+          Err(e) => println!("{}", e),
+      }
+  }
+  paths
+}
+
+// a text or a barcode engraved on its own, the canvas ends where it does
+fn export_generated(config: &args_parse::Config) {
+  if let Some(point_precision) = config.export_options.point_precision {
+      let drawing = vector::Drawing::fitted(with_generated(config, vec![]));
+      export_paths(config, &config.gcode, vec![], drawing.paths, drawing.width.ceil() as u32, drawing.height.ceil() as u32, point_precision);
+  }
+}
+
+// the edges of a computation as polylines
+fn edge_paths<T: canny::Coordinate>(edges: &canny::SEdges<T>) -> Vec<fill::Polyline> {
+  edges.iter().map(|edge| edge.iter().map(|p| (p.x.to_f32(), p.y.to_f32())).collect()).collect()
+}
+
+// seconds waited after each pierce
+fn pierce_dwell(config: &args_parse::Config) -> f32 {
+  config.leads.map(|l| l.dwell).unwrap_or(0.0)
+}
+
+// orders the paths and adds the leads and tabs, every pipeline cutting paths goes through it
+// the clearing passes of a pocket come first as they are, the leads and tabs only belong to the profile
+fn cut_paths(config: &args_parse::Config, clearing: Vec<fill::Polyline>, paths: Vec<fill::Polyline>, width: u32, height: u32, point_precision: f32) -> canny::SerializebleComputation<f32> {
+  let edges = paths
+      .into_iter()
+      .map(|path| path.into_iter().map(|(x, y)| canny::SPixelIndex { x, y }).collect())
+      .collect();
+  let edges = ordering::order_edges(edges, config.cut_order);
+  let mut cuts = edge_paths(&edges);
+  let mut leads = vec![];
+  if let Some(lead_options) = &config.leads {
+      let (led, lead_points) = leads::add_leads(cuts, lead_options, config.machine.unit_size);
+      cuts = led;
+      leads = vec![(0, 0); clearing.len()];
+      leads.extend(lead_points);
+  }
+  let mut tabs = vec![];
+  if let Some(tab_options) = &config.tabs {
+      // the tabs go between the leads of each cut
+      let cut_leads = leads.get(clearing.len()..).unwrap_or(&[]);
+      let (tabbed, tab_segments) = tabs::add_tabs(cuts, cut_leads, tab_options, config.machine.unit_size);
+      cuts = tabbed;
+      tabs = vec![vec![]; clearing.len()];
+      tabs.extend(tab_segments);
+  }
+  canny::SerializebleComputation::<f32> {
+      edges: clearing
+          .into_iter()
+          .chain(cuts)
+          .map(|cut| cut.into_iter().map(|(x, y)| canny::SPixelIndex { x, y }).collect())
+          .collect(),
+      tabs,
+      leads,
+      ..canny::empty_computation(width, height, point_precision)
+  }
+}
+
+// cuts the paths, then saves the points and the commands
+fn export_paths(config: &args_parse::Config, gcode_options: &gcode::GcodeOptions, clearing: Vec<fill::Polyline>, paths: Vec<fill::Polyline>, width: u32, height: u32, point_precision: f32) {
+  let computation = cut_paths(config, clearing, paths, width, height, point_precision);
+  save_text(
+      config.export_path.join(format!("{}_points.json", config.input_name)),
+      &serde_json::to_string(&computation).unwrap(),
+  );
+
+  if !config.export_options.exclude_cnc {
+      export_commands(config, gcode_options, &config.input_name, &canny::to_pierced_cnc(&computation, pierce_dwell(config)));
+  }
+}
+
+// shading strokes keep their sub pixel positions, the waves of the squiggles and spirals are smaller than a pixel
+fn export_shading(config: &args_parse::Config, gray_image: &image::GrayImage, options: &shading::ShadingOptions, point_precision: f32) {
+  let strokes = path::order_strokes(shading::shade(gray_image, options));
+  export_paths(config, &config.gcode, vec![], with_generated(config, strokes), gray_image.width(), gray_image.height(), point_precision);
+}
+
+// prints the estimated job time and the moves leaving the machine envelope
+fn report_job(commands: &[command::Command], cnc: &str, machine: &machine::MachineModel) {
+  let estimate = machine::estimate(commands, machine);
+  println!(
+      "Estimated job time: {} (drawing {}, travel {}, pen {})",
+      machine::format_duration(estimate.total),
+      machine::format_duration(estimate.drawing),
+      machine::format_duration(estimate.travel),
+      machine::format_duration(estimate.pen),
+  );
+
+  let violations = machine::validate(cnc, machine);
+  if !violations.is_empty() {
+      println!("{} moves are outside of the machine envelope:", violations.len());
+      for violation in violations.iter().take(10) {
+          println!("  line {}: {} ({}mm, {}mm)", violation.line, violation.command, violation.x, violation.y);
+      }
+      if violations.len() > 10 {
+          println!("  ...");
+      }
+  }
+}
+
+// estimates, validates, saves and optionally streams a command file
+// exports are named after `name`, ie: <name>_command.txt
+pub fn export_commands(config: &args_parse::Config, gcode_options: &gcode::GcodeOptions, name: &str, cnc: &str) {
+  let mut commands = command::parse_commands(cnc);
+  // multi pass jobs repeat every edge at each depth
+  let passes;
+  let cnc = match &config.depth {
+      Some(depth_options) => {
+          commands = depth::passes(&commands, depth_options, config.machine.unit_size);
+          passes = commands.iter().map(|c| c.to_string()).collect::<Vec<String>>().join("\n");
+          &passes
+      },
+      None => cnc,
+  };
+  report_job(&commands, cnc, &config.machine);
+
+  save_text(config.export_path.join(format!("{}_command.txt", name)), cnc);
+
+  if config.export_options.gcode || config.send_options.is_some() {
+      let gcode = gcode::to_gcode(&commands, &config.machine, gcode_options);
+      if config.export_options.gcode {
+          save_text(config.export_path.join(format!("{}.gcode", name)), &gcode);
+      }
+
+      if let Some(send_options) = &config.send_options {
+          match grbl::send(&gcode, send_options) {
+              Ok(()) => println!("Job sent"),
+              Err(grbl::StreamError::Rejected(line, code)) => println!("Line {} was rejected with error:{}, the job was aborted", line, code),
+              Err(grbl::StreamError::Alarm(code)) => println!("The controller raised ALARM:{}, unlock it before sending again", code),
+              Err(grbl::StreamError::TooLong(line)) => println!("Line {} does not fit in the {} byte buffer of the controller, the job was aborted", line, grbl::RX_BUFFER_SIZE),
+              Err(grbl::StreamError::Aborted) => println!("Job aborted"),
+              Err(e) => println!("Error streaming the job: {:?}", e),
+          }
+      }
+  }
+}
+
+// writes <name>_raster.gcode, the laser has no pen to move so there is no pen delay
+fn export_raster(config: &args_parse::Config, gray_image: &image::GrayImage, options: &raster::RasterOptions) {
+  let machine = machine::MachineModel {
+      pen_delay: 0.0,
+      ..config.machine
+  };
+  let job = raster::to_raster(gray_image, options, &machine);
+  println!("{} rows to engrave", job.rows);
+
+  // line numbers of the violations refer to the motion of the job, not to the G-code
+  let motion = job.commands.iter().map(|command| command.to_string()).collect::<Vec<String>>().join("\n");
+  report_job(&job.commands, &motion, &machine);
+
+  save_text(config.export_path.join(format!("{}_raster.gcode", config.input_name)), &job.gcode);
+}
+
+// writes <name>_resumed.<ext> next to the interrupted program
+fn resume_program(config: &args_parse::Config, point: resume::ResumePoint) {
+  let program = match std::fs::read_to_string(&config.input_file) {
+      Ok(program) => program,
+      Err(e) => {
+          println!("Error reading {}: {:?}", config.input_file.display(), e);
+          std::process::exit(1);
+      }
+  };
+
+  let start = match resume::find_start(&program, point, &config.machine) {
+      Ok(start) => start,
+      Err(e) => {
+          println!("{}", e);
+          std::process::exit(1);
+      }
+  };
+  println!("Resuming from line {}", start + 1);
+
+  let resumed = resume::resume(&program, start, &config.machine, &config.gcode);
+  save_text(config.export_path.join(format!("{}_resumed.{}", config.input_name, config.input_extension)), &resumed);
+}
@@ -0,0 +1,406 @@
+// Kinematic model of the machine, shared by the job time estimator and the simulator
+use crate::canny::PenDirection;
+use crate::command::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MachineModel {
+  // feed rate while the pen is down (mm/min)
+  pub max_feed: f32,
+  // feed rate while the pen is up (mm/min)
+  pub travel_rate: f32,
+  // acceleration and deceleration of the head (mm/s^2)
+  pub acceleration: f32,
+  // time it takes to raise or lower the pen (s)
+  pub pen_delay: f32,
+  // physical size of one command unit (mm)
+  pub unit_size: f32,
+  // soft limits of the workspace, unlimited when missing
+  pub envelope: Option<Envelope>,
+}
+
+// rectangular workspace of the machine (mm)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Envelope {
+  pub min_x: f32,
+  pub min_y: f32,
+  pub max_x: f32,
+  pub max_y: f32,
+}
+
+impl Envelope {
+  pub fn contains(&self, x: f32, y: f32) -> bool {
+    x >= self.min_x && x <= self.max_x && y >= self.min_y && y <= self.max_y
+  }
+}
+
+// a MOVE command that leaves the envelope
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Violation {
+  // 1 based line number in the command file
+  pub line: usize,
+  pub command: Command,
+  // target of the move (mm)
+  pub x: f32,
+  pub y: f32,
+}
+
+impl Default for MachineModel {
+  fn default() -> MachineModel {
+    MachineModel {
+      max_feed: 3000.0,
+      travel_rate: 6000.0,
+      acceleration: 500.0,
+      pen_delay: 0.15,
+      unit_size: 1.0,
+      envelope: None,
+    }
+  }
+}
+
+impl MachineModel {
+  // cruise speed in mm/s for the given pen state
+  pub fn speed(&self, pen: PenDirection) -> f32 {
+    match pen {
+      PenDirection::UP => self.travel_rate / 60.0,
+      PenDirection::DOWN => self.max_feed / 60.0,
+    }
+  }
+
+  // duration of a single move of `distance` command units
+  // every move starts and ends at rest, following a trapezoidal (or triangular) velocity profile
+  pub fn move_time(&self, distance: f32, pen: PenDirection) -> f32 {
+    let distance = distance * self.unit_size;
+    let speed = self.speed(pen);
+    if distance <= 0.0 || speed <= 0.0 {
+      return 0.0;
+    }
+    if self.acceleration <= 0.0 {
+      return distance / speed;
+    }
+
+    let ramp_distance = speed * speed / self.acceleration; // accelerate + decelerate
+    if distance >= ramp_distance {
+      distance / speed + speed / self.acceleration
+    }
+    else {
+      2.0 * (distance / self.acceleration).sqrt()
+    }
+  }
+
+  // checks if a point given in command units is inside the envelope
+  pub fn within_limits(&self, x: f32, y: f32) -> bool {
+    match self.envelope {
+      Some(envelope) => envelope.contains(x * self.unit_size, y * self.unit_size),
+      None => true,
+    }
+  }
+
+  // distance (in command units) covered `time` seconds into a move of `distance` command units
+  pub fn distance_at(&self, distance: f32, pen: PenDirection, time: f32) -> f32 {
+    let total = self.move_time(distance, pen);
+    if time >= total {
+      return distance;
+    }
+    if time <= 0.0 {
+      return 0.0;
+    }
+    if self.acceleration <= 0.0 {
+      return distance * time / total;
+    }
+
+    // peak speed is lower than the cruise speed for short moves
+    let peak = self.speed(pen).min(self.acceleration * total / 2.0);
+    let ramp_time = peak / self.acceleration;
+    let covered = if time < ramp_time {
+      0.5 * self.acceleration * time * time
+    }
+    else if time <= total - ramp_time {
+      0.5 * peak * ramp_time + peak * (time - ramp_time)
+    }
+    else {
+      let left = total - time;
+      distance * self.unit_size - 0.5 * self.acceleration * left * left
+    };
+    (covered / self.unit_size).min(distance)
+  }
+}
+
+// position and pen state of the head while walking a command list
+// the job estimate and the simulator both move it, so they agree on the duration of every command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadState {
+  pub x: f32,
+  pub y: f32,
+  pub pen: PenDirection,
+  // Z of the tool while the pen is down (mm)
+  pub depth: f32,
+  // the tool is raised over a tab, until the next cut
+  pub raised: bool,
+}
+
+// the motion caused by a single command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+  // length of the move (command units)
+  pub distance: f32,
+  // pen state during the move, tabs are crossed with the tool raised
+  pub pen: PenDirection,
+  // duration of the move (s)
+  pub moving: f32,
+  // pen changes and pierce dwells (s)
+  pub waiting: f32,
+}
+
+impl Step {
+  pub fn duration(&self) -> f32 {
+    self.moving + self.waiting
+  }
+}
+
+impl HeadState {
+  pub fn origin() -> HeadState {
+    HeadState {
+      x: 0.0,
+      y: 0.0,
+      pen: PenDirection::UP,
+      depth: 0.0,
+      raised: false,
+    }
+  }
+
+  // applies a command and returns the motion it causes
+  pub fn apply(&mut self, command: &Command, machine: &MachineModel) -> Step {
+    let tab = matches!(command, Command::Tab(..));
+    let was_raised = std::mem::replace(&mut self.raised, tab && self.pen == PenDirection::DOWN);
+    let mut waiting = 0.0;
+    let (to_x, to_y, pen) = match *command {
+      Command::Pen(direction) => (self.x, self.y, direction),
+      Command::Move(x, y) | Command::Tab(x, y) => (x, y, self.pen),
+      Command::Ramp(x, y, z) => {
+        self.depth = z;
+        (x, y, self.pen)
+      },
+      Command::Reset => (0.0, 0.0, PenDirection::UP),
+      // the pen is lifted to be swapped
+      Command::Tool(..) => (self.x, self.y, PenDirection::UP),
+      Command::Dwell(seconds) => {
+        waiting = seconds;
+        (self.x, self.y, self.pen)
+      },
+      // Z moves are not timed
+      Command::Depth(z) => {
+        self.depth = z;
+        (self.x, self.y, self.pen)
+      },
+      Command::End => (self.x, self.y, self.pen),
+    };
+
+    if pen != self.pen {
+      waiting += machine.pen_delay;
+    }
+    // the tool is raised at the first of a series of tab moves, and lowered again at the next cut
+    if self.raised != was_raised && pen == PenDirection::DOWN {
+      waiting += machine.pen_delay;
+    }
+    let moving_pen = if tab { PenDirection::UP } else { pen };
+    let distance = ((to_x - self.x).powi(2) + (to_y - self.y).powi(2)).sqrt();
+
+    self.x = to_x;
+    self.y = to_y;
+    self.pen = pen;
+    Step {
+      distance,
+      pen: moving_pen,
+      moving: machine.move_time(distance, moving_pen),
+      waiting,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct JobEstimate {
+  // all times are in seconds, all distances in mm
+  pub total: f32,
+  pub drawing: f32,
+  pub travel: f32,
+  pub pen: f32, // pen changes and pierce dwells
+  pub drawing_distance: f32,
+  pub travel_distance: f32,
+}
+
+// walks the command list the same way the simulator does, summing up the duration of each command
+pub fn estimate(commands: &[Command], machine: &MachineModel) -> JobEstimate {
+  let mut estimate = JobEstimate::default();
+  let mut head = HeadState::origin();
+
+  for command in commands {
+    if *command == Command::End {
+      break;
+    }
+    let step = head.apply(command, machine);
+    estimate.pen += step.waiting;
+    match step.pen {
+      PenDirection::UP => {
+        estimate.travel += step.moving;
+        estimate.travel_distance += step.distance * machine.unit_size;
+      },
+      PenDirection::DOWN => {
+        estimate.drawing += step.moving;
+        estimate.drawing_distance += step.distance * machine.unit_size;
+      },
+    }
+  }
+
+  estimate.total = estimate.drawing + estimate.travel + estimate.pen;
+  estimate
+}
+
+// flags every MOVE, TAB and RAMP of a command file that targets a point outside the envelope
+pub fn validate(commands: &str, machine: &MachineModel) -> Vec<Violation> {
+  commands
+    .lines()
+    .enumerate()
+    .filter_map(|(index, line)| match Command::parse(line) {
+      Some(command @ (Command::Move(x, y) | Command::Tab(x, y) | Command::Ramp(x, y, _))) if !machine.within_limits(x, y) => Some(Violation {
+        line: index + 1,
+        command,
+        x: x * machine.unit_size,
+        y: y * machine.unit_size,
+      }),
+      _ => None,
+    })
+    .collect()
+}
+
+// formats seconds as HH:MM:SS
+pub fn format_duration(seconds: f32) -> String {
+  let seconds = seconds.max(0.0).round() as u64;
+  format!("{:02}:{:02}:{:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+  }
+
+  #[test]
+  fn move_times() {
+    let machine = MachineModel::default();
+    // 50 mm/s reached after 5 mm of acceleration and deceleration
+    assert!(close(machine.move_time(100.0, PenDirection::DOWN), 2.1));
+    assert!(close(machine.move_time(100.0, PenDirection::UP), 1.2));
+    // too short to reach the cruise speed
+    assert!(close(machine.move_time(1.25, PenDirection::DOWN), 0.1));
+    assert_eq!(machine.move_time(0.0, PenDirection::DOWN), 0.0);
+    // command units are scaled to mm
+    let scaled = MachineModel { unit_size: 0.5, ..machine };
+    assert!(close(scaled.move_time(200.0, PenDirection::DOWN), 2.1));
+    // without acceleration, moves go at the cruise speed all along
+    let instant = MachineModel { acceleration: 0.0, ..machine };
+    assert!(close(instant.move_time(100.0, PenDirection::DOWN), 2.0));
+  }
+
+  #[test]
+  fn distances_along_a_move() {
+    let machine = MachineModel::default();
+    assert_eq!(machine.distance_at(100.0, PenDirection::DOWN, 0.0), 0.0);
+    assert!(close(machine.distance_at(100.0, PenDirection::DOWN, 0.1), 2.5));
+    assert!(close(machine.distance_at(100.0, PenDirection::DOWN, 1.05), 50.0));
+    assert!(close(machine.distance_at(100.0, PenDirection::DOWN, 2.0), 97.5));
+    assert_eq!(machine.distance_at(100.0, PenDirection::DOWN, 3.0), 100.0);
+    // triangular profiles peak halfway
+    assert!(close(machine.distance_at(1.25, PenDirection::DOWN, 0.05), 0.625));
+
+    let mut previous = 0.0;
+    for step in 0..=30 {
+      let covered = machine.distance_at(100.0, PenDirection::DOWN, step as f32 * 0.075);
+      assert!(covered >= previous);
+      previous = covered;
+    }
+  }
+
+  #[test]
+  fn job_estimates() {
+    let machine = MachineModel::default();
+    let commands = vec![
+      Command::Move(0.0, 100.0),
+      Command::Pen(PenDirection::DOWN),
+      Command::Move(100.0, 100.0),
+      Command::Dwell(0.5),
+      Command::Pen(PenDirection::UP),
+      Command::Reset,
+      Command::End,
+      Command::Move(5.0, 5.0),
+    ];
+    let estimate = estimate(&commands, &machine);
+    assert!(close(estimate.travel_distance, 100.0 + 100.0 * 2f32.sqrt()));
+    assert!(close(estimate.travel, 1.2 + 2f32.sqrt() + 0.2));
+    assert!(close(estimate.drawing_distance, 100.0));
+    assert!(close(estimate.drawing, 2.1));
+    assert!(close(estimate.pen, 0.8));
+    assert!(close(estimate.total, estimate.travel + estimate.drawing + estimate.pen));
+  }
+
+  #[test]
+  fn tabs_raise_the_tool_once() {
+    let machine = MachineModel::default();
+    let commands = vec![
+      Command::Pen(PenDirection::DOWN),
+      Command::Move(10.0, 0.0),
+      Command::Tab(20.0, 0.0),
+      Command::Tab(30.0, 0.0),
+      Command::Move(40.0, 0.0),
+      Command::Pen(PenDirection::UP),
+    ];
+    let estimate = estimate(&commands, &machine);
+    assert!(close(estimate.pen, 4.0 * machine.pen_delay));
+    assert!(close(estimate.drawing_distance, 20.0));
+    assert!(close(estimate.travel_distance, 20.0));
+
+    // raised at the first tab only, crossing both tabs with the pen up
+    let mut head = HeadState::origin();
+    let steps = commands.iter().map(|command| head.apply(command, &machine)).collect::<Vec<Step>>();
+    assert_eq!(steps.iter().map(|step| step.waiting / machine.pen_delay).collect::<Vec<f32>>(), vec![1.0, 0.0, 1.0, 0.0, 1.0, 1.0]);
+    assert_eq!(steps[3].pen, PenDirection::UP);
+    assert_eq!((head.x, head.y, head.pen, head.raised), (40.0, 0.0, PenDirection::UP, false));
+  }
+
+  #[test]
+  fn tool_changes_lift_the_pen() {
+    let machine = MachineModel::default();
+    let mut head = HeadState::origin();
+    head.apply(&Command::Pen(PenDirection::DOWN), &machine);
+    let step = head.apply(&Command::Tool(2, None), &machine);
+    assert_eq!((head.pen, step.waiting), (PenDirection::UP, machine.pen_delay));
+    assert_eq!(head.apply(&Command::Tool(3, None), &machine).waiting, 0.0);
+
+    let commands = [Command::Pen(PenDirection::DOWN), Command::Tool(2, None), Command::Move(10.0, 0.0)];
+    let estimate = estimate(&commands, &machine);
+    assert!(close(estimate.pen, 2.0 * machine.pen_delay));
+    assert!(close(estimate.travel_distance, 10.0));
+  }
+
+  #[test]
+  fn envelope_violations() {
+    let envelope = Envelope { min_x: 0.0, min_y: 0.0, max_x: 100.0, max_y: 50.0 };
+    let machine = MachineModel { unit_size: 0.5, envelope: Some(envelope), ..MachineModel::default() };
+    assert!(machine.within_limits(200.0, 100.0));
+    assert!(!machine.within_limits(-1.0, 0.0));
+    assert!(MachineModel::default().within_limits(-1e6, 1e6));
+
+    let violations = validate("PEN DOWN\nMOVE 10 10\nMOVE 250 10\nTAB 10 -2\nRAMP 10 120 -1\nDWELL 1", &machine);
+    assert_eq!(violations.iter().map(|v| v.line).collect::<Vec<_>>(), vec![3, 4, 5]);
+    assert_eq!(violations[0], Violation { line: 3, command: Command::Move(250.0, 10.0), x: 125.0, y: 5.0 });
+  }
+
+  #[test]
+  fn durations() {
+    assert_eq!(format_duration(0.0), "00:00:00");
+    assert_eq!(format_duration(3725.4), "01:02:05");
+    assert_eq!(format_duration(59.6), "00:01:00");
+    assert_eq!(format_duration(-5.0), "00:00:00");
+  }
+}
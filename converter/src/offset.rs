@@ -0,0 +1,359 @@
+// Polygon offsetting, moves contours away from the material to compensate for the width of the cut
+use crate::contour::{self, Contour};
+use crate::fill::{Point, Polygon};
+use crate::path;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// maximum distance between the round joins and their polygonal approximation (px)
+const ARC_TOLERANCE: f32 = 0.05;
+// intersections this close to the end of a segment are snapped to it
+const EPSILON: f32 = 1e-5;
+
+type Segment = (Point, Point);
+
+fn rotate(v: Point, angle: f32) -> Point {
+  let (sin, cos) = angle.sin_cos();
+  (v.0 * cos - v.1 * sin, v.0 * sin + v.1 * cos)
+}
+
+// unit normal pointing away from the material, see Contour
+fn normal(a: Point, b: Point) -> Point {
+  let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+  let length = (dx * dx + dy * dy).sqrt();
+  (dy / length, -dx / length)
+}
+
+// every edge moved by `distance` along its normal, with round joins around the convex corners
+// concave corners are joined through the original vertex, the loops this creates are removed afterwards
+fn raw_offset(points: &[Point], distance: f32) -> Polygon {
+  let n = points.len();
+  let step = (2.0 * (1.0 - ARC_TOLERANCE / distance).clamp(-1.0, 1.0).acos()).clamp(1e-3, PI / 4.0);
+  let mut raw = vec![];
+  for index in 0..n {
+    let (a, b, c) = (points[index], points[(index + 1) % n], points[(index + 2) % n]);
+    let n1 = normal(a, b);
+    raw.push((a.0 + n1.0 * distance, a.1 + n1.1 * distance));
+    raw.push((b.0 + n1.0 * distance, b.1 + n1.1 * distance));
+
+    let (d1, d2) = ((b.0 - a.0, b.1 - a.1), (c.0 - b.0, c.1 - b.1));
+    let turn = (d1.0 * d2.1 - d1.1 * d2.0).atan2(d1.0 * d2.0 + d1.1 * d2.1);
+    if turn > EPSILON {
+      // the offset edges move apart, the gap is closed by an arc around the corner
+      let steps = (turn / step).ceil() as usize;
+      for i in 1..steps {
+        let v = rotate(n1, turn * i as f32 / steps as f32);
+        raw.push((b.0 + v.0 * distance, b.1 + v.1 * distance));
+      }
+    }
+    else if turn < -EPSILON {
+      raw.push(b);
+    }
+  }
+  raw.dedup();
+  raw
+}
+
+// intersection of two segments as (position along the first one, position along the second one, point)
+fn intersection(s: Segment, t: Segment) -> Option<(f32, f32, Point)> {
+  let (r, q) = ((s.1 .0 - s.0 .0, s.1 .1 - s.0 .1), (t.1 .0 - t.0 .0, t.1 .1 - t.0 .1));
+  let denominator = r.0 * q.1 - r.1 * q.0;
+  if denominator.abs() < 1e-9 {
+    return None; // parallel
+  }
+  let w = (t.0 .0 - s.0 .0, t.0 .1 - s.0 .1);
+  let u = (w.0 * q.1 - w.1 * q.0) / denominator;
+  let v = (w.0 * r.1 - w.1 * r.0) / denominator;
+  if !(-EPSILON..=1.0 + EPSILON).contains(&u) || !(-EPSILON..=1.0 + EPSILON).contains(&v) {
+    return None;
+  }
+  // both segments must split at the very same point, so the pieces can be chained back together
+  let point = if u <= EPSILON {
+    s.0
+  }
+  else if u >= 1.0 - EPSILON {
+    s.1
+  }
+  else if v <= EPSILON {
+    t.0
+  }
+  else if v >= 1.0 - EPSILON {
+    t.1
+  }
+  else {
+    (s.0 .0 + u * r.0, s.0 .1 + u * r.1)
+  };
+  Some((u, v, point))
+}
+
+// splits the segments of closed polylines wherever they cross each other
+fn split(polygons: &[Polygon]) -> Vec<Segment> {
+  let mut segments: Vec<Segment> = vec![];
+  // index of the next segment along the same polygon
+  let mut next: Vec<usize> = vec![];
+  for points in polygons {
+    let (first, n) = (segments.len(), points.len());
+    for index in 0..n {
+      segments.push((points[index], points[(index + 1) % n]));
+      next.push(first + (index + 1) % n);
+    }
+  }
+  let n = segments.len();
+  let mut splits: Vec<Vec<(f32, Point)>> = vec![vec![]; n];
+
+  // sweep along x: only segments whose x ranges overlap can cross
+  let range = |s: &Segment| (s.0 .0.min(s.1 .0), s.0 .0.max(s.1 .0));
+  let mut sorted = (0..n).collect::<Vec<usize>>();
+  sorted.sort_unstable_by(|a, b| range(&segments[*a]).0.partial_cmp(&range(&segments[*b]).0).unwrap());
+  for (position, &i) in sorted.iter().enumerate() {
+    let right = range(&segments[i]).1;
+    for &j in &sorted[position + 1..] {
+      if range(&segments[j]).0 > right {
+        break;
+      }
+      let adjacent = next[i] == j || next[j] == i;
+      if adjacent {
+        continue;
+      }
+      if let Some((u, v, point)) = intersection(segments[i], segments[j]) {
+        if u > EPSILON && u < 1.0 - EPSILON {
+          splits[i].push((u, point));
+        }
+        if v > EPSILON && v < 1.0 - EPSILON {
+          splits[j].push((v, point));
+        }
+      }
+    }
+  }
+
+  let mut pieces = vec![];
+  for (segment, mut at) in segments.into_iter().zip(splits) {
+    at.sort_unstable_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let mut start = segment.0;
+    for (_, point) in at.into_iter().chain([(1.0, segment.1)]) {
+      if point != start {
+        pieces.push((start, point));
+        start = point;
+      }
+    }
+  }
+  pieces
+}
+
+fn key(p: Point) -> (u32, u32) {
+  (p.0.to_bits(), p.1.to_bits())
+}
+
+// chains pieces into closed loops, pieces that cannot be closed are dropped
+fn chain(pieces: Vec<Segment>) -> Vec<Polygon> {
+  let mut starting: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+  let mut ending: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+  for (index, piece) in pieces.iter().enumerate() {
+    starting.entry(key(piece.0)).or_default().push(index);
+    ending.entry(key(piece.1)).or_default().push(index);
+  }
+
+  // short pieces right past an intersection are barely closer than the offset and survive the distance test,
+  // they are removed with everything else leading nowhere or coming from nowhere
+  let mut used = vec![false; pieces.len()];
+  let connected = |links: &HashMap<(u32, u32), Vec<usize>>, p: Point, used: &[bool]| {
+    matches!(links.get(&key(p)), Some(links) if links.iter().any(|l| !used[*l]))
+  };
+  let mut dangling = (0..pieces.len()).collect::<Vec<usize>>();
+  while let Some(index) = dangling.pop() {
+    let (start, end) = pieces[index];
+    if used[index] || (connected(&ending, start, &used) && connected(&starting, end, &used)) {
+      continue;
+    }
+    used[index] = true;
+    // the neighbours may be dangling now
+    for links in [starting.get(&key(end)), ending.get(&key(start))].into_iter().flatten() {
+      dangling.extend(links.iter().copied().filter(|l| !used[*l]));
+    }
+  }
+
+  let mut loops = vec![];
+  for first in 0..pieces.len() {
+    if used[first] {
+      continue;
+    }
+    used[first] = true;
+    let mut polygon = vec![pieces[first].0];
+    let mut end = pieces[first].1;
+    let closed = loop {
+      if end == pieces[first].0 {
+        break true;
+      }
+      let next = starting.get(&key(end)).and_then(|candidates| candidates.iter().copied().find(|c| !used[*c]));
+      match next {
+        Some(next) => {
+          used[next] = true;
+          polygon.push(end);
+          end = pieces[next].1;
+        },
+        None => break false,
+      }
+    };
+    if closed && polygon.len() >= 3 {
+      loops.push(polygon);
+    }
+  }
+  loops
+}
+
+// the contour moved `distance` away from the material, outer boundaries grow and holes shrink
+// the offset of a contour is every point at exactly `distance` from it on the side of the cut: the raw offset
+// is split where it crosses itself, and the pieces closer than that to the contour are removed
+// a contour may split into several ones, holes smaller than the offset disappear
+pub fn offset(contour: &Contour, distance: f32) -> Vec<Contour> {
+  if distance <= 0.0 || contour.points.len() < 3 {
+    return vec![contour.clone()];
+  }
+  let raw = raw_offset(&contour.points, distance);
+  let margin = ARC_TOLERANCE + distance * 1e-3;
+  let pieces = split(&[raw])
+    .into_iter()
+    .filter(|(a, b)| {
+      let middle = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+      contour::distance_to(middle, &contour.points) >= distance - margin
+    })
+    .collect::<Vec<Segment>>();
+
+  chain(pieces)
+    .into_iter()
+    .filter(|points| contour::signed_area(points).abs() > ARC_TOLERANCE)
+    .map(Contour::new)
+    .collect()
+}
+
+// the region enclosed by the contours (even-odd rule) shrunk by `distance`: outer boundaries move towards the
+// material and holes away from it, the contours are the ones of the region itself and not of the cut around it
+// the offsets of all the contours are split where they cross each other, and the pieces closer than that to
+// any contour, or outside of the region, are removed
+pub fn inset(contours: &[Contour], distance: f32) -> Vec<Contour> {
+  if distance <= 0.0 {
+    return contours.to_vec();
+  }
+  // reversed contours have the material on the other side
+  let reversed = contours
+    .iter()
+    .filter(|c| c.points.len() >= 3)
+    .map(|c| c.points.iter().rev().copied().collect())
+    .collect::<Vec<Polygon>>();
+  let raw = reversed.iter().map(|points| raw_offset(points, distance)).collect::<Vec<Polygon>>();
+  let bounds = reversed.iter().map(|points| path::bounds(points)).collect::<Vec<(Point, Point)>>();
+
+  let margin = ARC_TOLERANCE + distance * 1e-3;
+  let pieces = split(&raw)
+    .into_iter()
+    .filter(|(a, b)| {
+      let middle = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+      let mut inside = false;
+      for (points, (low, high)) in reversed.iter().zip(&bounds) {
+        // contours further away than the bounding box cannot be closer than the offset
+        if middle.0 < low.0 - distance || middle.1 < low.1 - distance || middle.0 > high.0 + distance || middle.1 > high.1 + distance {
+          continue;
+        }
+        if contour::distance_to(middle, points) < distance - margin {
+          return false;
+        }
+        if contour::contains(points, middle) {
+          inside = !inside;
+        }
+      }
+      inside
+    })
+    .collect::<Vec<Segment>>();
+
+  chain(pieces)
+    .into_iter()
+    .filter(|points| contour::signed_area(points).abs() > ARC_TOLERANCE)
+    .map(|points| Contour::new(points.into_iter().rev().collect()))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::contour::signed_area;
+
+  fn square(from: f32, to: f32) -> Polygon {
+    vec![(from, from), (to, from), (to, to), (from, to)]
+  }
+
+  fn hole(from: f32, to: f32) -> Contour {
+    Contour::new(square(from, to).into_iter().rev().collect())
+  }
+
+  // every point of the offset is `distance` away from the contour, give or take the arc tolerance
+  fn at_distance(offset: &Contour, contour: &Contour, distance: f32) -> bool {
+    offset.points.iter().all(|p| (contour::distance_to(*p, &contour.points) - distance).abs() <= ARC_TOLERANCE + 1e-3)
+  }
+
+  // area of `straight` with round corners of radius 1, the arcs are approximated by chords
+  fn rounded(area: f32, straight: f32) -> bool {
+    area > straight + PI - 0.2 && area < straight + PI
+  }
+
+  #[test]
+  fn outer_contours_grow() {
+    let contour = Contour::new(square(0.0, 10.0));
+    let grown = offset(&contour, 1.0);
+    assert_eq!(grown.len(), 1);
+    assert!(!grown[0].hole);
+    // rounded corners
+    assert!(rounded(signed_area(&grown[0].points), 140.0));
+    assert!(at_distance(&grown[0], &contour, 1.0));
+    assert_eq!(offset(&contour, 0.0), vec![contour]);
+  }
+
+  #[test]
+  fn holes_shrink() {
+    let contour = hole(0.0, 10.0);
+    let shrunk = offset(&contour, 1.0);
+    assert_eq!(shrunk.len(), 1);
+    assert!(shrunk[0].hole);
+    // sharp corners inside the hole
+    assert!((signed_area(&shrunk[0].points) + 64.0).abs() < 1e-3);
+    assert!(at_distance(&shrunk[0], &contour, 1.0));
+    // smaller than the cut
+    assert!(offset(&contour, 6.0).is_empty());
+  }
+
+  #[test]
+  fn concave_contours() {
+    // a U whose gap closes once offset
+    let contour = Contour::new(vec![(0.0, 0.0), (30.0, 0.0), (30.0, 30.0), (20.0, 30.0), (20.0, 10.0), (10.0, 10.0), (10.0, 30.0), (0.0, 30.0)]);
+    let open = offset(&contour, 2.0);
+    assert_eq!(open.len(), 1);
+    assert!(at_distance(&open[0], &contour, 2.0));
+    // the offsets of both arms merge over the gap
+    let closed = offset(&contour, 6.0);
+    assert_eq!(closed.len(), 1);
+    assert!(!closed[0].hole && at_distance(&closed[0], &contour, 6.0));
+    assert!(closed[0].points.iter().all(|p| !(p.0 > 10.0 && p.0 < 20.0 && p.1 > 10.0 && p.1 < 33.0)));
+
+    // a hole shaped like an hourglass splits in two
+    let hourglass = Contour::new(vec![(0.0, 0.0), (0.0, 20.0), (9.0, 11.0), (18.0, 20.0), (18.0, 0.0), (9.0, 9.0)]);
+    assert!(hourglass.hole);
+    assert_eq!(offset(&hourglass, 2.0).len(), 2);
+  }
+
+  #[test]
+  fn insets() {
+    let square_inset = inset(&[Contour::new(square(0.0, 10.0))], 1.0);
+    assert_eq!(square_inset.len(), 1);
+    assert!((signed_area(&square_inset[0].points) - 64.0).abs() < 1e-3);
+
+    // a frame, its holes grow as its outside shrinks
+    let frame = [Contour::new(square(0.0, 20.0)), hole(5.0, 15.0)];
+    let mut inset = inset(&frame, 1.0);
+    inset.sort_by(|a, b| signed_area(&b.points).partial_cmp(&signed_area(&a.points)).unwrap());
+    assert_eq!(inset.len(), 2);
+    assert!((signed_area(&inset[0].points) - 324.0).abs() < 1e-3);
+    assert!(inset[1].hole);
+    assert!(rounded(-signed_area(&inset[1].points), 140.0));
+    // too thin for the tool
+    assert!(super::inset(&frame, 3.0).is_empty());
+  }
+}
@@ -0,0 +1,272 @@
+// Cut ordering: holes and inner features are cut before the contours around them
+use crate::canny::{Coordinate, SEdge, SEdges};
+use crate::contour;
+use crate::fill::{Point, Polyline};
+use crate::path::{bounds, distance};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CutOrder {
+  // edges are drawn in the order they were traced
+  Traced,
+  // children of the containment tree are cut before their parent, siblings by nearest neighbour
+  InsideOut,
+}
+
+struct Node {
+  points: Polyline,
+  // first point repeated at the end
+  closed: bool,
+  // closed, or ending close enough to its start to be a contour around the edges inside of it
+  encloses: bool,
+  // absolute area of the edges enclosing one
+  area: f32,
+  // bounding box, as (min, max)
+  bounds: (Point, Point),
+}
+
+// containment tree over the edges: the parent of an edge is the smallest closed edge around its first point
+// open edges are always leaves, unless they nearly close
+pub struct ContainmentTree {
+  nodes: Vec<Node>,
+  pub parents: Vec<Option<usize>>,
+  pub children: Vec<Vec<usize>>,
+  pub roots: Vec<usize>,
+}
+
+impl ContainmentTree {
+  pub fn new(edges: &[Polyline]) -> ContainmentTree {
+    let nodes = edges
+      .iter()
+      .map(|points| {
+        let closed = points.len() >= 4 && points[0] == points[points.len() - 1];
+        let encloses = closed || nearly_closed(points);
+        let area = if encloses { contour::signed_area(&points[..points.len() - closed as usize]).abs() } else { 0.0 };
+        Node { points: points.clone(), closed, encloses, area, bounds: bounds(points) }
+      })
+      .collect::<Vec<Node>>();
+
+    let mut parents = vec![None; nodes.len()];
+    for (index, node) in nodes.iter().enumerate() {
+      let p = match node.points.first() {
+        Some(p) => *p,
+        None => continue,
+      };
+      let mut parent: Option<usize> = None;
+      for (other, candidate) in nodes.iter().enumerate() {
+        let (low, high) = candidate.bounds;
+        if other == index || !candidate.encloses || candidate.area <= node.area {
+          continue;
+        }
+        if p.0 < low.0 || p.1 < low.1 || p.0 > high.0 || p.1 > high.1 {
+          continue;
+        }
+        let smaller = match parent {
+          Some(parent) => candidate.area < nodes[parent].area,
+          None => true,
+        };
+        if smaller && contour::contains(&candidate.points, p) {
+          parent = Some(other);
+        }
+      }
+      parents[index] = parent;
+    }
+
+    let mut children = vec![vec![]; nodes.len()];
+    let mut roots = vec![];
+    for (index, parent) in parents.iter().enumerate() {
+      match parent {
+        Some(parent) => children[*parent].push(index),
+        None => roots.push(index),
+      }
+    }
+
+    ContainmentTree { nodes, parents, children, roots }
+  }
+
+  // number of closed edges around an edge: parts are at even depths, holes at odd ones
+  pub fn depth(&self, index: usize) -> usize {
+    let mut depth = 0;
+    let mut parent = self.parents[index];
+    while let Some(index) = parent {
+      depth += 1;
+      parent = self.parents[index];
+    }
+    depth
+  }
+
+  // holes are inside of parts, islands inside of holes are parts again
+  pub fn is_hole(&self, index: usize) -> bool {
+    self.depth(index) % 2 == 1
+  }
+
+  // closest point of an edge where cutting may start: any vertex of a closed edge, either end of an open one
+  fn entry(&self, index: usize, from: Point) -> (usize, f32) {
+    let node = &self.nodes[index];
+    if node.points.is_empty() {
+      return (0, f32::MAX);
+    }
+    if !node.closed {
+      let last = node.points.len() - 1;
+      let (start, end) = (distance(from, node.points[0]), distance(from, node.points[last]));
+      return if end < start { (last, end) } else { (0, start) };
+    }
+    node.points[..node.points.len() - 1]
+      .iter()
+      .enumerate()
+      .map(|(vertex, p)| (vertex, distance(from, *p)))
+      .fold((0, f32::MAX), |best, current| if current.1 < best.1 { current } else { best })
+  }
+
+  // cuts the given siblings nearest first, each one after everything it contains
+  // the plan holds (edge, entry vertex) pairs
+  fn visit(&self, siblings: &[usize], position: &mut Point, plan: &mut Vec<(usize, usize)>) {
+    let mut remaining = siblings.to_vec();
+    while !remaining.is_empty() {
+      let (at, _) = remaining
+        .iter()
+        .enumerate()
+        .map(|(at, index)| (at, self.entry(*index, *position).1))
+        .fold((0, f32::MAX), |best, current| if current.1 < best.1 { current } else { best });
+      let index = remaining.swap_remove(at);
+
+      self.visit(&self.children[index], position, plan);
+      let (vertex, _) = self.entry(index, *position);
+      let node = &self.nodes[index];
+      if !node.points.is_empty() {
+        // closed edges end where they start, open ones at their other end
+        *position = if node.closed {
+          node.points[vertex]
+        }
+        else if vertex == 0 {
+          node.points[node.points.len() - 1]
+        }
+        else {
+          node.points[0]
+        };
+        plan.push((index, vertex));
+      }
+    }
+  }
+
+  // cutting order starting from the origin, as (edge, entry vertex) pairs
+  pub fn plan(&self) -> Vec<(usize, usize)> {
+    let mut plan = Vec::with_capacity(self.nodes.len());
+    self.visit(&self.roots, &mut (0.0, 0.0), &mut plan);
+    plan
+  }
+}
+
+// the edge started at the given vertex: closed edges are rotated, open ones reversed when entered from their end
+fn enter<T: Coordinate>(edge: &SEdge<T>, vertex: usize, closed: bool) -> SEdge<T> {
+  if vertex == 0 {
+    return edge.clone();
+  }
+  if !closed {
+    return edge.iter().rev().copied().collect();
+  }
+  let mut rotated = edge[vertex..edge.len() - 1].to_vec();
+  rotated.extend_from_slice(&edge[..=vertex]);
+  rotated
+}
+
+// traced edges rarely end on the pixel they started from: ends this close still enclose the edges inside (px)
+const CLOSING_TOLERANCE: f32 = 5.0;
+
+// an open edge almost returning to its start, it is ordered like a closed edge but still cut from one end to the other,
+// the gap is not cut as it is not in the image
+// short edges stay open, their ends are close because the whole edge is small
+fn nearly_closed(points: &[Point]) -> bool {
+  let (first, last) = match (points.first(), points.last()) {
+    (Some(first), Some(last)) => (*first, *last),
+    _ => return false,
+  };
+  let length = points.windows(2).map(|pair| distance(pair[0], pair[1])).sum::<f32>();
+  let gap = distance(first, last);
+  points.len() >= 3 && gap > 0.0 && gap <= CLOSING_TOLERANCE && length > 4.0 * CLOSING_TOLERANCE
+}
+
+pub fn order_edges<T: Coordinate>(edges: SEdges<T>, order: CutOrder) -> SEdges<T> {
+  if order == CutOrder::Traced {
+    return edges;
+  }
+  let polylines = edges
+    .iter()
+    .map(|edge| edge.iter().map(|p| (p.x.to_f32(), p.y.to_f32())).collect())
+    .collect::<Vec<Polyline>>();
+  let tree = ContainmentTree::new(&polylines);
+  tree
+    .plan()
+    .into_iter()
+    .map(|(index, vertex)| enter(&edges[index], vertex, tree.nodes[index].closed))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::canny::SPixelIndex;
+
+  fn square(from: f32, to: f32) -> Polyline {
+    vec![(from, from), (to, from), (to, to), (from, to), (from, from)]
+  }
+
+  // a part with a hole, an island in the hole and an open edge, next to a second part
+  fn drawing() -> Vec<Polyline> {
+    vec![square(0.0, 50.0), square(10.0, 30.0), square(15.0, 25.0), vec![(45.0, 45.0), (40.0, 40.0)], square(60.0, 90.0)]
+  }
+
+  fn edges(polylines: &[Polyline]) -> SEdges<f32> {
+    polylines.iter().map(|points| points.iter().map(|(x, y)| SPixelIndex { x: *x, y: *y }).collect()).collect()
+  }
+
+  fn polylines(edges: &SEdges<f32>) -> Vec<Polyline> {
+    edges.iter().map(|edge| edge.iter().map(|p| (p.x, p.y)).collect()).collect()
+  }
+
+  #[test]
+  fn containment() {
+    let tree = ContainmentTree::new(&drawing());
+    assert_eq!(tree.parents, vec![None, Some(0), Some(1), Some(0), None]);
+    assert_eq!(tree.roots, vec![0, 4]);
+    assert_eq!(tree.children[0], vec![1, 3]);
+    assert_eq!((0..5).map(|index| tree.depth(index)).collect::<Vec<_>>(), vec![0, 1, 2, 1, 0]);
+    assert_eq!((0..5).map(|index| tree.is_hole(index)).collect::<Vec<_>>(), vec![false, true, false, true, false]);
+    // open edges contain nothing
+    let tree = ContainmentTree::new(&[vec![(0.0, 0.0), (100.0, 0.0), (100.0, 100.0)], square(10.0, 20.0)]);
+    assert_eq!(tree.parents, vec![None, None]);
+  }
+
+  #[test]
+  fn inside_out_plan() {
+    // the island, then its hole, the open edge from its closest end, the part from its closest corner, and the next part
+    assert_eq!(ContainmentTree::new(&drawing()).plan(), vec![(2, 0), (1, 0), (3, 1), (0, 2), (4, 0)]);
+  }
+
+  #[test]
+  fn ordered_edges() {
+    let ordered = polylines(&order_edges(edges(&drawing()), CutOrder::InsideOut));
+    assert_eq!(ordered[0], square(15.0, 25.0));
+    assert_eq!(ordered[2], vec![(40.0, 40.0), (45.0, 45.0)]);
+    // entered at its corner closest to the end of the open edge
+    assert_eq!(ordered[3], vec![(50.0, 50.0), (0.0, 50.0), (0.0, 0.0), (50.0, 0.0), (50.0, 50.0)]);
+    assert_eq!(polylines(&order_edges(edges(&drawing()), CutOrder::Traced)), drawing());
+  }
+
+  #[test]
+  fn nearly_closed_edges() {
+    // a part ending a pixel away from its start, around a hole
+    let part = vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0), (0.0, 1.0)];
+    let tree = ContainmentTree::new(&[part.clone(), square(5.0, 15.0)]);
+    assert_eq!(tree.parents, vec![None, Some(0)]);
+    assert!(tree.is_hole(1));
+    // the hole first, then the part from its closer end, without cutting the gap
+    let ordered = polylines(&order_edges(edges(&[part.clone(), square(5.0, 15.0)]), CutOrder::InsideOut));
+    assert_eq!(ordered, vec![square(5.0, 15.0), part.into_iter().rev().collect()]);
+
+    // too short to enclose anything
+    let short = vec![(0.0, 0.0), (3.0, 0.0), (3.0, 3.0), (0.0, 2.0)];
+    assert!(!nearly_closed(&short));
+    assert!(!nearly_closed(&square(0.0, 20.0)));
+    assert_eq!(ContainmentTree::new(&[short, vec![(1.0, 1.0), (2.0, 1.0)]]).parents, vec![None, None]);
+  }
+}
@@ -0,0 +1,210 @@
+// Path optimisation: orders strokes to shorten the moves performed with the pen up
+use crate::fill::{Point, Polyline};
+
+pub fn distance(a: Point, b: Point) -> f32 {
+  ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+// lowest and highest corners of the box around the points, inverted when there are none
+pub fn bounds(points: &[Point]) -> (Point, Point) {
+  points.iter().fold(((f32::MAX, f32::MAX), (f32::MIN, f32::MIN)), |(low, high), p| {
+    ((low.0.min(p.0), low.1.min(p.1)), (high.0.max(p.0), high.1.max(p.1)))
+  })
+}
+
+// points bucketed on a uniform grid, so the nearest one is found without checking all of them
+pub struct PointGrid {
+  points: Vec<Point>,
+  cell: f32,
+  origin: Point,
+  columns: usize,
+  rows: usize,
+  cells: Vec<Vec<usize>>,
+}
+
+impl PointGrid {
+  // a cell size around the average distance between points keeps a few points per cell
+  pub fn new(points: Vec<Point>, cell: f32) -> PointGrid {
+    let cell = cell.max(1e-3);
+    let (low, high) = if points.is_empty() { ((0.0, 0.0), (0.0, 0.0)) } else { bounds(&points) };
+    let columns = ((high.0 - low.0) / cell).floor() as usize + 1;
+    let rows = ((high.1 - low.1) / cell).floor() as usize + 1;
+
+    let mut grid = PointGrid {
+      points: vec![],
+      cell,
+      origin: low,
+      columns,
+      rows,
+      cells: vec![vec![]; columns * rows],
+    };
+    for (index, p) in points.iter().enumerate() {
+      let (x, y) = grid.cell_of(*p);
+      grid.cells[y * columns + x].push(index);
+    }
+    grid.points = points;
+    grid
+  }
+
+  // average spacing of `count` points spread over the given area
+  pub fn cell_for(width: f32, height: f32, count: usize) -> f32 {
+    (width.max(1.0) * height.max(1.0) / count.max(1) as f32).sqrt()
+  }
+
+  pub fn point(&self, index: usize) -> Point {
+    self.points[index]
+  }
+
+  // cell of a point, clamped to the grid
+  fn cell_of(&self, p: Point) -> (usize, usize) {
+    let x = ((p.0 - self.origin.0) / self.cell).floor().max(0.0) as usize;
+    let y = ((p.1 - self.origin.1) / self.cell).floor().max(0.0) as usize;
+    (x.min(self.columns - 1), y.min(self.rows - 1))
+  }
+
+  pub fn remove(&mut self, index: usize) {
+    let (x, y) = self.cell_of(self.points[index]);
+    self.cells[y * self.columns + x].retain(|i| *i != index);
+  }
+
+  // nearest point, searching rings of cells around `from` until no closer one can exist
+  pub fn nearest(&self, from: Point) -> Option<usize> {
+    self.nearest_k(from, 1).first().copied()
+  }
+
+  // the k nearest points, closest first
+  pub fn nearest_k(&self, from: Point, k: usize) -> Vec<usize> {
+    if k == 0 {
+      return vec![];
+    }
+    let (cx, cy) = self.cell_of(from);
+    let (cx, cy) = (cx as i64, cy as i64);
+    // points outside of the grid are further away from every cell
+    let outside = {
+      let dx = (self.origin.0 - from.0).max(from.0 - (self.origin.0 + self.columns as f32 * self.cell)).max(0.0);
+      let dy = (self.origin.1 - from.1).max(from.1 - (self.origin.1 + self.rows as f32 * self.cell)).max(0.0);
+      dx.max(dy)
+    };
+    let reach = cx.max(self.columns as i64 - 1 - cx).max(cy).max(self.rows as i64 - 1 - cy);
+
+    let mut best: Vec<(usize, f32)> = vec![];
+    for ring in 0..=reach {
+      if best.len() == k && best[k - 1].1 < outside.max((ring - 1).max(0) as f32 * self.cell) {
+        break;
+      }
+      for y in (cy - ring).max(0)..=(cy + ring).min(self.rows as i64 - 1) {
+        for x in (cx - ring).max(0)..=(cx + ring).min(self.columns as i64 - 1) {
+          if (x - cx).abs() != ring && (y - cy).abs() != ring {
+            continue; // inner cells were already checked
+          }
+          for &index in &self.cells[y as usize * self.columns + x as usize] {
+            let d = distance(from, self.points[index]);
+            if best.len() < k || d < best[k - 1].1 {
+              let at = best.iter().position(|(_, best_d)| d < *best_d).unwrap_or(best.len());
+              best.insert(at, (index, d));
+              best.truncate(k);
+            }
+          }
+        }
+      }
+    }
+    best.into_iter().map(|(index, _)| index).collect()
+  }
+}
+
+// greedy nearest neighbour ordering, starting from the origin
+// a stroke is reversed when its end is closer than its start
+pub fn order_strokes(strokes: Vec<Polyline>) -> Vec<Polyline> {
+  let strokes = strokes.into_iter().filter(|s| !s.is_empty()).collect::<Vec<Polyline>>();
+  if strokes.is_empty() {
+    return strokes;
+  }
+
+  // endpoint 2 * i is the start of the i-th stroke, 2 * i + 1 its end
+  let endpoints = strokes.iter().flat_map(|s| [s[0], s[s.len() - 1]]).collect::<Vec<Point>>();
+  let (low, high) = bounds(&endpoints);
+  let cell = PointGrid::cell_for(high.0 - low.0, high.1 - low.1, endpoints.len());
+  let mut grid = PointGrid::new(endpoints, cell);
+
+  let mut ordered = Vec::with_capacity(strokes.len());
+  let mut position = (0.0, 0.0);
+  while let Some(endpoint) = grid.nearest(position) {
+    let index = endpoint / 2;
+    grid.remove(2 * index);
+    grid.remove(2 * index + 1);
+
+    let mut stroke = strokes[index].clone();
+    if endpoint % 2 == 1 {
+      stroke.reverse();
+    }
+    position = stroke[stroke.len() - 1];
+    ordered.push(stroke);
+  }
+  ordered
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::rngs::StdRng;
+  use rand::{Rng, SeedableRng};
+
+  fn random_points(count: usize) -> Vec<Point> {
+    let mut rng = StdRng::seed_from_u64(7);
+    (0..count).map(|_| (rng.gen_range(-50.0..150.0), rng.gen_range(0.0..60.0))).collect()
+  }
+
+  #[test]
+  fn nearest_points() {
+    let points = random_points(500);
+    let mut grid = PointGrid::new(points.clone(), 5.0);
+    let brute = |from: Point, skip: &[usize]| {
+      let mut indices = (0..points.len()).filter(|i| !skip.contains(i)).collect::<Vec<usize>>();
+      indices.sort_by(|a, b| distance(from, points[*a]).partial_cmp(&distance(from, points[*b])).unwrap());
+      indices
+    };
+    // from inside and far outside of the grid
+    for from in [(10.0, 10.0), (149.0, 0.0), (-500.0, 30.0), (60.0, 400.0)] {
+      assert_eq!(grid.nearest(from), Some(brute(from, &[])[0]));
+      assert_eq!(grid.nearest_k(from, 5), brute(from, &[])[..5]);
+    }
+
+    let removed = brute((10.0, 10.0), &[])[..3].to_vec();
+    for index in &removed {
+      grid.remove(*index);
+    }
+    assert_eq!(grid.nearest((10.0, 10.0)), Some(brute((10.0, 10.0), &removed)[0]));
+    assert!(grid.nearest_k((0.0, 0.0), 0).is_empty());
+    assert_eq!(PointGrid::new(vec![], 1.0).nearest((0.0, 0.0)), None);
+  }
+
+  #[test]
+  fn strokes_in_order() {
+    let strokes = vec![
+      vec![(50.0, 0.0), (60.0, 0.0)],
+      vec![],
+      // reversed, its end is closer to the origin
+      vec![(20.0, 0.0), (1.0, 0.0)],
+      vec![(45.0, 5.0), (30.0, 0.0)],
+    ];
+    assert_eq!(
+      order_strokes(strokes),
+      vec![vec![(1.0, 0.0), (20.0, 0.0)], vec![(30.0, 0.0), (45.0, 5.0)], vec![(50.0, 0.0), (60.0, 0.0)]]
+    );
+    assert!(order_strokes(vec![vec![]]).is_empty());
+  }
+
+  #[test]
+  fn ordering_keeps_every_stroke() {
+    let strokes = random_points(200).chunks(2).map(|pair| pair.to_vec()).collect::<Vec<Polyline>>();
+    let ordered = order_strokes(strokes.clone());
+    assert_eq!(ordered.len(), strokes.len());
+    for stroke in &strokes {
+      let reversed = stroke.iter().rev().copied().collect::<Polyline>();
+      assert!(ordered.contains(stroke) || ordered.contains(&reversed));
+    }
+    // shorter travels than in the original order
+    let travel = |strokes: &[Polyline]| strokes.windows(2).map(|pair| distance(pair[0][pair[0].len() - 1], pair[1][0])).sum::<f32>();
+    assert!(travel(&ordered) < travel(&strokes) / 2.0);
+  }
+}
@@ -0,0 +1,138 @@
+// Pocketing: clears the inside of closed regions, for engraving and milling instead of cutting out
+use crate::contour::Contour;
+use crate::fill::{self, Polygon, Polyline};
+use crate::offset;
+use crate::path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PocketMode {
+  // rings parallel to the contours, from the middle of the pocket outwards
+  Offset,
+  // parallel lines joined into zig-zag strokes, followed by a ring along the contours
+  ZigZag,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PocketOptions {
+  pub mode: PocketMode,
+  // distance between neighbouring passes of the tool (mm)
+  pub step_over: f32,
+  // direction of the zig-zag lines, measured from the x axis towards the y axis (degrees)
+  pub angle: f32,
+}
+
+impl Default for PocketOptions {
+  fn default() -> Self {
+    PocketOptions {
+      mode: PocketMode::Offset,
+      step_over: 1.0,
+      angle: 0.0,
+    }
+  }
+}
+
+// the ring of each contour, closed by repeating its first point
+fn rings(contours: &[Contour]) -> Vec<Polyline> {
+  contours.iter().map(|c| c.closed()).collect()
+}
+
+// toolpaths clearing the regions enclosed by the contours (even-odd rule), holes are left untouched
+// the center of the tool stays `radius` away from the contours, both are given in command units
+// returns the passes clearing the inside, then the rings finishing along the contours
+pub fn pocket(contours: &[Contour], options: &PocketOptions, radius: f32, unit_size: f32) -> (Vec<Polyline>, Vec<Polyline>) {
+  let step_over = options.step_over / unit_size;
+  let boundary = offset::inset(contours, radius);
+  if boundary.is_empty() || step_over <= 0.0 {
+    return (vec![], rings(&boundary));
+  }
+
+  let clearing = match options.mode {
+    PocketMode::Offset => {
+      let mut levels = vec![];
+      loop {
+        let inset = offset::inset(contours, radius + step_over * (levels.len() + 1) as f32);
+        if inset.is_empty() {
+          break;
+        }
+        levels.push(rings(&inset));
+      }
+      // the innermost rings first, working outwards
+      levels.into_iter().rev().flatten().collect()
+    },
+    PocketMode::ZigZag => {
+      let polygons = boundary.iter().map(|c| c.points.clone()).collect::<Vec<Polygon>>();
+      path::order_strokes(fill::hatch(&fill::polygon_boundary(&polygons), step_over, options.angle))
+    },
+  };
+  (clearing, rings(&boundary))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::contour::signed_area;
+
+  fn square(from: f32, to: f32) -> Contour {
+    Contour::new(vec![(from, from), (to, from), (to, to), (from, to)])
+  }
+
+  fn hole(from: f32, to: f32) -> Contour {
+    Contour::new(square(from, to).points.into_iter().rev().collect())
+  }
+
+  fn area(ring: &Polyline) -> f32 {
+    signed_area(&ring[..ring.len() - 1]).abs()
+  }
+
+  #[test]
+  fn offset_rings_work_outwards() {
+    let options = PocketOptions { step_over: 2.0, ..PocketOptions::default() };
+    let (clearing, finishing) = pocket(&[square(0.0, 20.0)], &options, 1.0, 1.0);
+    assert_eq!(clearing.iter().map(area).collect::<Vec<_>>(), vec![4.0, 36.0, 100.0, 196.0]);
+    assert!(clearing.iter().all(|ring| ring[0] == ring[ring.len() - 1]));
+    assert_eq!(finishing.len(), 1);
+    assert_eq!(area(&finishing[0]), 324.0);
+
+    // the step over is in mm
+    let (clearing, _) = pocket(&[square(0.0, 20.0)], &options, 1.0, 0.5);
+    assert_eq!(clearing.len(), 2);
+  }
+
+  #[test]
+  fn zig_zag() {
+    let options = PocketOptions { mode: PocketMode::ZigZag, step_over: 2.0, angle: 0.0 };
+    let (clearing, finishing) = pocket(&[square(0.0, 20.0)], &options, 1.0, 1.0);
+    assert_eq!(clearing.len(), 1);
+    assert_eq!(clearing[0].len(), 2 * 9);
+    assert!(clearing[0].iter().all(|p| p.0 >= 1.0 && p.0 <= 19.0 && p.1 >= 2.0 && p.1 <= 18.0));
+    assert_eq!(area(&finishing[0]), 324.0);
+  }
+
+  #[test]
+  fn holes_are_left_untouched() {
+    let frame = [square(0.0, 40.0), hole(15.0, 25.0)];
+    for mode in [PocketMode::Offset, PocketMode::ZigZag] {
+      let options = PocketOptions { mode, step_over: 1.5, angle: 30.0 };
+      let (clearing, finishing) = pocket(&frame, &options, 1.0, 1.0);
+      assert!(!clearing.is_empty());
+      // the tool stays a radius away from the hole, its rounded corners included
+      let clearance = |p: &(f32, f32)| {
+        let (dx, dy) = ((15.0 - p.0).max(p.0 - 25.0).max(0.0), (15.0 - p.1).max(p.1 - 25.0).max(0.0));
+        dx.hypot(dy)
+      };
+      assert!(clearing.iter().flatten().all(|p| clearance(p) > 0.95));
+      assert_eq!(finishing.len(), 2);
+    }
+  }
+
+  #[test]
+  fn nothing_to_clear() {
+    // the tool does not fit
+    assert_eq!(pocket(&[square(0.0, 2.0)], &PocketOptions::default(), 1.5, 1.0), (vec![], vec![]));
+    // without a step over, only the contours are followed
+    let options = PocketOptions { step_over: 0.0, ..PocketOptions::default() };
+    let (clearing, finishing) = pocket(&[square(0.0, 20.0)], &options, 1.0, 1.0);
+    assert!(clearing.is_empty());
+    assert_eq!(finishing.len(), 1);
+  }
+}
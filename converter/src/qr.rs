@@ -0,0 +1,623 @@
+// QR code encoder (ISO/IEC 18004): the data is encoded in a single numeric, alphanumeric or byte segment,
+// in the smallest version holding it at the requested error correction level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EcLevel {
+  // about 7% of the codewords can be restored
+  L,
+  // 15%
+  M,
+  // 25%
+  Q,
+  // 30%
+  H,
+}
+
+impl EcLevel {
+  fn index(self) -> usize {
+    match self {
+      EcLevel::L => 0,
+      EcLevel::M => 1,
+      EcLevel::Q => 2,
+      EcLevel::H => 3,
+    }
+  }
+
+  // the two bits of the format information
+  fn format_bits(self) -> u32 {
+    match self {
+      EcLevel::L => 1,
+      EcLevel::M => 0,
+      EcLevel::Q => 3,
+      EcLevel::H => 2,
+    }
+  }
+}
+
+// error correction codewords of each block, by level and version (index 0 is unused)
+const ECC_CODEWORDS_PER_BLOCK: [[u8; 41]; 4] = [
+  [0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+  [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28],
+  [0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30, 30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+  [0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+];
+
+// error correction blocks, by level and version (index 0 is unused)
+const ERROR_CORRECTION_BLOCKS: [[u8; 41]; 4] = [
+  [0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13, 14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25],
+  [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49],
+  [0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29, 34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68],
+  [0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32, 35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81],
+];
+
+const ALPHANUMERIC: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+// penalty weights of the mask evaluation
+const PENALTY_RUN: usize = 3;
+const PENALTY_BLOCK: usize = 3;
+const PENALTY_FINDER: usize = 40;
+const PENALTY_BALANCE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+  Numeric,
+  Alphanumeric,
+  Byte,
+}
+
+impl Mode {
+  // the most compact mode holding every character
+  fn of(data: &[u8]) -> Mode {
+    if data.iter().all(u8::is_ascii_digit) {
+      Mode::Numeric
+    }
+    else if data.iter().all(|b| ALPHANUMERIC.as_bytes().contains(b)) {
+      Mode::Alphanumeric
+    }
+    else {
+      Mode::Byte
+    }
+  }
+
+  fn indicator(self) -> u32 {
+    match self {
+      Mode::Numeric => 0b0001,
+      Mode::Alphanumeric => 0b0010,
+      Mode::Byte => 0b0100,
+    }
+  }
+
+  fn count_bits(self, version: usize) -> usize {
+    let range = match version {
+      1..=9 => 0,
+      10..=26 => 1,
+      _ => 2,
+    };
+    match self {
+      Mode::Numeric => [10, 12, 14][range],
+      Mode::Alphanumeric => [9, 11, 13][range],
+      Mode::Byte => [8, 16, 16][range],
+    }
+  }
+
+  fn data_bits(self, length: usize) -> usize {
+    match self {
+      Mode::Numeric => length / 3 * 10 + [0, 4, 7][length % 3],
+      Mode::Alphanumeric => length / 2 * 11 + length % 2 * 6,
+      Mode::Byte => length * 8,
+    }
+  }
+}
+
+struct BitBuffer {
+  bits: Vec<bool>,
+}
+
+impl BitBuffer {
+  fn push(&mut self, value: u32, length: usize) {
+    self.bits.extend((0..length).rev().map(|i| (value >> i) & 1 == 1));
+  }
+
+  fn bytes(&self) -> Vec<u8> {
+    self.bits.chunks(8).map(|chunk| chunk.iter().enumerate().fold(0u8, |byte, (i, bit)| byte | ((*bit as u8) << (7 - i)))).collect()
+  }
+}
+
+// modules available to the data and error correction codewords, remainder bits included
+fn raw_data_modules(version: usize) -> usize {
+  let mut modules = (16 * version + 128) * version + 64;
+  if version >= 2 {
+    let alignments = version / 7 + 2;
+    modules -= (25 * alignments - 10) * alignments - 55;
+    if version >= 7 {
+      modules -= 36;
+    }
+  }
+  modules
+}
+
+fn data_codewords(version: usize, level: EcLevel) -> usize {
+  let level = level.index();
+  raw_data_modules(version) / 8 - ECC_CODEWORDS_PER_BLOCK[level][version] as usize * ERROR_CORRECTION_BLOCKS[level][version] as usize
+}
+
+// centers of the alignment patterns along each axis
+fn alignment_positions(version: usize) -> Vec<usize> {
+  if version == 1 {
+    return vec![];
+  }
+  let count = version / 7 + 2;
+  let size = version * 4 + 17;
+  let step = if version == 32 { 26 } else { (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2 };
+  let mut positions = (0..count - 1).map(|i| size - 7 - i * step).collect::<Vec<usize>>();
+  positions.push(6);
+  positions.reverse();
+  positions
+}
+
+// arithmetic in GF(256), with the polynomial x^8 + x^4 + x^3 + x^2 + 1
+fn multiply(a: u8, b: u8) -> u8 {
+  let mut product: u16 = 0;
+  for i in (0..8).rev() {
+    product = (product << 1) ^ ((product >> 7) * 0x11d);
+    product ^= ((b >> i) & 1) as u16 * a as u16;
+  }
+  product as u8
+}
+
+// coefficients of the Reed-Solomon generator polynomial of the given degree, the leading 1 left out
+fn generator(degree: usize) -> Vec<u8> {
+  let mut coefficients = vec![0u8; degree];
+  coefficients[degree - 1] = 1;
+  let mut root = 1u8;
+  for _ in 0..degree {
+    for j in 0..degree {
+      coefficients[j] = multiply(coefficients[j], root);
+      if j + 1 < degree {
+        coefficients[j] ^= coefficients[j + 1];
+      }
+    }
+    root = multiply(root, 0x02);
+  }
+  coefficients
+}
+
+// remainder of the data divided by the generator polynomial
+fn remainder(data: &[u8], generator: &[u8]) -> Vec<u8> {
+  let mut result = vec![0u8; generator.len()];
+  for byte in data {
+    let factor = byte ^ result.remove(0);
+    result.push(0);
+    for (r, g) in result.iter_mut().zip(generator) {
+      *r ^= multiply(*g, factor);
+    }
+  }
+  result
+}
+
+// the codewords of the data followed by the error correction ones, interleaved across the blocks
+fn codewords(data: &[u8], version: usize, level: EcLevel) -> Vec<u8> {
+  let blocks = ERROR_CORRECTION_BLOCKS[level.index()][version] as usize;
+  let ecc_length = ECC_CODEWORDS_PER_BLOCK[level.index()][version] as usize;
+  let raw = raw_data_modules(version) / 8;
+  // the last blocks hold one more data codeword than the first ones
+  let short_blocks = blocks - raw % blocks;
+  let short_length = raw / blocks - ecc_length;
+
+  let generator = generator(ecc_length);
+  let mut data_blocks = vec![];
+  let mut ecc_blocks = vec![];
+  let mut start = 0;
+  for i in 0..blocks {
+    let length = short_length + if i < short_blocks { 0 } else { 1 };
+    let block = &data[start..start + length];
+    ecc_blocks.push(remainder(block, &generator));
+    data_blocks.push(block);
+    start += length;
+  }
+
+  let mut result = vec![];
+  for i in 0..=short_length {
+    result.extend(data_blocks.iter().filter_map(|block| block.get(i)));
+  }
+  for i in 0..ecc_length {
+    result.extend(ecc_blocks.iter().map(|block| block[i]));
+  }
+  result
+}
+
+// the data codewords of the segment, padded to the capacity of the version
+fn encode(data: &[u8], mode: Mode, version: usize, level: EcLevel) -> Vec<u8> {
+  let mut buffer = BitBuffer { bits: vec![] };
+  buffer.push(mode.indicator(), 4);
+  buffer.push(data.len() as u32, mode.count_bits(version));
+  match mode {
+    Mode::Numeric => {
+      for digits in data.chunks(3) {
+        let value = digits.iter().fold(0, |value, digit| value * 10 + (digit - b'0') as u32);
+        buffer.push(value, digits.len() * 3 + 1);
+      }
+    },
+    Mode::Alphanumeric => {
+      let value = |c: &u8| ALPHANUMERIC.bytes().position(|a| a == *c).unwrap_or(0) as u32;
+      for pair in data.chunks(2) {
+        match pair {
+          [a, b] => buffer.push(value(a) * 45 + value(b), 11),
+          [a] => buffer.push(value(a), 6),
+          _ => {},
+        }
+      }
+    },
+    Mode::Byte => data.iter().for_each(|byte| buffer.push(*byte as u32, 8)),
+  }
+
+  // terminator, then zeros up to a byte boundary, then alternating pad codewords
+  let capacity = data_codewords(version, level) * 8;
+  let terminator = (capacity - buffer.bits.len()).min(4);
+  buffer.push(0, terminator);
+  buffer.push(0, (8 - buffer.bits.len() % 8) % 8);
+  let mut bytes = buffer.bytes();
+  for pad in [0xec, 0x11].iter().cycle() {
+    if bytes.len() * 8 >= capacity {
+      break;
+    }
+    bytes.push(*pad);
+  }
+  bytes
+}
+
+struct Matrix {
+  size: usize,
+  dark: Vec<Vec<bool>>,
+  // modules of the finder, timing and alignment patterns and of the format and version information
+  function: Vec<Vec<bool>>,
+}
+
+impl Matrix {
+  fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+    self.dark[y][x] = dark;
+    self.function[y][x] = true;
+  }
+
+  fn finder(&mut self, cx: i32, cy: i32) {
+    for dy in -4..=4 {
+      for dx in -4..=4 {
+        let (x, y) = (cx + dx, cy + dy);
+        if x >= 0 && y >= 0 && x < self.size as i32 && y < self.size as i32 {
+          // the separator around the pattern is light
+          let distance = dx.abs().max(dy.abs());
+          self.set_function(x as usize, y as usize, distance != 2 && distance != 4);
+        }
+      }
+    }
+  }
+
+  fn alignment(&mut self, cx: usize, cy: usize) {
+    for dy in -2..=2i32 {
+      for dx in -2..=2i32 {
+        self.set_function((cx as i32 + dx) as usize, (cy as i32 + dy) as usize, dx.abs().max(dy.abs()) != 1);
+      }
+    }
+  }
+
+  fn function_patterns(&mut self, version: usize) {
+    for i in 0..self.size {
+      self.set_function(6, i, i % 2 == 0);
+      self.set_function(i, 6, i % 2 == 0);
+    }
+    let far = self.size as i32 - 4;
+    self.finder(3, 3);
+    self.finder(far, 3);
+    self.finder(3, far);
+
+    let positions = alignment_positions(version);
+    let last = positions.len().saturating_sub(1);
+    // the corners with finder patterns have none
+    let corners = [(0, 0), (0, last), (last, 0)];
+    for (i, &x) in positions.iter().enumerate() {
+      for (j, &y) in positions.iter().enumerate() {
+        if !corners.contains(&(i, j)) {
+          self.alignment(x, y);
+        }
+      }
+    }
+
+    // reserved now, drawn for real with the chosen mask
+    self.format(EcLevel::L, 0);
+    if version >= 7 {
+      let mut remainder = version as u32;
+      for _ in 0..12 {
+        remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1f25);
+      }
+      let bits = (version as u32) << 12 | remainder;
+      for i in 0..18 {
+        let bit = (bits >> i) & 1 == 1;
+        let (a, b) = (self.size - 11 + i % 3, i / 3);
+        self.set_function(a, b, bit);
+        self.set_function(b, a, bit);
+      }
+    }
+  }
+
+  // the error correction level and the mask, protected by a BCH code, in both copies
+  fn format(&mut self, level: EcLevel, mask: u32) {
+    let data = level.format_bits() << 3 | mask;
+    let mut remainder = data;
+    for _ in 0..10 {
+      remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+    }
+    let bits = (data << 10 | remainder) ^ 0x5412;
+    let bit = |i: usize| (bits >> i) & 1 == 1;
+
+    for i in 0..=5 {
+      self.set_function(8, i, bit(i));
+    }
+    self.set_function(8, 7, bit(6));
+    self.set_function(8, 8, bit(7));
+    self.set_function(7, 8, bit(8));
+    for i in 9..15 {
+      self.set_function(14 - i, 8, bit(i));
+    }
+
+    let size = self.size;
+    for i in 0..8 {
+      self.set_function(size - 1 - i, 8, bit(i));
+    }
+    for i in 8..15 {
+      self.set_function(8, size - 15 + i, bit(i));
+    }
+    // the dark module, always there
+    self.set_function(8, size - 8, true);
+  }
+
+  // the bits of the codewords placed in two module wide columns, zig-zagging up and down from the bottom right
+  fn place(&mut self, codewords: &[u8]) {
+    let mut index = 0;
+    let mut right = self.size as i32 - 1;
+    while right >= 1 {
+      // the vertical timing pattern is skipped
+      if right == 6 {
+        right = 5;
+      }
+      let upward = (right + 1) & 2 == 0;
+      for vertical in 0..self.size {
+        let y = if upward { self.size - 1 - vertical } else { vertical };
+        for j in 0..2 {
+          let x = (right - j) as usize;
+          if !self.function[y][x] && index < codewords.len() * 8 {
+            self.dark[y][x] = (codewords[index / 8] >> (7 - index % 8)) & 1 == 1;
+            index += 1;
+          }
+        }
+      }
+      right -= 2;
+    }
+  }
+
+  // masking twice restores the modules
+  fn mask(&mut self, mask: u32) {
+    for y in 0..self.size {
+      for x in 0..self.size {
+        let invert = match mask {
+          0 => (x + y) % 2 == 0,
+          1 => y % 2 == 0,
+          2 => x % 3 == 0,
+          3 => (x + y) % 3 == 0,
+          4 => (x / 3 + y / 2) % 2 == 0,
+          5 => x * y % 2 + x * y % 3 == 0,
+          6 => (x * y % 2 + x * y % 3) % 2 == 0,
+          _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+        };
+        if invert && !self.function[y][x] {
+          self.dark[y][x] = !self.dark[y][x];
+        }
+      }
+    }
+  }
+
+  // how hard the symbol is to read: long runs, blocks of a color, finder like patterns and unbalanced colors
+  fn penalty(&self) -> usize {
+    let size = self.size;
+    let mut penalty = 0;
+    let finder = [true, false, true, true, true, false, true];
+    for horizontal in [true, false] {
+      let line = |i: usize, j: usize| if horizontal { self.dark[i][j] } else { self.dark[j][i] };
+      for i in 0..size {
+        let mut run = 1;
+        for j in 1..=size {
+          if j < size && line(i, j) == line(i, j - 1) {
+            run += 1;
+            continue;
+          }
+          if run >= 5 {
+            penalty += PENALTY_RUN + run - 5;
+          }
+          run = 1;
+        }
+        for j in 0..size.saturating_sub(6) {
+          if (0..7).all(|k| line(i, j + k) == finder[k]) {
+            // four light modules on either side, or the border of the symbol
+            let before = (j.saturating_sub(4)..j).all(|k| !line(i, k));
+            let after = (j + 7..(j + 11).min(size)).all(|k| !line(i, k));
+            if before || after {
+              penalty += PENALTY_FINDER;
+            }
+          }
+        }
+      }
+    }
+
+    for y in 1..size {
+      for x in 1..size {
+        let color = self.dark[y][x];
+        if self.dark[y - 1][x] == color && self.dark[y][x - 1] == color && self.dark[y - 1][x - 1] == color {
+          penalty += PENALTY_BLOCK;
+        }
+      }
+    }
+
+    let dark = self.dark.iter().flatten().filter(|d| **d).count();
+    let total = size * size;
+    let deviation = (dark * 100 / total).abs_diff(50);
+    penalty + deviation / 5 * PENALTY_BALANCE
+  }
+}
+
+// the modules of the symbol, rows from the top, dark when true, without the quiet zone
+pub fn encode_text(text: &str, level: EcLevel) -> Result<Vec<Vec<bool>>, String> {
+  let data = text.as_bytes();
+  let mode = Mode::of(data);
+  let version = (1..=40)
+    .find(|&version| {
+      let bits = 4 + mode.count_bits(version) + mode.data_bits(data.len());
+      data.len() < 1 << mode.count_bits(version) && bits <= data_codewords(version, level) * 8
+    })
+    .ok_or_else(|| format!("The data is too long for a QR code at the {:?} error correction level", level))?;
+
+  let size = version * 4 + 17;
+  let mut matrix = Matrix { size, dark: vec![vec![false; size]; size], function: vec![vec![false; size]; size] };
+  matrix.function_patterns(version);
+  matrix.place(&codewords(&encode(data, mode, version, level), version, level));
+
+  let mut best = (usize::MAX, 0);
+  for mask in 0..8 {
+    matrix.mask(mask);
+    matrix.format(level, mask);
+    let penalty = matrix.penalty();
+    if penalty < best.0 {
+      best = (penalty, mask);
+    }
+    matrix.mask(mask);
+  }
+  matrix.mask(best.1);
+  matrix.format(level, best.1);
+  Ok(matrix.dark)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FORMAT_STRINGS: [[&str; 8]; 4] = [
+    ["111011111000100", "111001011110011", "111110110101010", "111100010011101", "110011000101111", "110001100011000", "110110001000001", "110100101110110"],
+    ["101010000010010", "101000100100101", "101111001111100", "101101101001011", "100010111111001", "100000011001110", "100111110010111", "100101010100000"],
+    ["011010101011111", "011000001101000", "011111100110001", "011101000000110", "010010010110100", "010000110000011", "010111011011010", "010101111101101"],
+    ["001011010001001", "001001110111110", "001110011100111", "001100111010000", "000011101100010", "000001001010101", "000110100001100", "000100000111011"],
+  ];
+
+  fn bits(modules: impl Iterator<Item = bool>) -> String {
+    modules.map(|dark| if dark { '1' } else { '0' }).collect()
+  }
+
+  #[test]
+  fn modes() {
+    assert_eq!(Mode::of(b"0123"), Mode::Numeric);
+    assert_eq!(Mode::of(b"HELLO WORLD"), Mode::Alphanumeric);
+    assert_eq!(Mode::of(b"Hello"), Mode::Byte);
+    assert_eq!(Mode::Numeric.data_bits(8), 27);
+    assert_eq!(Mode::Alphanumeric.data_bits(11), 61);
+  }
+
+  #[test]
+  fn capacities() {
+    assert_eq!(data_codewords(1, EcLevel::L), 19);
+    assert_eq!(data_codewords(1, EcLevel::H), 9);
+    assert_eq!(data_codewords(7, EcLevel::M), 124);
+    assert_eq!(data_codewords(40, EcLevel::L), 2956);
+    assert_eq!(data_codewords(40, EcLevel::H), 1276);
+    assert!(alignment_positions(1).is_empty());
+    assert_eq!(alignment_positions(2), vec![6, 18]);
+    assert_eq!(alignment_positions(7), vec![6, 22, 38]);
+    assert_eq!(alignment_positions(32), vec![6, 34, 60, 86, 112, 138]);
+    assert_eq!(alignment_positions(40), vec![6, 30, 58, 86, 114, 142, 170]);
+  }
+
+  #[test]
+  fn smallest_version() {
+    // a version 1-L symbol holds 17 bytes
+    assert_eq!(encode_text(&"a".repeat(17), EcLevel::L).unwrap().len(), 21);
+    assert_eq!(encode_text(&"a".repeat(18), EcLevel::L).unwrap().len(), 25);
+    assert_eq!(encode_text(&"1".repeat(7089), EcLevel::L).unwrap().len(), 177);
+    assert!(encode_text(&"1".repeat(7090), EcLevel::L).is_err());
+  }
+
+  #[test]
+  fn galois_field() {
+    assert_eq!(multiply(2, 128), 0x1d);
+    assert_eq!(multiply(87, 1), 87);
+    assert_eq!(multiply(3, 7), 9);
+    assert_eq!(generator(2), vec![3, 2]);
+  }
+
+  #[test]
+  fn codewords_of_known_symbols() {
+    let data = encode(b"HELLO WORLD", Mode::Alphanumeric, 1, EcLevel::Q);
+    assert_eq!(data, vec![32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236]);
+    let ecc = [168, 72, 22, 82, 217, 54, 156, 0, 46, 15, 180, 122, 16];
+    assert_eq!(codewords(&data, 1, EcLevel::Q), [data.as_slice(), &ecc].concat());
+
+    let data = encode(b"01234567", Mode::Numeric, 1, EcLevel::M);
+    assert_eq!(data, vec![16, 32, 12, 86, 97, 128, 236, 17, 236, 17, 236, 17, 236, 17, 236, 17]);
+    let ecc = [165, 36, 212, 193, 237, 54, 199, 135, 44, 85];
+    assert_eq!(codewords(&data, 1, EcLevel::M), [data.as_slice(), &ecc].concat());
+  }
+
+  #[test]
+  fn interleaved_blocks() {
+    // version 5-Q has two blocks of 15 data codewords then two of 16, with 18 error correction codewords each
+    let data = (0..62).collect::<Vec<u8>>();
+    let result = codewords(&data, 5, EcLevel::Q);
+    assert_eq!(result.len(), 134);
+    assert_eq!(result[..8], [0, 15, 30, 46, 1, 16, 31, 47]);
+    // the last data codewords only come from the longer blocks
+    assert_eq!(result[56..62], [14, 29, 44, 60, 45, 61]);
+  }
+
+  #[test]
+  fn format_information() {
+    for (level, strings) in [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H].into_iter().zip(FORMAT_STRINGS) {
+      for (mask, expected) in strings.iter().enumerate() {
+        let size = 21;
+        let mut matrix = Matrix { size, dark: vec![vec![false; size]; size], function: vec![vec![false; size]; size] };
+        matrix.format(level, mask as u32);
+        let dark = &matrix.dark;
+        // along the top left finder pattern, most significant bit first, the timing patterns are skipped
+        let first = bits((0..6).map(|x| dark[8][x]).chain([dark[8][7], dark[8][8], dark[7][8]]).chain((0..6).rev().map(|y| dark[y][8])));
+        // below the top right finder pattern, then beside the bottom left one
+        let second = bits((0..7).map(|i| dark[size - 1 - i][8]).chain((size - 8..size).map(|x| dark[8][x])));
+        assert_eq!(&first, expected);
+        assert_eq!(&second, expected);
+        assert!(dark[size - 8][8]);
+      }
+    }
+  }
+
+  #[test]
+  fn known_symbol() {
+    // the example symbol of ISO/IEC 18004, version 1-M with mask 010
+    let expected = [
+    "111111100101101111111",
+    "100000100111101000001",
+    "101110101000001011101",
+    "101110101100001011101",
+    "101110101011101011101",
+    "100000101000101000001",
+    "111111101010101111111",
+    "000000001001100000000",
+    "101111100100101111100",
+    "000101011010100101100",
+    "001000110101010011111",
+    "000010000100000111100",
+    "000111111001010010000",
+    "000000001011111001100",
+    "111111100110101100000",
+    "100000101011111000101",
+    "101110101000100101100",
+    "101110101100100100000",
+    "101110101011010010100",
+    "100000100000000110110",
+    "111111101111010010100",
+    ];
+    let symbol = encode_text("01234567", EcLevel::M).unwrap();
+    assert_eq!(symbol.iter().map(|row| bits(row.iter().copied())).collect::<Vec<_>>(), expected);
+  }
+}
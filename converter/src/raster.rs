@@ -0,0 +1,209 @@
+// Laser raster engraving: scans the image row by row, modulating the laser power with the pixel intensity
+use crate::canny::PenDirection;
+use crate::command::Command;
+use crate::gcode::mm;
+use crate::machine::MachineModel;
+use image::GrayImage;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RasterOptions {
+  // distance between scanned rows (mm)
+  pub line_interval: f32,
+  // distance travelled with the laser off before and after each row, so it burns at a constant speed (mm)
+  pub overscan: f32,
+  // S values of the lightest burned and of the black pixels
+  pub min_power: u32,
+  pub max_power: u32,
+  // power = darkness ^ gamma, values above 1 lighten the mid tones
+  pub gamma: f32,
+  // rows without anything to burn are not scanned
+  pub skip_blank_rows: bool,
+}
+
+impl Default for RasterOptions {
+  fn default() -> Self {
+    RasterOptions {
+      line_interval: 0.1,
+      overscan: 2.0,
+      min_power: 0,
+      max_power: 1000,
+      gamma: 1.0,
+      skip_blank_rows: true,
+    }
+  }
+}
+
+pub struct RasterJob {
+  pub gcode: String,
+  // the motion of the job, one pen down move per scanned row (command units)
+  // used to estimate and validate the job the same way as drawings
+  pub commands: Vec<Command>,
+  pub rows: usize,
+}
+
+// S value of a pixel, white is always off
+fn power(pixel: u8, options: &RasterOptions) -> u32 {
+  let darkness = 1.0 - pixel as f32 / 255.0;
+  if darkness <= 0.0 {
+    return 0;
+  }
+  let corrected = darkness.powf(options.gamma);
+  let range = options.max_power.saturating_sub(options.min_power) as f32;
+  options.min_power + (corrected * range).round() as u32
+}
+
+// bidirectional raster in laser mode (M4), consecutive pixels of the same power are burned in a single move
+// one pixel of the image is one command unit wide, rows are sampled every line_interval mm
+pub fn to_raster(image: &GrayImage, options: &RasterOptions, machine: &MachineModel) -> RasterJob {
+  let mut gcode: Vec<String> = vec![
+    "G21".to_string(), // millimeters
+    "G90".to_string(), // absolute positioning
+    "M4 S0".to_string(), // dynamic laser power, off while not moving
+  ];
+  let mut commands = vec![Command::Pen(PenDirection::UP), Command::Reset];
+  let mut rows = 0;
+
+  // everything below is in command units (pixels)
+  let interval = options.line_interval.max(0.001) / machine.unit_size;
+  let overscan = options.overscan / machine.unit_size;
+  let row_count = (image.height() as f32 / interval).ceil() as usize;
+
+  for row in 0..row_count {
+    let y = row as f32 * interval;
+    let image_row = (y as u32).min(image.height() - 1);
+    let powers = (0..image.width())
+      .map(|x| power(image.get_pixel(x, image_row)[0], options))
+      .collect::<Vec<u32>>();
+
+    // only the part of the row between the first and the last burned pixel is scanned
+    let (first, last) = match (powers.iter().position(|p| *p > 0), powers.iter().rposition(|p| *p > 0)) {
+      (Some(first), Some(last)) => (first, last),
+      _ => {
+        if options.skip_blank_rows {
+          continue;
+        }
+        (0, powers.len() - 1)
+      },
+    };
+
+    // every other scanned row goes right to left
+    let forward = rows % 2 == 0;
+    rows += 1;
+    let (start, end) = if forward {
+      (first as f32 - overscan, (last + 1) as f32 + overscan)
+    }
+    else {
+      ((last + 1) as f32 + overscan, first as f32 - overscan)
+    };
+
+    gcode.push(format!("G0 X{} Y{} S0", mm(start, machine), mm(y, machine)));
+    commands.push(Command::Move(start, y));
+    commands.push(Command::Pen(PenDirection::DOWN));
+
+    // runs of equal power, as (power, x where the run ends)
+    let mut runs: Vec<(u32, f32)> = vec![];
+    let columns: Vec<usize> = if forward { (first..=last).collect() } else { (first..=last).rev().collect() };
+    for column in columns {
+      let edge = if forward { (column + 1) as f32 } else { column as f32 };
+      match runs.last_mut() {
+        Some((power, x)) if *power == powers[column] => *x = edge,
+        _ => runs.push((powers[column], edge)),
+      }
+    }
+
+    let direction = if forward { 1.0 } else { -1.0 };
+    // the feed rate is repeated on every row, so the job can be resumed from any of them
+    gcode.push(format!("G1 X{} S0 F{}", mm(start + direction * overscan, machine), machine.max_feed));
+    for (power, x) in runs {
+      gcode.push(format!("G1 X{} S{}", mm(x, machine), power));
+    }
+    gcode.push(format!("G1 X{} S0", mm(end, machine)));
+    commands.push(Command::Move(end, y));
+    commands.push(Command::Pen(PenDirection::UP));
+  }
+
+  gcode.push("M5".to_string()); // laser off
+  gcode.push("G0 X0 Y0".to_string());
+  gcode.push("M2".to_string()); // end of program
+  commands.push(Command::Reset);
+  commands.push(Command::End);
+
+  RasterJob {
+    gcode: gcode.join("\n"),
+    commands,
+    rows,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::Luma;
+
+  fn image(rows: &[[u8; 4]]) -> GrayImage {
+    GrayImage::from_fn(4, rows.len() as u32, |x, y| Luma([rows[y as usize][x as usize]]))
+  }
+
+  #[test]
+  fn powers() {
+    let options = RasterOptions::default();
+    assert_eq!(power(255, &options), 0);
+    assert_eq!(power(0, &options), 1000);
+    assert_eq!(power(128, &options), 498);
+    // the lightest burned pixels start at the minimum power
+    let options = RasterOptions { min_power: 200, ..options };
+    assert_eq!((power(255, &options), power(254, &options), power(0, &options)), (0, 203, 1000));
+    let options = RasterOptions { gamma: 2.0, min_power: 0, ..options };
+    assert_eq!(power(128, &options), 248);
+  }
+
+  #[test]
+  fn rows_go_back_and_forth() {
+    let machine = MachineModel::default();
+    let options = RasterOptions { line_interval: 1.0, overscan: 1.0, ..RasterOptions::default() };
+    let job = to_raster(&image(&[[255, 0, 0, 128], [255; 4], [0, 255, 255, 255]]), &options, &machine);
+    assert_eq!(job.rows, 2);
+    assert_eq!(
+      job.gcode.lines().collect::<Vec<_>>(),
+      vec![
+        "G21", "G90", "M4 S0",
+        "G0 X0.000 Y0.000 S0", "G1 X1.000 S0 F3000", "G1 X3.000 S1000", "G1 X4.000 S498", "G1 X5.000 S0",
+        // the blank row is skipped
+        "G0 X2.000 Y2.000 S0", "G1 X1.000 S0 F3000", "G1 X0.000 S1000", "G1 X-1.000 S0",
+        "M5", "G0 X0 Y0", "M2",
+      ]
+    );
+    assert_eq!(
+      job.commands,
+      vec![
+        Command::Pen(PenDirection::UP),
+        Command::Reset,
+        Command::Move(0.0, 0.0),
+        Command::Pen(PenDirection::DOWN),
+        Command::Move(5.0, 0.0),
+        Command::Pen(PenDirection::UP),
+        Command::Move(2.0, 2.0),
+        Command::Pen(PenDirection::DOWN),
+        Command::Move(-1.0, 2.0),
+        Command::Pen(PenDirection::UP),
+        Command::Reset,
+        Command::End,
+      ]
+    );
+  }
+
+  #[test]
+  fn blank_rows_and_intervals() {
+    let machine = MachineModel { unit_size: 0.5, ..MachineModel::default() };
+    let blank = image(&[[255; 4], [255; 4]]);
+    assert_eq!(to_raster(&blank, &RasterOptions::default(), &machine).rows, 0);
+
+    // rows every 0.25 mm are two per pixel
+    let options = RasterOptions { line_interval: 0.25, overscan: 0.0, skip_blank_rows: false, ..RasterOptions::default() };
+    let job = to_raster(&blank, &options, &machine);
+    assert_eq!(job.rows, 4);
+    assert!(job.gcode.contains("G0 X0.000 Y0.000 S0\nG1 X0.000 S0 F3000\nG1 X2.000 S0\nG1 X2.000 S0"));
+    assert!(job.gcode.contains("G0 X2.000 Y0.250 S0\nG1 X2.000 S0 F3000\nG1 X0.000 S0\nG1 X0.000 S0"));
+    assert!(job.gcode.contains("G0 X2.000 Y0.750 S0"));
+  }
+}
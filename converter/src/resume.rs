@@ -0,0 +1,244 @@
+// Resumes an interrupted job, either a command file or G-code, from a given point
+use crate::canny::PenDirection;
+use crate::command::Command;
+use crate::gcode::{self, GcodeOptions};
+use crate::grbl::{Controller, Interpreter};
+use crate::machine::{HeadState, MachineModel};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResumePoint {
+  // 1 based line number of the program
+  Line(usize),
+  // 0 based index of the edge (each pen down starts a new one)
+  Edge(usize),
+  // percentage of the estimated job time
+  Percent(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgramFormat {
+  Commands,
+  Gcode,
+}
+
+// command files are recognized by their first known command
+pub fn detect_format(program: &str) -> ProgramFormat {
+  if program.lines().any(|line| Command::parse(line).is_some()) {
+    ProgramFormat::Commands
+  }
+  else {
+    ProgramFormat::Gcode
+  }
+}
+
+// commands performed by each line of the program
+fn line_commands(program: &str, format: ProgramFormat, machine: &MachineModel) -> Vec<Vec<Command>> {
+  let mut interpreter = Interpreter::new(machine.unit_size, None);
+  program
+    .lines()
+    .map(|line| match format {
+      ProgramFormat::Commands => Command::parse(line).into_iter().collect(),
+      ProgramFormat::Gcode => {
+        // unsupported blocks do not move the head, they are kept as they are
+        let _ = interpreter.execute(line);
+        interpreter.take_commands()
+      },
+    })
+    .collect()
+}
+
+// 0 based index of the first line to be executed again
+pub fn find_start(program: &str, point: ResumePoint, machine: &MachineModel) -> Result<usize, String> {
+  let lines = line_commands(program, detect_format(program), machine);
+
+  match point {
+    ResumePoint::Line(line) => {
+      if line == 0 || line > lines.len() {
+        return Err(format!("Line {} is outside of the program (1..{})", line, lines.len()));
+      }
+      Ok(line - 1)
+    },
+    ResumePoint::Edge(edge) => {
+      let mut head = HeadState::origin();
+      let mut edges = 0;
+      for (index, commands) in lines.iter().enumerate() {
+        for command in commands {
+          let pen = head.pen;
+          head.apply(command, machine);
+          if pen == PenDirection::UP && head.pen == PenDirection::DOWN {
+            if edges == edge {
+              return Ok(index);
+            }
+            edges += 1;
+          }
+        }
+      }
+      Err(format!("Edge {} does not exist, the program has {} edges", edge, edges))
+    },
+    ResumePoint::Percent(percent) => {
+      if !(0.0..=100.0).contains(&percent) {
+        return Err(format!("{}% is not a valid percentage", percent));
+      }
+      let mut head = HeadState::origin();
+      let times = lines
+        .iter()
+        .map(|commands| commands.iter().map(|command| head.apply(command, machine).duration()).sum::<f32>())
+        .collect::<Vec<f32>>();
+      let target = times.iter().sum::<f32>() * percent / 100.0;
+
+      let mut elapsed = 0.0;
+      for (index, time) in times.iter().enumerate() {
+        if elapsed + time > target {
+          return Ok(index);
+        }
+        elapsed += time;
+      }
+      Ok(lines.len().saturating_sub(1))
+    },
+  }
+}
+
+// head state right before the given line
+fn state_at(lines: &[Vec<Command>], start: usize, machine: &MachineModel) -> HeadState {
+  let mut head = HeadState::origin();
+  for command in lines[..start].iter().flatten() {
+    if *command == Command::End {
+      break;
+    }
+    head.apply(command, machine);
+  }
+  head
+}
+
+// Z of the tool right before the given line of a G-code program, the depth it is lowered to when the pen is the Z axis
+fn depth_at(program: &str, start: usize, machine: &MachineModel) -> f32 {
+  let mut interpreter = Interpreter::new(machine.unit_size, None);
+  for line in program.lines().take(start) {
+    let _ = interpreter.execute(line);
+  }
+  interpreter.z.min(0.0)
+}
+
+// generates the remainder of the program from the line found by `find_start`, preceded by the moves that bring the
+// machine to the state it had at that line
+pub fn resume(program: &str, start: usize, machine: &MachineModel, options: &GcodeOptions) -> String {
+  let format = detect_format(program);
+  let lines = line_commands(program, format, machine);
+  let head = state_at(&lines, start, machine);
+
+  let mut resumed: Vec<String> = vec![];
+  match format {
+    ProgramFormat::Commands => {
+      resumed.push(Command::Pen(PenDirection::UP).to_string());
+      // layered jobs continue with the pen that was in use
+      let tool = lines[..start].iter().flatten().rev().find(|command| matches!(command, Command::Tool(..)));
+      if let Some(tool) = tool {
+        resumed.push(tool.to_string());
+      }
+      resumed.push(Command::Move(head.x, head.y).to_string());
+      // multi pass jobs continue at the depth of the pass in progress
+      let depth = lines[..start].iter().flatten().rev().find_map(|command| match command {
+        Command::Depth(z) | Command::Ramp(_, _, z) => Some(*z),
+        _ => None,
+      });
+      if let Some(depth) = depth {
+        resumed.push(Command::Depth(depth).to_string());
+      }
+      if head.pen == PenDirection::DOWN {
+        resumed.push(Command::Pen(PenDirection::DOWN).to_string());
+      }
+    },
+    ProgramFormat::Gcode => {
+      resumed.push("G21".to_string());
+      resumed.push("G90".to_string());
+      let depth = depth_at(program, start, machine);
+      gcode::pen_gcode(PenDirection::UP, depth, machine, options, &mut resumed);
+      let tool_change = program.lines().take(start).filter(|line| line.trim_start().starts_with("M0")).last();
+      if let Some(tool_change) = tool_change {
+        resumed.push(tool_change.to_string());
+      }
+      if options.safe_z.is_some() {
+        resumed.push(options.pen_down.clone()); // spindle on
+      }
+      resumed.push(format!("G0 X{} Y{}", gcode::mm(head.x, machine), gcode::mm(head.y, machine)));
+      if head.pen == PenDirection::DOWN {
+        gcode::pen_gcode(PenDirection::DOWN, depth, machine, options, &mut resumed);
+      }
+    },
+  }
+
+  resumed.extend(program.lines().skip(start).map(|line| line.to_string()));
+  resumed.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PROGRAM: &str = "PEN UP\nTOOL 2 #ff0000\nMOVE 0 0\nDEPTH -1\nPEN DOWN\nMOVE 10 0\nMOVE 10 10\nPEN UP\nMOVE 20 20\nPEN DOWN\nMOVE 30 20\nPEN UP\nEND";
+
+  fn gcode_of(options: &GcodeOptions) -> String {
+    gcode::to_gcode(&crate::command::parse_commands(PROGRAM), &MachineModel::default(), options)
+  }
+
+  fn line_of(program: &str, line: &str) -> usize {
+    program.lines().position(|l| l == line).unwrap()
+  }
+
+  #[test]
+  fn formats() {
+    assert_eq!(detect_format(PROGRAM), ProgramFormat::Commands);
+    assert_eq!(detect_format(&gcode_of(&GcodeOptions::default())), ProgramFormat::Gcode);
+  }
+
+  #[test]
+  fn start_points() {
+    let machine = MachineModel::default();
+    assert_eq!(find_start(PROGRAM, ResumePoint::Line(7), &machine), Ok(6));
+    assert!(find_start(PROGRAM, ResumePoint::Line(0), &machine).is_err());
+    assert!(find_start(PROGRAM, ResumePoint::Line(14), &machine).is_err());
+
+    assert_eq!(find_start(PROGRAM, ResumePoint::Edge(0), &machine), Ok(4));
+    assert_eq!(find_start(PROGRAM, ResumePoint::Edge(1), &machine), Ok(9));
+    assert_eq!(find_start(PROGRAM, ResumePoint::Edge(2), &machine), Err("Edge 2 does not exist, the program has 2 edges".to_string()));
+
+    // lines taking no time are skipped
+    assert_eq!(find_start(PROGRAM, ResumePoint::Percent(0.0), &machine), Ok(4));
+    assert_eq!(find_start(PROGRAM, ResumePoint::Percent(100.0), &machine), Ok(12));
+    assert!(find_start(PROGRAM, ResumePoint::Percent(150.0), &machine).is_err());
+
+    // G-code programs count the same edges
+    let gcode = gcode_of(&GcodeOptions::default());
+    let second = gcode.lines().enumerate().filter(|(_, line)| *line == "M3 S1000").nth(1).unwrap().0;
+    assert_eq!(find_start(&gcode, ResumePoint::Edge(1), &machine), Ok(second));
+  }
+
+  #[test]
+  fn resumed_commands() {
+    let resumed = resume(PROGRAM, 6, &MachineModel::default(), &GcodeOptions::default());
+    assert_eq!(resumed, "PEN UP\nTOOL 2 #ff0000\nMOVE 10 0\nDEPTH -1\nPEN DOWN\nMOVE 10 10\nPEN UP\nMOVE 20 20\nPEN DOWN\nMOVE 30 20\nPEN UP\nEND");
+    // from a travel, the pen stays up
+    let resumed = resume(PROGRAM, 8, &MachineModel::default(), &GcodeOptions::default());
+    assert_eq!(resumed, "PEN UP\nTOOL 2 #ff0000\nMOVE 10 10\nDEPTH -1\nMOVE 20 20\nPEN DOWN\nMOVE 30 20\nPEN UP\nEND");
+  }
+
+  #[test]
+  fn resumed_gcode() {
+    let machine = MachineModel::default();
+    let options = GcodeOptions::default();
+    let gcode = gcode_of(&options);
+    let start = line_of(&gcode, "G1 X10.000 Y10.000 F3000");
+    let resumed = resume(&gcode, start, &machine, &options);
+    let prefix = ["G21", "G90", "M5", "G4 P0.150", "M0 (pen 2 #ff0000)", "G0 X10.000 Y0.000", "M3 S1000", "G4 P0.150"];
+    assert_eq!(resumed.lines().take(prefix.len()).collect::<Vec<_>>(), prefix);
+    assert_eq!(resumed.lines().skip(prefix.len()).collect::<Vec<_>>(), gcode.lines().skip(start).collect::<Vec<_>>());
+
+    // the tool goes back down to the depth of the cut
+    let options = GcodeOptions { safe_z: Some(5.0), ..options };
+    let gcode = gcode_of(&options);
+    let start = line_of(&gcode, "G1 X10.000 Y10.000 F3000");
+    let resumed = resume(&gcode, start, &machine, &options);
+    let prefix = ["G21", "G90", "G0 Z5.000", "M0 (pen 2 #ff0000)", "M3 S1000", "G0 X10.000 Y0.000", "G1 Z-1.000 F300", "G1 X10.000 Y10.000 F3000"];
+    assert_eq!(resumed.lines().take(prefix.len()).collect::<Vec<_>>(), prefix);
+  }
+}
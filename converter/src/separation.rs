@@ -0,0 +1,230 @@
+// Color separation: quantizes the input into a few colors and traces each of them as its own layer
+use crate::canny::{self, SerializebleComputation};
+use crate::fill::{self, FillOptions};
+use crate::path;
+use image::{GrayImage, Luma, RgbImage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeparationOptions {
+  // number of colors found by k-means, ignored when a palette is given
+  pub colors: usize,
+  // pen colors to quantize to
+  pub palette: Option<Vec<[u8; 3]>>,
+  // one command file per pen instead of a single job with tool changes
+  pub split_files: bool,
+  // the lightest color is considered to be the paper and is not drawn unless this is set
+  pub include_background: bool,
+}
+
+// pixels sampled to find the k-means clusters
+const KMEANS_SAMPLES: usize = 20000;
+const KMEANS_ITERATIONS: usize = 20;
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+  (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+fn nearest(color: [f32; 3], centers: &[[f32; 3]]) -> usize {
+  let mut best = 0;
+  for (index, center) in centers.iter().enumerate() {
+    if distance(color, *center) < distance(color, centers[best]) {
+      best = index;
+    }
+  }
+  best
+}
+
+fn to_f32(pixel: &image::Rgb<u8>) -> [f32; 3] {
+  [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32]
+}
+
+// k-means++ seeding followed by Lloyd iterations on a sample of the pixels
+// a fixed seed keeps the layers stable between runs
+pub fn kmeans(image: &RgbImage, colors: usize) -> Vec<[u8; 3]> {
+  let mut rng = StdRng::seed_from_u64(0x5eed);
+  let pixels = image.pixels().map(to_f32).collect::<Vec<[f32; 3]>>();
+  if pixels.is_empty() || colors == 0 {
+    return vec![];
+  }
+  let samples = (0..KMEANS_SAMPLES.min(pixels.len()))
+    .map(|_| pixels[rng.gen_range(0..pixels.len())])
+    .collect::<Vec<[f32; 3]>>();
+
+  let mut centers = vec![samples[rng.gen_range(0..samples.len())]];
+  while centers.len() < colors {
+    let weights = samples
+      .iter()
+      .map(|sample| centers.iter().map(|center| distance(*sample, *center)).fold(f32::MAX, f32::min))
+      .collect::<Vec<f32>>();
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+      break; // fewer distinct colors than requested
+    }
+    let mut pick = rng.gen_range(0.0..total);
+    let mut chosen = samples.len() - 1;
+    for (index, weight) in weights.iter().enumerate() {
+      if pick < *weight {
+        chosen = index;
+        break;
+      }
+      pick -= weight;
+    }
+    centers.push(samples[chosen]);
+  }
+
+  for _ in 0..KMEANS_ITERATIONS {
+    let mut sums = vec![[0.0f32; 3]; centers.len()];
+    let mut counts = vec![0usize; centers.len()];
+    for sample in &samples {
+      let index = nearest(*sample, &centers);
+      for channel in 0..3 {
+        sums[index][channel] += sample[channel];
+      }
+      counts[index] += 1;
+    }
+    for (index, center) in centers.iter_mut().enumerate() {
+      if counts[index] > 0 {
+        for channel in 0..3 {
+          center[channel] = sums[index][channel] / counts[index] as f32;
+        }
+      }
+    }
+  }
+
+  centers
+    .into_iter()
+    .map(|c| [c[0].round() as u8, c[1].round() as u8, c[2].round() as u8])
+    .collect()
+}
+
+// index of the palette color assigned to each pixel, row by row
+pub fn quantize(image: &RgbImage, palette: &[[u8; 3]]) -> Vec<usize> {
+  let centers = palette
+    .iter()
+    .map(|c| [c[0] as f32, c[1] as f32, c[2] as f32])
+    .collect::<Vec<[f32; 3]>>();
+  image.pixels().map(|pixel| nearest(to_f32(pixel), &centers)).collect()
+}
+
+// outline of the region assigned to `layer`, as a white on black image that can be traced
+pub fn layer_outline(labels: &[usize], width: u32, height: u32, layer: usize) -> GrayImage {
+  let label = |x: i64, y: i64| -> Option<usize> {
+    if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+      None
+    }
+    else {
+      Some(labels[(y as u32 * width + x as u32) as usize])
+    }
+  };
+
+  GrayImage::from_fn(width, height, |x, y| {
+    let (x, y) = (x as i64, y as i64);
+    let inside = label(x, y) == Some(layer);
+    let border = [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+      .iter()
+      .any(|&(nx, ny)| label(nx, ny) != Some(layer));
+    Luma([if inside && border { 255 } else { 0 }])
+  })
+}
+
+fn luma(color: [u8; 3]) -> f32 {
+  0.299 * color[0] as f32 + 0.587 * color[1] as f32 + 0.114 * color[2] as f32
+}
+
+// traced layers, darkest first, along with their pen color
+// with a fill, each layer is hatched after its outline is drawn
+pub fn separate(image: &RgbImage, options: &SeparationOptions, fill_options: Option<&FillOptions>, point_precision: f32) -> Vec<([u8; 3], SerializebleComputation)> {
+  let palette = match &options.palette {
+    Some(palette) => palette.clone(),
+    None => kmeans(image, options.colors),
+  };
+  let labels = quantize(image, &palette);
+
+  let mut order = (0..palette.len()).collect::<Vec<usize>>();
+  order.sort_by(|a, b| luma(palette[*a]).partial_cmp(&luma(palette[*b])).unwrap());
+  if !options.include_background {
+    order.pop();
+  }
+
+  order
+    .into_iter()
+    .map(|layer| {
+      let outline = image::DynamicImage::ImageLuma8(layer_outline(&labels, image.width(), image.height(), layer));
+      let mut computation = canny::to_serializable_points(&outline, point_precision);
+      if let Some(fill_options) = fill_options {
+        let boundary = fill::region_boundary(image.width(), image.height(), |x, y| {
+          labels[(y * image.width() + x) as usize] == layer
+        });
+        computation.edges.extend(fill::to_edges(&path::order_strokes(fill::fill(&boundary, fill_options))));
+      }
+      (palette[layer], computation)
+    })
+    .filter(|(_, computation)| !computation.edges.is_empty())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::Rgb;
+
+  const WHITE: [u8; 3] = [255, 255, 255];
+  const RED: [u8; 3] = [200, 30, 30];
+  const BLUE: [u8; 3] = [20, 40, 160];
+
+  // white paper with a red square on the left and a blue one on the right
+  fn image() -> RgbImage {
+    RgbImage::from_fn(120, 60, |x, y| {
+      Rgb(match (x, y) {
+        (10..=49, 10..=49) => RED,
+        (70..=109, 10..=49) => BLUE,
+        _ => WHITE,
+      })
+    })
+  }
+
+  #[test]
+  fn kmeans_finds_the_colors() {
+    let mut colors = kmeans(&image(), 3);
+    colors.sort();
+    assert_eq!(colors, vec![BLUE, RED, WHITE]);
+    // there are not that many colors to be found
+    assert_eq!(kmeans(&RgbImage::from_pixel(10, 10, Rgb(RED)), 4), vec![RED]);
+    assert!(kmeans(&image(), 0).is_empty());
+  }
+
+  #[test]
+  fn pixels_go_to_the_nearest_color() {
+    let image = RgbImage::from_fn(3, 1, |x, _| Rgb([[250, 250, 240], [180, 60, 40], [0, 0, 90]][x as usize]));
+    assert_eq!(quantize(&image, &[WHITE, RED, BLUE]), vec![0, 1, 2]);
+  }
+
+  #[test]
+  fn outlines_of_a_layer() {
+    // a 3x3 square in the middle of a 5x5 image, only its border is kept
+    let labels = (0..25).map(|i| if (1..4).contains(&(i % 5)) && (1..4).contains(&(i / 5)) { 1 } else { 0 }).collect::<Vec<usize>>();
+    let outline = layer_outline(&labels, 5, 5, 1);
+    let white = outline.enumerate_pixels().filter(|(_, _, p)| p[0] == 255).map(|(x, y, _)| (x, y)).collect::<Vec<_>>();
+    assert_eq!(white.len(), 8);
+    assert!(!white.contains(&(2, 2)));
+    // the image edges are borders too
+    assert_eq!(layer_outline(&labels, 5, 5, 0).pixels().filter(|p| p[0] == 255).count(), 16);
+  }
+
+  #[test]
+  fn layers_darkest_first() {
+    let options = SeparationOptions { colors: 3, palette: Some(vec![WHITE, RED, BLUE]), split_files: false, include_background: false };
+    let layers = separate(&image(), &options, None, 1.0);
+    assert_eq!(layers.iter().map(|(color, _)| *color).collect::<Vec<_>>(), vec![BLUE, RED]);
+    // each outline stays around its own square
+    for ((_, computation), left) in layers.iter().zip([70, 10]) {
+      assert!(!computation.edges.is_empty());
+      assert!(computation.edges.iter().flatten().all(|p| p.x + 1 >= left && p.x <= left + 40 && p.y >= 9 && p.y <= 50));
+    }
+
+    let options = SeparationOptions { include_background: true, palette: None, ..options };
+    assert_eq!(separate(&image(), &options, None, 1.0).len(), 3);
+  }
+}
@@ -0,0 +1,311 @@
+// Grayscale shading: renders tone as line density instead of tracing edges
+use crate::fill::{self, Point, Polyline};
+use crate::path::PointGrid;
+use crate::tsp;
+use image::GrayImage;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadingMode {
+  // hatch lines, twice as dense for every darker tone level
+  Hatch,
+  // weighted Voronoi stippling, dots gather in dark areas
+  Stipple,
+  // rows of waves getting taller and denser in dark areas
+  Squiggle,
+  // same as squiggle, along a spiral from the center of the image
+  Spiral,
+  // a single line going through stipples, in the order of a travelling salesman path
+  Tsp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadingOptions {
+  pub mode: ShadingMode,
+  // distance between the lines of the darkest tone, or between squiggle rows (px)
+  pub spacing: f32,
+  // hatch direction (degrees)
+  pub angle: f32,
+  // number of tone levels for hatching
+  pub levels: usize,
+  // number of stipples
+  pub points: usize,
+  // relaxation steps that move stipples towards the centroid of their Voronoi cell
+  pub iterations: usize,
+  // length of the pen down move drawing a stipple (px), 0 only touches the paper
+  pub dot_size: f32,
+  // maximum number of 2-opt and Or-opt passes improving the single line path
+  pub tsp_iterations: usize,
+}
+
+impl Default for ShadingOptions {
+  fn default() -> Self {
+    ShadingOptions {
+      mode: ShadingMode::Hatch,
+      spacing: 4.0,
+      angle: 45.0,
+      levels: 4,
+      points: 5000,
+      iterations: 20,
+      dot_size: 1.0,
+      tsp_iterations: 20,
+    }
+  }
+}
+
+// 0 for white, 1 for black
+fn darkness(image: &GrayImage, x: f32, y: f32) -> f32 {
+  let x = (x.round().max(0.0) as u32).min(image.width() - 1);
+  let y = (y.round().max(0.0) as u32).min(image.height() - 1);
+  1.0 - image.get_pixel(x, y)[0] as f32 / 255.0
+}
+
+fn inside(image: &GrayImage, p: Point) -> bool {
+  p.0 >= 0.0 && p.1 >= 0.0 && p.0 <= (image.width() - 1) as f32 && p.1 <= (image.height() - 1) as f32
+}
+
+// the lightest level is hatched at the widest spacing, every darker level adds the lines
+// halfway between the existing ones, so the spacing is halved at each level
+fn hatch(image: &GrayImage, options: &ShadingOptions) -> Vec<Polyline> {
+  // thresholding a blurred image avoids hatching the noise of the photo
+  let smooth = imageproc::filter::gaussian_blur_f32(image, (options.spacing / 2.0).max(0.5));
+  let levels = options.levels.max(1);
+  let widest = options.spacing * 2f32.powi(levels as i32 - 1);
+
+  let mut strokes = vec![];
+  for level in 0..levels {
+    let threshold = 255.0 * (levels - level) as f32 / (levels + 1) as f32;
+    let boundary = fill::region_boundary(image.width(), image.height(), |x, y| {
+      (smooth.get_pixel(x, y)[0] as f32) < threshold
+    });
+    let (spacing, phase) = if level == 0 {
+      (widest, 0.0)
+    }
+    else {
+      (widest / 2f32.powi(level as i32 - 1), 0.5)
+    };
+    strokes.extend(fill::hatch_with_phase(&boundary, spacing, options.angle, phase));
+  }
+  strokes
+}
+
+// weighted Voronoi stippling (Secord, 2002): dots start spread by darkness, then move
+// repeatedly to the darkness weighted centroid of the pixels closest to them
+pub fn stipple_points(image: &GrayImage, options: &ShadingOptions) -> Vec<Point> {
+  let (width, height) = (image.width(), image.height());
+  let weights = image.pixels().map(|p| 1.0 - p[0] as f32 / 255.0).collect::<Vec<f32>>();
+  if options.points == 0 || weights.iter().all(|w| *w <= 0.0) {
+    return vec![];
+  }
+
+  // rejection sampling, a fixed seed keeps the result stable between runs
+  let mut rng = StdRng::seed_from_u64(0x5eed);
+  let mut points: Vec<Point> = vec![];
+  let mut attempts = 0;
+  while points.len() < options.points && attempts < options.points * 1000 {
+    attempts += 1;
+    let (x, y) = (rng.gen_range(0..width), rng.gen_range(0..height));
+    if rng.gen::<f32>() < weights[(y * width + x) as usize] {
+      points.push((x as f32, y as f32));
+    }
+  }
+
+  let cell = PointGrid::cell_for(width as f32, height as f32, points.len());
+  for _ in 0..options.iterations {
+    let grid = PointGrid::new(points.clone(), cell);
+    let mut centroids = vec![(0.0f32, 0.0f32, 0.0f32); points.len()];
+    for y in 0..height {
+      for x in 0..width {
+        let weight = weights[(y * width + x) as usize];
+        if weight <= 0.0 {
+          continue;
+        }
+        if let Some(index) = grid.nearest((x as f32, y as f32)) {
+          let centroid = &mut centroids[index];
+          centroid.0 += weight * x as f32;
+          centroid.1 += weight * y as f32;
+          centroid.2 += weight;
+        }
+      }
+    }
+    for (point, (x, y, weight)) in points.iter_mut().zip(centroids) {
+      if weight > 0.0 {
+        *point = (x / weight, y / weight);
+      }
+    }
+  }
+
+  points
+}
+
+fn stipple(image: &GrayImage, options: &ShadingOptions) -> Vec<Polyline> {
+  stipple_points(image, options)
+    .into_iter()
+    .map(|(x, y)| vec![(x - options.dot_size / 2.0, y), (x + options.dot_size / 2.0, y)])
+    .collect()
+}
+
+fn single_line(image: &GrayImage, options: &ShadingOptions) -> Vec<Polyline> {
+  let points = stipple_points(image, options);
+  let order = tsp::solve(&points, options.tsp_iterations);
+  vec![order.into_iter().map(|index| points[index]).collect()]
+}
+
+// one continuous line going back and forth over the rows, waving more in dark areas
+fn squiggle(image: &GrayImage, options: &ShadingOptions) -> Vec<Polyline> {
+  let spacing = options.spacing.max(1.0);
+  // the darkest areas get a full wave every `spacing` pixels
+  let max_frequency = 2.0 * PI / spacing;
+  let amplitude = spacing * 0.45;
+
+  let mut line: Polyline = vec![];
+  let mut phase = 0.0f32;
+  let mut row = 0;
+  let mut y = spacing / 2.0;
+  while y < image.height() as f32 {
+    let forward = row % 2 == 0;
+    for i in 0..image.width() {
+      let x = if forward { i } else { image.width() - 1 - i } as f32;
+      let dark = darkness(image, x, y);
+      phase += max_frequency * dark;
+      line.push((x, y + amplitude * dark * phase.sin()));
+    }
+    y += spacing;
+    row += 1;
+  }
+  vec![line]
+}
+
+// an archimedean spiral from the center, with the squiggle waves applied to its radius
+fn spiral(image: &GrayImage, options: &ShadingOptions) -> Vec<Polyline> {
+  let spacing = options.spacing.max(1.0);
+  let max_frequency = 2.0 * PI / spacing;
+  let amplitude = spacing * 0.45;
+  let center = (image.width() as f32 / 2.0, image.height() as f32 / 2.0);
+  let max_radius = (center.0 * center.0 + center.1 * center.1).sqrt();
+
+  let mut strokes: Vec<Polyline> = vec![];
+  let mut stroke: Polyline = vec![];
+  let mut phase = 0.0f32;
+  let mut angle = 0.0f32;
+  loop {
+    let radius = spacing * angle / (2.0 * PI);
+    if radius > max_radius {
+      break;
+    }
+    let (sin, cos) = angle.sin_cos();
+    let base = (center.0 + radius * cos, center.1 + radius * sin);
+
+    // parts of the spiral outside of the image are not drawn
+    if inside(image, base) {
+      let dark = darkness(image, base.0, base.1);
+      phase += max_frequency * dark;
+      let offset = radius + amplitude * dark * phase.sin();
+      stroke.push((center.0 + offset * cos, center.1 + offset * sin));
+    }
+    else if !stroke.is_empty() {
+      strokes.push(std::mem::take(&mut stroke));
+    }
+    // about one pixel along the spiral per step
+    angle += 1.0 / radius.max(1.0);
+  }
+  if !stroke.is_empty() {
+    strokes.push(stroke);
+  }
+  strokes
+}
+
+pub fn shade(image: &GrayImage, options: &ShadingOptions) -> Vec<Polyline> {
+  if image.width() == 0 || image.height() == 0 {
+    return vec![];
+  }
+  match options.mode {
+    ShadingMode::Hatch => hatch(image, options),
+    ShadingMode::Stipple => stipple(image, options),
+    ShadingMode::Squiggle => squiggle(image, options),
+    ShadingMode::Spiral => spiral(image, options),
+    ShadingMode::Tsp => single_line(image, options),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::Luma;
+
+  // black on the left half, white on the right half
+  fn half_black() -> GrayImage {
+    GrayImage::from_fn(60, 40, |x, _| Luma([if x < 30 { 0 } else { 255 }]))
+  }
+
+  fn length(line: &Polyline) -> f32 {
+    line.windows(2).map(|pair| crate::path::distance(pair[0], pair[1])).sum()
+  }
+
+  #[test]
+  fn hatch_density() {
+    let options = ShadingOptions { spacing: 2.0, angle: 0.0, levels: 3, ..ShadingOptions::default() };
+    assert!(shade(&GrayImage::from_pixel(40, 40, Luma([255])), &options).is_empty());
+    let lengths = [0, 100, 160].map(|tone| shade(&GrayImage::from_pixel(40, 40, Luma([tone])), &options).iter().map(length).sum::<f32>());
+    assert!(lengths[0] > lengths[1] && lengths[1] > lengths[2] && lengths[2] > 0.0);
+    // the darkest tone gets lines `spacing` apart
+    let lines = shade(&GrayImage::from_pixel(40, 40, Luma([0])), &options).iter().flatten().map(|p| p.1 as i32).collect::<std::collections::BTreeSet<_>>();
+    assert_eq!(lines.iter().copied().collect::<Vec<_>>(), (0..20).map(|i| i * 2).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn stipples_gather_in_dark_areas() {
+    let options = ShadingOptions { mode: ShadingMode::Stipple, points: 100, iterations: 5, dot_size: 1.0, ..ShadingOptions::default() };
+    let points = stipple_points(&half_black(), &options);
+    assert_eq!(points.len(), 100);
+    assert!(points.iter().all(|p| p.0 < 30.0));
+    // the same every time
+    assert_eq!(stipple_points(&half_black(), &options), points);
+
+    let dots = shade(&half_black(), &options);
+    assert_eq!(dots.len(), 100);
+    assert!(dots.iter().all(|dot| (length(dot) - 1.0).abs() < 1e-4));
+    assert!(stipple_points(&GrayImage::from_pixel(10, 10, Luma([255])), &options).is_empty());
+  }
+
+  #[test]
+  fn single_line() {
+    let options = ShadingOptions { mode: ShadingMode::Tsp, points: 50, iterations: 2, ..ShadingOptions::default() };
+    let lines = shade(&half_black(), &options);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].len(), 50);
+  }
+
+  #[test]
+  fn squiggles() {
+    let options = ShadingOptions { mode: ShadingMode::Squiggle, spacing: 10.0, ..ShadingOptions::default() };
+    let lines = shade(&half_black(), &options);
+    assert_eq!(lines.len(), 1);
+    // four rows, back and forth
+    let line = &lines[0];
+    assert_eq!(line.len(), 4 * 60);
+    assert_eq!((line[0].0, line[59].0, line[60].0), (0.0, 59.0, 59.0));
+    // waves on the black half only
+    let row = &line[..60];
+    assert!(row[30..].iter().all(|p| p.1 == 5.0));
+    assert!(row[..30].iter().any(|p| (p.1 - 5.0).abs() > 1.0));
+    assert!(row.iter().all(|p| (p.1 - 5.0).abs() <= 4.5));
+  }
+
+  #[test]
+  fn spirals() {
+    let options = ShadingOptions { mode: ShadingMode::Spiral, spacing: 5.0, ..ShadingOptions::default() };
+    let strokes = shade(&GrayImage::from_pixel(40, 40, Luma([255])), &options);
+    // the corners of the image are reached by pieces of the spiral
+    assert!(strokes.len() > 1);
+    let center = (20.0, 20.0);
+    let radius = |p: &Point| crate::path::distance(*p, center);
+    for stroke in &strokes {
+      assert!(stroke.iter().all(|p| inside(&GrayImage::new(40, 40), *p)));
+      assert!(stroke.windows(2).all(|pair| radius(&pair[1]) >= radius(&pair[0])));
+    }
+    assert!(shade(&GrayImage::new(0, 0), &options).is_empty());
+  }
+}
@@ -0,0 +1,738 @@
+// SVG input: the shapes of a drawing flattened into polylines, in the coordinates of its viewBox
+use crate::fill::{Point, Polyline};
+use crate::vector::{self, Drawing};
+
+// affine transform, as the SVG matrix(a b c d e f)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Transform([f32; 6]);
+
+impl Transform {
+  const IDENTITY: Transform = Transform([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+  fn apply(&self, p: Point) -> Point {
+    let [a, b, c, d, e, f] = self.0;
+    (a * p.0 + c * p.1 + e, b * p.0 + d * p.1 + f)
+  }
+
+  // `other` applied first, then self
+  fn then(&self, other: &Transform) -> Transform {
+    let [a1, b1, c1, d1, e1, f1] = self.0;
+    let [a2, b2, c2, d2, e2, f2] = other.0;
+    Transform([
+      a1 * a2 + c1 * b2,
+      b1 * a2 + d1 * b2,
+      a1 * c2 + c1 * d2,
+      b1 * c2 + d1 * d2,
+      a1 * e2 + c1 * f2 + e1,
+      b1 * e2 + d1 * f2 + f1,
+    ])
+  }
+
+  // largest stretch of a unit vector, curves are flattened before being transformed
+  fn scale(&self) -> f32 {
+    let [a, b, c, d, _, _] = self.0;
+    (a * a + b * b).max(c * c + d * d).sqrt().max(1e-6)
+  }
+}
+
+// numbers and flags of attribute values, separated by whitespace and commas
+struct Lexer<'a> {
+  text: &'a [u8],
+  at: usize,
+}
+
+impl<'a> Lexer<'a> {
+  fn new(text: &'a str) -> Lexer<'a> {
+    Lexer { text: text.as_bytes(), at: 0 }
+  }
+
+  fn skip_separators(&mut self) {
+    while self.at < self.text.len() && (self.text[self.at].is_ascii_whitespace() || self.text[self.at] == b',') {
+      self.at += 1;
+    }
+  }
+
+  fn is_done(&mut self) -> bool {
+    self.skip_separators();
+    self.at >= self.text.len()
+  }
+
+  // the next character when it is a letter
+  fn letter(&mut self) -> Option<char> {
+    self.skip_separators();
+    let c = *self.text.get(self.at)?;
+    if c.is_ascii_alphabetic() && c != b'e' && c != b'E' {
+      self.at += 1;
+      Some(c as char)
+    }
+    else {
+      None
+    }
+  }
+
+  fn number(&mut self) -> Option<f32> {
+    self.skip_separators();
+    let start = self.at;
+    let mut end = self.at;
+    let (mut dot, mut exponent) = (false, false);
+    while end < self.text.len() {
+      let c = self.text[end];
+      let sign = (c == b'-' || c == b'+') && (end == start || matches!(self.text[end - 1], b'e' | b'E'));
+      if c.is_ascii_digit() || sign {
+        end += 1;
+      }
+      // a second dot starts the next number, ie: "0.5.5"
+      else if c == b'.' && !dot && !exponent {
+        dot = true;
+        end += 1;
+      }
+      else if (c == b'e' || c == b'E') && !exponent && end > start {
+        exponent = true;
+        end += 1;
+      }
+      else {
+        break;
+      }
+    }
+    let number = std::str::from_utf8(&self.text[start..end]).ok()?.parse::<f32>().ok()?;
+    self.at = end;
+    Some(number)
+  }
+
+  // arc flags may be written without separators, ie: "a1 1 0 01 5 5"
+  fn flag(&mut self) -> Option<bool> {
+    self.skip_separators();
+    let flag = match self.text.get(self.at)? {
+      b'0' => false,
+      b'1' => true,
+      _ => return None,
+    };
+    self.at += 1;
+    Some(flag)
+  }
+
+  fn point(&mut self) -> Option<Point> {
+    Some((self.number()?, self.number()?))
+  }
+}
+
+fn numbers(text: &str) -> Vec<f32> {
+  let mut lexer = Lexer::new(text);
+  let mut numbers = vec![];
+  while let Some(number) = lexer.number() {
+    numbers.push(number);
+  }
+  numbers
+}
+
+// transform attribute, a list of transform functions applied from right to left
+fn parse_transform(text: &str) -> Transform {
+  let mut transform = Transform::IDENTITY;
+  for function in text.split(')') {
+    let (name, arguments) = match function.split_once('(') {
+      Some((name, arguments)) => (name.trim().trim_start_matches(',').trim(), numbers(arguments)),
+      None => continue,
+    };
+    let arg = |index: usize, default: f32| arguments.get(index).copied().unwrap_or(default);
+    let next = match name {
+      "matrix" if arguments.len() == 6 => Transform([arg(0, 1.0), arg(1, 0.0), arg(2, 0.0), arg(3, 1.0), arg(4, 0.0), arg(5, 0.0)]),
+      "translate" => Transform([1.0, 0.0, 0.0, 1.0, arg(0, 0.0), arg(1, 0.0)]),
+      "scale" => Transform([arg(0, 1.0), 0.0, 0.0, arg(1, arg(0, 1.0)), 0.0, 0.0]),
+      "rotate" => {
+        let (sin, cos) = arg(0, 0.0).to_radians().sin_cos();
+        let (x, y) = (arg(1, 0.0), arg(2, 0.0));
+        // rotation around (x, y)
+        Transform([1.0, 0.0, 0.0, 1.0, x, y])
+          .then(&Transform([cos, sin, -sin, cos, 0.0, 0.0]))
+          .then(&Transform([1.0, 0.0, 0.0, 1.0, -x, -y]))
+      },
+      "skewX" => Transform([1.0, 0.0, arg(0, 0.0).to_radians().tan(), 1.0, 0.0, 0.0]),
+      "skewY" => Transform([1.0, arg(0, 0.0).to_radians().tan(), 0.0, 1.0, 0.0, 0.0]),
+      _ => continue,
+    };
+    transform = transform.then(&next);
+  }
+  transform
+}
+
+// elliptical arc of the path data, from the endpoint parameterization of the SVG specification (F.6.5)
+#[allow(clippy::too_many_arguments)]
+fn arc_to(from: Point, radii: Point, rotation: f32, large: bool, sweep: bool, to: Point, tolerance: f32, points: &mut Polyline) {
+  let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+  if from == to {
+    return;
+  }
+  if rx == 0.0 || ry == 0.0 {
+    points.push(to);
+    return;
+  }
+  let rotation = rotation.to_radians();
+  let (sin, cos) = rotation.sin_cos();
+  let (dx, dy) = ((from.0 - to.0) / 2.0, (from.1 - to.1) / 2.0);
+  let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+
+  // radii too small to reach the endpoint are scaled up
+  let lambda = x1 * x1 / (rx * rx) + y1 * y1 / (ry * ry);
+  if lambda > 1.0 {
+    rx *= lambda.sqrt();
+    ry *= lambda.sqrt();
+  }
+  let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+  let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+  let sign = if large == sweep { -1.0 } else { 1.0 };
+  let coefficient = sign * (numerator / denominator).max(0.0).sqrt();
+  let (cx1, cy1) = (coefficient * rx * y1 / ry, -coefficient * ry * x1 / rx);
+  let center = (cos * cx1 - sin * cy1 + (from.0 + to.0) / 2.0, sin * cx1 + cos * cy1 + (from.1 + to.1) / 2.0);
+
+  let start = ((y1 - cy1) / ry).atan2((x1 - cx1) / rx);
+  let end = ((-y1 - cy1) / ry).atan2((-x1 - cx1) / rx);
+  let mut delta = end - start;
+  if sweep && delta < 0.0 {
+    delta += 2.0 * std::f32::consts::PI;
+  }
+  else if !sweep && delta > 0.0 {
+    delta -= 2.0 * std::f32::consts::PI;
+  }
+  vector::arc(center, (rx, ry), rotation, start, delta, tolerance, points);
+  // lands exactly on the endpoint, so closed shapes stay closed
+  let last = points.len() - 1;
+  points[last] = to;
+}
+
+// path data, each subpath becomes a polyline
+// like SVG renderers, the path is drawn up to the first error
+fn path(d: &str, transform: &Transform, tolerance: f32) -> Vec<Polyline> {
+  let tolerance = tolerance / transform.scale();
+  let mut lexer = Lexer::new(d);
+  let mut subpaths: Vec<Polyline> = vec![];
+  let mut current: Polyline = vec![];
+  let (mut position, mut start) = ((0.0, 0.0), (0.0, 0.0));
+  // second control point of the last curve, reflected by the smooth curves
+  let mut control: Option<(char, Point)> = None;
+  let mut command: Option<char> = None;
+
+  while !lexer.is_done() {
+    if let Some(letter) = lexer.letter() {
+      command = Some(letter);
+    }
+    let letter = match command {
+      Some(letter) => letter,
+      None => break,
+    };
+    let relative = letter.is_ascii_lowercase();
+    let origin = if relative { position } else { (0.0, 0.0) };
+    let offset = |p: Point| (p.0 + origin.0, p.1 + origin.1);
+    if current.is_empty() && !matches!(letter, 'M' | 'm') {
+      current.push(position);
+    }
+
+    let mut next_control = None;
+    let parsed = match letter.to_ascii_uppercase() {
+      'M' => lexer.point().map(|p| {
+        if current.len() > 1 {
+          subpaths.push(std::mem::take(&mut current));
+        }
+        position = offset(p);
+        start = position;
+        current = vec![position];
+        // following pairs are implicit lines
+        command = Some(if relative { 'l' } else { 'L' });
+      }),
+      'L' => lexer.point().map(|p| {
+        position = offset(p);
+        current.push(position);
+      }),
+      'H' => lexer.number().map(|x| {
+        position = (x + origin.0, position.1);
+        current.push(position);
+      }),
+      'V' => lexer.number().map(|y| {
+        position = (position.0, y + origin.1);
+        current.push(position);
+      }),
+      'C' | 'S' => {
+        let first = if letter.eq_ignore_ascii_case(&'C') {
+          lexer.point().map(offset)
+        }
+        else {
+          // reflection of the previous control point, or the current point
+          Some(match control {
+            Some(('C', c)) => (2.0 * position.0 - c.0, 2.0 * position.1 - c.1),
+            _ => position,
+          })
+        };
+        first.and_then(|first| {
+          let second = offset(lexer.point()?);
+          let end = offset(lexer.point()?);
+          vector::cubic(position, first, second, end, tolerance, &mut current);
+          next_control = Some(('C', second));
+          position = end;
+          Some(())
+        })
+      },
+      'Q' | 'T' => {
+        let first = if letter.eq_ignore_ascii_case(&'Q') {
+          lexer.point().map(offset)
+        }
+        else {
+          Some(match control {
+            Some(('Q', c)) => (2.0 * position.0 - c.0, 2.0 * position.1 - c.1),
+            _ => position,
+          })
+        };
+        first.and_then(|first| {
+          let end = offset(lexer.point()?);
+          vector::quadratic(position, first, end, tolerance, &mut current);
+          next_control = Some(('Q', first));
+          position = end;
+          Some(())
+        })
+      },
+      'A' => (|| {
+        let radii = lexer.point()?;
+        let rotation = lexer.number()?;
+        let (large, sweep) = (lexer.flag()?, lexer.flag()?);
+        let end = offset(lexer.point()?);
+        arc_to(position, radii, rotation, large, sweep, end, tolerance, &mut current);
+        position = end;
+        Some(())
+      })(),
+      'Z' => {
+        current.push(start);
+        subpaths.push(std::mem::take(&mut current));
+        position = start;
+        // numbers cannot follow
+        command = None;
+        Some(())
+      },
+      _ => None,
+    };
+    if parsed.is_none() {
+      break;
+    }
+    control = next_control;
+  }
+  if current.len() > 1 {
+    subpaths.push(current);
+  }
+
+  subpaths
+    .into_iter()
+    .map(|subpath| {
+      let mut points = subpath.into_iter().map(|p| transform.apply(p)).collect::<Polyline>();
+      points.dedup();
+      points
+    })
+    .filter(|points| points.len() > 1)
+    .collect()
+}
+
+// a start or empty tag of the document, end tags only carry their name
+struct Tag<'a> {
+  name: &'a str,
+  attributes: Vec<(&'a str, &'a str)>,
+  end: bool,
+  empty: bool,
+}
+
+// user units (px) per absolute unit, relative units depend on fonts and viewports
+const UNITS: [(&str, f32); 6] = [("px", 1.0), ("in", 96.0), ("cm", 96.0 / 2.54), ("mm", 96.0 / 25.4), ("pt", 96.0 / 72.0), ("pc", 16.0)];
+
+// a length in user units
+fn parse_length(value: &str) -> Result<f32, String> {
+  let value = value.trim();
+  let (number, scale) = UNITS
+    .iter()
+    .find_map(|(unit, scale)| value.strip_suffix(unit).map(|number| (number, *scale)))
+    .unwrap_or((value, 1.0));
+  match number.trim().parse::<f32>() {
+    Ok(number) => Ok(number * scale),
+    Err(_) => Err(format!("Unsupported length {:?}, lengths may only use px, in, cm, mm, pt or pc", value)),
+  }
+}
+
+impl<'a> Tag<'a> {
+  fn attribute(&self, name: &str) -> Option<&'a str> {
+    self.attributes.iter().find(|(key, _)| *key == name).map(|(_, value)| *value)
+  }
+
+  // a missing length is 0
+  fn length(&self, name: &str) -> Result<f32, String> {
+    self.attribute(name).map(parse_length).unwrap_or(Ok(0.0))
+  }
+}
+
+fn parse_tag(content: &str) -> Tag<'_> {
+  let end = content.starts_with('/');
+  let empty = content.ends_with('/');
+  let content = content.trim_start_matches('/').trim_end_matches('/');
+  let name_end = content.find(|c: char| c.is_ascii_whitespace()).unwrap_or(content.len());
+  let mut attributes = vec![];
+  let mut rest = &content[name_end..];
+  while let Some(equals) = rest.find('=') {
+    let key = rest[..equals].trim();
+    let value = rest[equals + 1..].trim_start();
+    let quote = match value.chars().next() {
+      Some(quote @ ('"' | '\'')) => quote,
+      _ => break,
+    };
+    let close = match value[1..].find(quote) {
+      Some(close) => close + 1,
+      None => break,
+    };
+    attributes.push((key, &value[1..close]));
+    rest = &value[close + 1..];
+  }
+  Tag { name: &content[..name_end], attributes, end, empty }
+}
+
+// tags of an XML document, comments, declarations and text are skipped
+fn tags(text: &str) -> Vec<Tag<'_>> {
+  let mut tags = vec![];
+  let mut rest = text;
+  while let Some(open) = rest.find('<') {
+    rest = &rest[open..];
+    let skipped = [("<!--", "-->"), ("<![CDATA[", "]]>"), ("<?", "?>"), ("<!", ">")]
+      .iter()
+      .find(|(start, _)| rest.starts_with(start));
+    if let Some((_, end)) = skipped {
+      match rest.find(end) {
+        Some(at) => rest = &rest[at + end.len()..],
+        None => break,
+      }
+      continue;
+    }
+
+    // quoted values may contain '>'
+    let mut quote: Option<char> = None;
+    let close = rest.char_indices().skip(1).find(|(_, c)| {
+      match quote {
+        Some(q) if *c == q => quote = None,
+        Some(_) => {},
+        None if *c == '"' || *c == '\'' => quote = Some(*c),
+        None => return *c == '>',
+      }
+      false
+    });
+    match close {
+      Some((at, _)) => {
+        tags.push(parse_tag(rest[1..at].trim()));
+        rest = &rest[at + 1..];
+      },
+      None => break,
+    }
+  }
+  tags
+}
+
+// contents of the <style> elements
+fn style_sheets(text: &str) -> Vec<&str> {
+  let mut sheets = vec![];
+  let mut rest = text;
+  while let Some(open) = rest.find("<style") {
+    rest = &rest[open + "<style".len()..];
+    let start = match rest.find('>') {
+      Some(at) if rest.starts_with(|c: char| c == '>' || c == '/' || c.is_ascii_whitespace()) => at + 1,
+      Some(_) => continue,
+      None => break,
+    };
+    if rest[..start].ends_with("/>") {
+      continue;
+    }
+    let end = rest.find("</style").unwrap_or(rest.len());
+    sheets.push(&rest[start..end]);
+    rest = &rest[end..];
+  }
+  sheets
+}
+
+// elements whose content is not drawn directly
+const HIDDEN: [&str; 10] = ["defs", "clipPath", "mask", "symbol", "marker", "pattern", "metadata", "title", "desc", "style"];
+
+// path data of the basic shapes
+fn shape_path(tag: &Tag) -> Result<Option<String>, String> {
+  let length = |name: &str| tag.length(name);
+  Ok(match tag.name {
+    "path" => tag.attribute("d").map(|d| d.to_string()),
+    "line" => Some(format!("M {} {} L {} {}", length("x1")?, length("y1")?, length("x2")?, length("y2")?)),
+    "polyline" | "polygon" => {
+      let points = match tag.attribute("points") {
+        Some(points) => numbers(points),
+        None => return Ok(None),
+      };
+      let mut d = points
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(index, p)| format!("{} {} {}", if index == 0 { "M" } else { "L" }, p[0], p[1]))
+        .collect::<Vec<String>>()
+        .join(" ");
+      if tag.name == "polygon" {
+        d.push_str(" Z");
+      }
+      Some(d)
+    },
+    "rect" => {
+      let (x, y, w, h) = (length("x")?, length("y")?, length("width")?, length("height")?);
+      if w <= 0.0 || h <= 0.0 {
+        return Ok(None);
+      }
+      // a missing corner radius takes the value of the other one
+      let (rx, ry) = match (tag.attribute("rx").map(|_| length("rx")).transpose()?, tag.attribute("ry").map(|_| length("ry")).transpose()?) {
+        (Some(rx), Some(ry)) => (rx, ry),
+        (Some(r), None) | (None, Some(r)) => (r, r),
+        (None, None) => (0.0, 0.0),
+      };
+      let (rx, ry) = (rx.clamp(0.0, w / 2.0), ry.clamp(0.0, h / 2.0));
+      if rx == 0.0 || ry == 0.0 {
+        return Ok(Some(format!("M {} {} H {} V {} H {} Z", x, y, x + w, y + h, x)));
+      }
+      Some(format!(
+        "M {} {} H {} A {} {} 0 0 1 {} {} V {} A {} {} 0 0 1 {} {} H {} A {} {} 0 0 1 {} {} V {} A {} {} 0 0 1 {} {} Z",
+        x + rx, y, x + w - rx, rx, ry, x + w, y + ry, y + h - ry, rx, ry, x + w - rx, y + h, x + rx, rx, ry, x, y + h - ry,
+        y + ry, rx, ry, x + rx, y,
+      ))
+    },
+    "circle" | "ellipse" => {
+      let (cx, cy) = (length("cx")?, length("cy")?);
+      let (rx, ry) = if tag.name == "circle" { (length("r")?, length("r")?) } else { (length("rx")?, length("ry")?) };
+      if rx <= 0.0 || ry <= 0.0 {
+        return Ok(None);
+      }
+      Some(format!(
+        "M {} {} A {} {} 0 0 1 {} {} A {} {} 0 0 1 {} {} Z",
+        cx + rx, cy, rx, ry, cx - rx, cy, rx, ry, cx + rx, cy
+      ))
+    },
+    _ => None,
+  })
+}
+
+// viewport of a nested <svg>: its viewBox is fitted into its width and height as preserveAspectRatio says
+fn viewport(tag: &Tag) -> Result<Transform, String> {
+  let (x, y) = (tag.length("x")?, tag.length("y")?);
+  let view_box = match tag.attribute("viewBox").map(numbers).filter(|v| v.len() == 4) {
+    Some(view_box) => view_box,
+    None => return Ok(Transform([1.0, 0.0, 0.0, 1.0, x, y])),
+  };
+  if tag.attribute("width").is_none() || tag.attribute("height").is_none() {
+    return Err("Nested <svg> elements with a viewBox need a width and a height".to_string());
+  }
+  let (width, height) = (tag.length("width")?, tag.length("height")?);
+  if view_box[2] <= 0.0 || view_box[3] <= 0.0 {
+    return Err("The viewBox of a nested <svg> element must have a positive size".to_string());
+  }
+
+  let (mut sx, mut sy) = (width / view_box[2], height / view_box[3]);
+  let (mut dx, mut dy) = (0.0, 0.0);
+  let mut aspect = tag.attribute("preserveAspectRatio").unwrap_or("xMidYMid meet").split_whitespace();
+  let align = aspect.next().unwrap_or("xMidYMid");
+  if align != "none" {
+    // uniform scale, the content is aligned within the room left on one axis
+    let scale = if aspect.next() == Some("slice") { sx.max(sy) } else { sx.min(sy) };
+    let room = (width - view_box[2] * scale, height - view_box[3] * scale);
+    let share = |min: &str, mid: &str| if align.contains(mid) { 0.5 } else if align.contains(min) { 0.0 } else { 1.0 };
+    dx = room.0 * share("xMin", "xMid");
+    dy = room.1 * share("YMin", "YMid");
+    sx = scale;
+    sy = scale;
+  }
+  Ok(Transform([sx, 0.0, 0.0, sy, x + dx - view_box[0] * sx, y + dy - view_box[1] * sy]))
+}
+
+// every shape of the document, flattened so the polylines stray at most `tolerance` from the curves
+pub fn parse(text: &str, tolerance: f32) -> Result<Drawing, String> {
+  let tags = tags(text);
+  let root = tags.iter().find(|tag| tag.name == "svg" && !tag.end).ok_or("No <svg> element found")?;
+
+  // the viewBox sets the coordinates of the drawing, its size otherwise comes from the width and height
+  // a size relative to the page leaves the drawing fitted around its paths
+  let absolute = |name: &str| root.attribute(name).filter(|value| !value.trim_end().ends_with('%')).is_some();
+  let view_box = root.attribute("viewBox").map(numbers).filter(|v| v.len() == 4);
+  let (origin, size) = match &view_box {
+    Some(v) => (Transform([1.0, 0.0, 0.0, 1.0, -v[0], -v[1]]), Some((v[2], v[3]))),
+    None if absolute("width") && absolute("height") => (Transform::IDENTITY, Some((root.length("width")?, root.length("height")?))),
+    None => (Transform::IDENTITY, None),
+  };
+
+  // fills and strokes are ignored anyway, only the rules hiding shapes would change the drawing
+  if style_sheets(text).iter().any(|sheet| sheet.contains("display") || sheet.contains("visibility")) {
+    return Err("CSS <style> sheets setting display or visibility are not supported, use presentation attributes instead".to_string());
+  }
+
+  // transform and visibility of the open elements
+  let mut stack: Vec<(&str, Transform, bool)> = vec![];
+  let mut paths = vec![];
+  for tag in &tags {
+    if tag.end {
+      if let Some(at) = stack.iter().rposition(|(name, _, _)| *name == tag.name) {
+        stack.truncate(at);
+      }
+      continue;
+    }
+    let (parent, parent_hidden) = stack.last().map(|(_, t, h)| (*t, *h)).unwrap_or((origin, false));
+    let mut transform = match tag.attribute("transform") {
+      Some(text) => parent.then(&parse_transform(text)),
+      None => parent,
+    };
+    if tag.name == "svg" && !std::ptr::eq(tag, root) {
+      transform = transform.then(&viewport(tag)?);
+    }
+    let hidden = parent_hidden
+      || HIDDEN.contains(&tag.name)
+      || tag.attribute("display") == Some("none")
+      || tag.attribute("style").map(|style| style.replace(' ', "").contains("display:none")).unwrap_or(false);
+
+    // the shapes these would repeat could not be cut as drawn
+    if tag.name == "use" && !hidden {
+      return Err("<use> elements are not supported, the shapes they reference would be left out".to_string());
+    }
+
+    if !hidden {
+      if let Some(d) = shape_path(tag)? {
+        paths.extend(path(&d, &transform, tolerance));
+      }
+    }
+    if !tag.empty {
+      stack.push((tag.name, transform, hidden));
+    }
+  }
+
+  Ok(match size {
+    Some((width, height)) => Drawing { width, height, paths, circles: vec![] },
+    None => Drawing::fitted(paths),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn near(a: Point, b: Point) -> bool {
+    (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+  }
+
+  fn paths(body: &str) -> Vec<Polyline> {
+    parse(&format!("<svg viewBox=\"0 0 100 100\">{}</svg>", body), 0.01).unwrap().paths
+  }
+
+  #[test]
+  fn lines_and_closing() {
+    let paths = paths("<path d=\"M10 10 l 10 0 h 5 v 5 z M 50 50 60 50 60 60\"/>");
+    assert_eq!(paths, vec![vec![(10.0, 10.0), (20.0, 10.0), (25.0, 10.0), (25.0, 15.0), (10.0, 10.0)], vec![(50.0, 50.0), (60.0, 50.0), (60.0, 60.0)]]);
+  }
+
+  #[test]
+  fn numbers_without_separators() {
+    let paths = paths("<path d=\"M0.5.5L1e1-2\"/>");
+    assert_eq!(paths, vec![vec![(0.5, 0.5), (10.0, -2.0)]]);
+  }
+
+  #[test]
+  fn curves_end_on_their_endpoint() {
+    let paths = paths("<path d=\"M0 0 c 0 10 10 10 10 0 s 10 -10 10 0 Q 25 10 30 0 T 40 0\"/>");
+    let path = &paths[0];
+    assert!(path.len() > 8);
+    assert!(path.contains(&(10.0, 0.0)));
+    assert!(path.contains(&(20.0, 0.0)));
+    assert!(path.contains(&(30.0, 0.0)));
+    assert!(near(path[path.len() - 1], (40.0, 0.0)));
+    // the smooth curve reflects the second control point, so it bulges below
+    assert!(path.iter().any(|p| p.0 > 10.0 && p.0 < 20.0 && p.1 < -5.0));
+  }
+
+  #[test]
+  fn arcs_stay_on_the_circle() {
+    let paths = paths("<path d=\"M0 0 a5 5 0 0110 0\"/>");
+    let path = &paths[0];
+    assert!(path.len() > 4);
+    assert_eq!(path[path.len() - 1], (10.0, 0.0));
+    for p in path {
+      assert!(((p.0 - 5.0).hypot(p.1) - 5.0).abs() < 0.02);
+      // sweep flag set: the angle increases, clockwise on screen through the top of the circle
+      assert!(p.1 <= 1e-3);
+    }
+  }
+
+  #[test]
+  fn transforms() {
+    assert!(near(parse_transform("translate(10 20) scale(2)").apply((1.0, 1.0)), (12.0, 22.0)));
+    assert!(near(parse_transform("rotate(90)").apply((1.0, 0.0)), (0.0, 1.0)));
+    assert!(near(parse_transform("rotate(90, 10, 10)").apply((11.0, 10.0)), (10.0, 11.0)));
+    assert!(near(parse_transform("matrix(1 0 0 1 5 6)").apply((0.0, 0.0)), (5.0, 6.0)));
+
+    let paths = paths("<g transform=\"translate(10 0)\"><line x1=\"0\" y1=\"0\" x2=\"1\" y2=\"0\" transform=\"scale(3)\"/></g>");
+    assert_eq!(paths, vec![vec![(10.0, 0.0), (13.0, 0.0)]]);
+  }
+
+  #[test]
+  fn view_box_origin() {
+    let drawing = parse("<svg viewBox=\"-10 -20 50 60\"><line x1=\"-10\" y1=\"-20\" x2=\"0\" y2=\"0\"/></svg>", 0.1).unwrap();
+    assert_eq!((drawing.width, drawing.height), (50.0, 60.0));
+    assert_eq!(drawing.paths, vec![vec![(0.0, 0.0), (10.0, 20.0)]]);
+  }
+
+  #[test]
+  fn shapes() {
+    let rect = &paths("<rect x=\"1\" y=\"2\" width=\"3\" height=\"4\"/>")[0];
+    assert_eq!(rect, &vec![(1.0, 2.0), (4.0, 2.0), (4.0, 6.0), (1.0, 6.0), (1.0, 2.0)]);
+    let circle = &paths("<circle cx=\"50\" cy=\"50\" r=\"10\"/>")[0];
+    assert_eq!(circle[0], circle[circle.len() - 1]);
+    assert!(circle.iter().all(|p| ((p.0 - 50.0).hypot(p.1 - 50.0) - 10.0).abs() < 0.02));
+    let polygon = &paths("<polygon points=\"0,0 1,0 1,1\"/>")[0];
+    assert_eq!(polygon.len(), 4);
+  }
+
+  #[test]
+  fn hidden_elements() {
+    let paths = paths("<defs><rect width=\"5\" height=\"5\"/></defs><g display=\"none\"><line x2=\"1\"/></g><line x2=\"2\" style=\"display: none\"/>");
+    assert!(paths.is_empty());
+  }
+
+  #[test]
+  fn units() {
+    assert_eq!(parse_length("2in"), Ok(192.0));
+    assert!((parse_length("25.4mm").unwrap() - 96.0).abs() < 1e-3);
+    assert_eq!(parse_length(" 12 "), Ok(12.0));
+    assert!(parse_length("2em").is_err());
+    assert!(parse_length("50%").is_err());
+    assert_eq!(paths("<line x2=\"1in\"/>"), vec![vec![(0.0, 0.0), (96.0, 0.0)]]);
+    assert!(parse("<svg viewBox=\"0 0 10 10\"><rect width=\"1em\" height=\"1\"/></svg>", 0.1).is_err());
+
+    // without a viewBox the size is given in px
+    let drawing = parse("<svg width=\"1in\" height=\"2in\"></svg>", 0.1).unwrap();
+    assert_eq!((drawing.width, drawing.height), (96.0, 192.0));
+  }
+
+  #[test]
+  fn nested_viewports() {
+    let paths = paths("<svg x=\"10\" width=\"20\" height=\"20\" viewBox=\"0 0 10 10\"><line x2=\"10\"/></svg>");
+    assert_eq!(paths, vec![vec![(10.0, 0.0), (30.0, 0.0)]]);
+
+    // meet keeps the aspect ratio and centers the content
+    let paths = self::paths("<svg width=\"40\" height=\"20\" viewBox=\"0 0 10 10\"><line x2=\"10\"/></svg>");
+    assert_eq!(paths, vec![vec![(10.0, 0.0), (30.0, 0.0)]]);
+    let paths = self::paths("<svg width=\"40\" height=\"20\" viewBox=\"0 0 10 10\" preserveAspectRatio=\"xMaxYMin\"><line x2=\"10\"/></svg>");
+    assert_eq!(paths, vec![vec![(20.0, 0.0), (40.0, 0.0)]]);
+    let paths = self::paths("<svg width=\"40\" height=\"20\" viewBox=\"0 0 10 10\" preserveAspectRatio=\"none\"><line x2=\"10\" y2=\"10\"/></svg>");
+    assert_eq!(paths, vec![vec![(0.0, 0.0), (40.0, 20.0)]]);
+
+    assert!(parse("<svg viewBox=\"0 0 10 10\"><svg viewBox=\"0 0 1 1\"></svg></svg>", 0.1).is_err());
+  }
+
+  #[test]
+  fn unsupported_elements() {
+    assert!(parse("<svg><defs><path id=\"a\" d=\"M0 0 L1 1\"/></defs><use href=\"#a\"/></svg>", 0.1).is_err());
+    assert!(parse("<svg><style>.a { display: none }</style><path class=\"a\" d=\"M0 0 L1 1\"/></svg>", 0.1).is_err());
+    assert!(parse("<svg><style><![CDATA[.a{visibility:hidden}]]></style><path class=\"a\" d=\"M0 0 L1 1\"/></svg>", 0.1).is_err());
+    assert!(parse("<svg><style/><path d=\"M0 0 L1 1\"/></svg>", 0.1).is_ok());
+  }
+
+  #[test]
+  fn style_sheets_are_ignored() {
+    // as exported by Illustrator
+    let svg = "<svg viewBox=\"0 0 10 10\"><defs><style>.cls-1{fill:none;stroke:#000;stroke-miterlimit:10;}</style></defs><line class=\"cls-1\" x2=\"5\"/></svg>";
+    assert_eq!(parse(svg, 0.1).unwrap().paths, vec![vec![(0.0, 0.0), (5.0, 0.0)]]);
+    assert_eq!(style_sheets("<style type=\"text/css\">a</style><style/><styles>b</styles><style>c"), vec!["a", "c"]);
+    assert!(parse("<html></html>", 0.1).is_err());
+  }
+}
@@ -0,0 +1,238 @@
+// Holding tabs: short uncut bridges keeping the parts attached to the sheet
+use crate::fill::{Point, Polyline};
+use crate::ordering::ContainmentTree;
+use crate::path::distance;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TabPlacement {
+  // evenly spaced along the contour
+  Even,
+  // in the middle of the longest straight edges, evenly spaced when none is long enough
+  Straight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TabOptions {
+  // tabs on each part
+  pub count: usize,
+  // length of contour left uncut by each tab (mm)
+  pub width: f32,
+  pub placement: TabPlacement,
+}
+
+impl Default for TabOptions {
+  fn default() -> Self {
+    TabOptions {
+      count: 4,
+      width: 2.0,
+      placement: TabPlacement::Even,
+    }
+  }
+}
+
+// the closed contour inside of a cut, between its lead-in and its lead-out
+fn contour_range(cut: &[Point], (lead_in, lead_out): (usize, usize)) -> Option<(usize, usize)> {
+  if cut.len() < lead_in + lead_out + 4 {
+    return None;
+  }
+  let (from, to) = (lead_in, cut.len() - 1 - lead_out);
+  if cut[from] == cut[to] {
+    Some((from, to))
+  }
+  else {
+    None
+  }
+}
+
+// centers of the tabs, as distances along the contour
+fn tab_centers(points: &[Point], lengths: &[f32], options: &TabOptions, width: f32) -> Vec<f32> {
+  let perimeter = lengths[lengths.len() - 1];
+  // at most half of the contour is left uncut
+  let count = options.count.min((perimeter / (2.0 * width)).floor() as usize);
+  if count == 0 {
+    return vec![];
+  }
+
+  if options.placement == TabPlacement::Straight {
+    // edges leaving room for the tab and some cut on both sides
+    let mut edges = (0..points.len() - 1)
+      .map(|index| (index, lengths[index + 1] - lengths[index]))
+      .filter(|(_, length)| *length >= 2.0 * width)
+      .collect::<Vec<(usize, f32)>>();
+    edges.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    if !edges.is_empty() {
+      let mut centers = edges
+        .into_iter()
+        .take(count)
+        .map(|(index, _)| (lengths[index] + lengths[index + 1]) / 2.0)
+        .collect::<Vec<f32>>();
+      centers.sort_by(|a, b| a.partial_cmp(b).unwrap());
+      return centers;
+    }
+  }
+  (0..count).map(|k| (k as f32 + 0.5) * perimeter / count as f32).collect()
+}
+
+// tabs on a closed contour, returned as the contour with a vertex at both ends of each tab,
+// along with the indices of the segments crossing a tab (segment i goes from point i to point i + 1)
+fn add_contour_tabs(points: &[Point], options: &TabOptions, width: f32) -> (Polyline, Vec<usize>) {
+  let mut lengths = vec![0.0];
+  for pair in points.windows(2) {
+    lengths.push(lengths[lengths.len() - 1] + distance(pair[0], pair[1]));
+  }
+  let tabs = tab_centers(points, &lengths, options, width)
+    .into_iter()
+    .map(|center| (center - width / 2.0, center + width / 2.0))
+    .collect::<Vec<(f32, f32)>>();
+  if tabs.is_empty() {
+    return (points.to_vec(), vec![]);
+  }
+  let mut splits = tabs.iter().flat_map(|(start, end)| [*start, *end]).collect::<Vec<f32>>();
+  splits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+  // vertices with their distance along the contour, tab ends included
+  let mut split = vec![(points[0], 0.0)];
+  let mut next = splits.into_iter().peekable();
+  for index in 1..points.len() {
+    let (a, b) = (points[index - 1], points[index]);
+    let (from, to) = (lengths[index - 1], lengths[index]);
+    while let Some(at) = next.next_if(|at| *at < to) {
+      if at > from {
+        let t = (at - from) / (to - from);
+        split.push(((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t), at));
+      }
+    }
+    split.push((b, to));
+  }
+
+  let crossing = (0..split.len() - 1)
+    .filter(|index| {
+      let middle = (split[*index].1 + split[index + 1].1) / 2.0;
+      tabs.iter().any(|(start, end)| middle > *start && middle < *end)
+    })
+    .collect();
+  (split.into_iter().map(|(p, _)| p).collect(), crossing)
+}
+
+// tabs on the closed contours of the parts, holes and open paths are cut through
+// `leads` are the lead-in and lead-out point counts of each cut given by add_leads(), empty without leads
+// returns the cuts with the tab ends inserted, and the tab segments of each cut
+pub fn add_tabs(cuts: Vec<Polyline>, leads: &[(usize, usize)], options: &TabOptions, unit_size: f32) -> (Vec<Polyline>, Vec<Vec<usize>>) {
+  let width = options.width / unit_size;
+  let ranges = cuts
+    .iter()
+    .enumerate()
+    .map(|(index, cut)| contour_range(cut, leads.get(index).copied().unwrap_or((0, 0))))
+    .collect::<Vec<Option<(usize, usize)>>>();
+  let contours = cuts
+    .iter()
+    .zip(&ranges)
+    .map(|(cut, range)| match range {
+      Some((from, to)) => cut[*from..=*to].to_vec(),
+      None => cut.clone(),
+    })
+    .collect::<Vec<Polyline>>();
+  let tree = ContainmentTree::new(&contours);
+
+  let mut tabbed = Vec::with_capacity(cuts.len());
+  let mut tabs = Vec::with_capacity(cuts.len());
+  for (index, (cut, range)) in cuts.into_iter().zip(ranges).enumerate() {
+    let (from, to) = match range {
+      Some(range) if !tree.is_hole(index) && width > 0.0 => range,
+      _ => {
+        tabbed.push(cut);
+        tabs.push(vec![]);
+        continue;
+      },
+    };
+    let (contour, crossing) = add_contour_tabs(&cut[from..=to], options, width);
+    let mut with_tabs = cut[..from].to_vec();
+    with_tabs.extend(contour);
+    with_tabs.extend_from_slice(&cut[to + 1..]);
+    tabs.push(crossing.into_iter().map(|segment| segment + from).collect());
+    tabbed.push(with_tabs);
+  }
+  (tabbed, tabs)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rectangle(width: f32, height: f32) -> Polyline {
+    vec![(0.0, 0.0), (width, 0.0), (width, height), (0.0, height), (0.0, 0.0)]
+  }
+
+  #[test]
+  fn contours_between_leads() {
+    let mut cut = vec![(-2.0, -2.0)];
+    cut.extend(rectangle(10.0, 10.0));
+    cut.push((-1.0, 1.0));
+    assert_eq!(contour_range(&cut, (1, 1)), Some((1, 5)));
+    assert_eq!(contour_range(&cut, (0, 0)), None);
+    assert_eq!(contour_range(&rectangle(10.0, 10.0), (0, 0)), Some((0, 4)));
+    assert_eq!(contour_range(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)], (0, 0)), None);
+  }
+
+  #[test]
+  fn open_paths_going_back_over_a_point() {
+    // a loop followed by more of the path, such as a retraced segment
+    let mut path = rectangle(40.0, 40.0);
+    path.push((0.0, -20.0));
+    let (cuts, tabs) = add_tabs(vec![path.clone()], &[], &TabOptions::default(), 1.0);
+    assert_eq!((&cuts[0], &tabs[0]), (&path, &vec![]));
+  }
+
+  #[test]
+  fn even_tabs() {
+    let (cuts, tabs) = add_tabs(vec![rectangle(40.0, 40.0)], &[], &TabOptions::default(), 1.0);
+    assert_eq!(
+      cuts[0],
+      vec![
+        (0.0, 0.0), (19.0, 0.0), (21.0, 0.0), (40.0, 0.0), (40.0, 19.0), (40.0, 21.0), (40.0, 40.0), (21.0, 40.0), (19.0, 40.0), (0.0, 40.0),
+        (0.0, 21.0), (0.0, 19.0), (0.0, 0.0),
+      ]
+    );
+    assert_eq!(tabs[0], vec![1, 4, 7, 10]);
+
+    // the width is in mm
+    let (cuts, tabs) = add_tabs(vec![rectangle(40.0, 40.0)], &[], &TabOptions { count: 1, ..TabOptions::default() }, 0.5);
+    // around the corner halfway along the contour
+    assert_eq!(cuts[0][..5], [(0.0, 0.0), (40.0, 0.0), (40.0, 38.0), (40.0, 40.0), (38.0, 40.0)]);
+    assert_eq!(tabs[0], vec![2, 3]);
+  }
+
+  #[test]
+  fn tabs_on_straight_edges() {
+    let options = TabOptions { count: 2, placement: TabPlacement::Straight, ..TabOptions::default() };
+    let (cuts, tabs) = add_tabs(vec![rectangle(100.0, 10.0)], &[], &options, 1.0);
+    assert_eq!(cuts[0][1..3], [(49.0, 0.0), (51.0, 0.0)]);
+    assert_eq!(cuts[0][5..7], [(51.0, 10.0), (49.0, 10.0)]);
+    assert_eq!(tabs[0], vec![1, 5]);
+  }
+
+  #[test]
+  fn tabs_leave_most_of_the_contour_cut() {
+    // half of a 16 units long contour
+    let options = TabOptions { count: 10, ..TabOptions::default() };
+    assert_eq!(add_tabs(vec![rectangle(4.0, 4.0)], &[], &options, 1.0).1[0], vec![1, 4, 7, 10]);
+    assert!(add_tabs(vec![rectangle(1.0, 0.9)], &[], &options, 1.0).1[0].is_empty());
+  }
+
+  #[test]
+  fn parts_only() {
+    // a part with a hole, led in and out, and an open edge
+    let hole = vec![(10.0, 10.0), (10.0, 30.0), (30.0, 30.0), (30.0, 10.0), (10.0, 10.0)];
+    let mut part = vec![(-1.0, -1.0)];
+    part.extend(rectangle(40.0, 40.0));
+    part.push((-1.0, 1.0));
+    let open = vec![(50.0, 0.0), (90.0, 0.0)];
+    let (cuts, tabs) = add_tabs(vec![hole.clone(), part, open.clone()], &[(0, 0), (1, 1), (0, 0)], &TabOptions::default(), 1.0);
+    assert_eq!((&cuts[0], &tabs[0]), (&hole, &vec![]));
+    assert_eq!((&cuts[2], &tabs[2]), (&open, &vec![]));
+    // the leads are kept, the tabs are counted from the start of the cut
+    assert_eq!((cuts[1][0], cuts[1][cuts[1].len() - 1]), ((-1.0, -1.0), (-1.0, 1.0)));
+    assert_eq!(tabs[1], vec![2, 5, 8, 11]);
+    assert_eq!((cuts[1][2], cuts[1][3]), ((19.0, 0.0), (21.0, 0.0)));
+  }
+}
@@ -0,0 +1,257 @@
+// Text engraving: lines of text laid out as toolpaths, with a single stroke font or the outlines of a TrueType font
+use crate::fill::{Point, Polyline};
+use crate::hershey;
+use crate::vector;
+use rusttype::{Font, OutlineBuilder, Scale};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextFont {
+  // single stroke Hershey font, for pen plotters and engravers
+  Hershey,
+  // outlines of the embedded DejaVu Sans, for cutting letters out
+  Sans,
+  // outlines of a TrueType or OpenType font file
+  File(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlign {
+  Left,
+  Center,
+  Right,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextOptions {
+  // lines are split on new lines, or on a literal \n
+  pub text: String,
+  pub font: TextFont,
+  // height of the capitals (mm)
+  pub size: f32,
+  // distance between the baselines of the lines, as a multiple of the size
+  pub line_spacing: f32,
+  pub align: TextAlign,
+  // the start, middle or end of the first baseline, following the alignment (mm)
+  pub position: (f32, f32),
+}
+
+impl Default for TextOptions {
+  fn default() -> Self {
+    TextOptions {
+      text: String::new(),
+      font: TextFont::Hershey,
+      size: 10.0,
+      line_spacing: 1.5,
+      align: TextAlign::Left,
+      position: (0.0, 0.0),
+    }
+  }
+}
+
+// collects the contours of a glyph outline, flattened into polylines
+struct Outline {
+  paths: Vec<Polyline>,
+  current: Polyline,
+  offset: Point,
+  tolerance: f32,
+}
+
+impl Outline {
+  fn last(&self) -> Point {
+    self.current.last().copied().unwrap_or(self.offset)
+  }
+
+  fn at(&self, x: f32, y: f32) -> Point {
+    (self.offset.0 + x, self.offset.1 + y)
+  }
+}
+
+impl OutlineBuilder for Outline {
+  fn move_to(&mut self, x: f32, y: f32) {
+    if self.current.len() > 1 {
+      self.paths.push(std::mem::take(&mut self.current));
+    }
+    self.current = vec![self.at(x, y)];
+  }
+
+  fn line_to(&mut self, x: f32, y: f32) {
+    let to = self.at(x, y);
+    self.current.push(to);
+  }
+
+  fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+    let (from, control, to) = (self.last(), self.at(x1, y1), self.at(x, y));
+    vector::quadratic(from, control, to, self.tolerance, &mut self.current);
+  }
+
+  fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+    let (from, first, second, to) = (self.last(), self.at(x1, y1), self.at(x2, y2), self.at(x, y));
+    vector::cubic(from, first, second, to, self.tolerance, &mut self.current);
+  }
+
+  fn close(&mut self) {
+    if let Some(&first) = self.current.first() {
+      if self.current.last() != Some(&first) {
+        self.current.push(first);
+      }
+    }
+    if self.current.len() > 1 {
+      self.paths.push(std::mem::take(&mut self.current));
+    }
+    self.current.clear();
+  }
+}
+
+enum Glyphs<'a> {
+  Hershey,
+  Outlines(Font<'a>, Scale),
+}
+
+impl<'a> Glyphs<'a> {
+  fn load(options: &TextOptions) -> Result<Glyphs<'a>, String> {
+    let font = match &options.font {
+      TextFont::Hershey => return Ok(Glyphs::Hershey),
+      TextFont::Sans => Font::try_from_bytes(include_bytes!("../assets/DejaVuSans.ttf") as &[u8]),
+      TextFont::File(path) => {
+        let bytes = std::fs::read(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        Font::try_from_vec(bytes)
+      },
+    };
+    let font = font.ok_or("The font file is not a valid TrueType or OpenType font")?;
+    // the font is scaled so its capitals are `size` high, fonts without an H get a common ratio instead
+    let reference = Scale::uniform(100.0);
+    let cap_height = font
+      .glyph('H')
+      .scaled(reference)
+      .exact_bounding_box()
+      .map(|b| b.height())
+      .filter(|h| *h > 0.0)
+      .unwrap_or(70.0);
+    Ok(Glyphs::Outlines(font, Scale::uniform(100.0 * options.size / cap_height)))
+  }
+
+  // paths of a line of text starting at the origin, with its baseline along the x axis (y down), and its width
+  fn line(&self, line: &str, size: f32, tolerance: f32) -> (Vec<Polyline>, f32) {
+    let mut paths = vec![];
+    let mut x = 0.0;
+    match self {
+      Glyphs::Hershey => {
+        let scale = size / hershey::CAP_HEIGHT;
+        for c in line.chars() {
+          let (width, strokes) = match hershey::glyph(c).or_else(|| hershey::glyph('?')) {
+            Some(glyph) => glyph,
+            None => continue,
+          };
+          paths.extend(strokes.into_iter().map(|stroke| stroke.into_iter().map(|(gx, gy)| (x + gx * scale, -gy * scale)).collect()));
+          x += width * scale;
+        }
+      },
+      Glyphs::Outlines(font, scale) => {
+        let mut previous = None;
+        for c in line.chars() {
+          let glyph = font.glyph(c).scaled(*scale);
+          if let Some(previous) = previous {
+            x += font.pair_kerning(*scale, previous, glyph.id());
+          }
+          let mut outline = Outline { paths: vec![], current: vec![], offset: (x, 0.0), tolerance };
+          glyph.build_outline(&mut outline);
+          outline.close();
+          paths.extend(outline.paths);
+          x += glyph.h_metrics().advance_width;
+          previous = Some(glyph.id());
+        }
+      },
+    }
+    (paths, x)
+  }
+}
+
+// toolpaths of the text in command units, curves stray at most `tolerance` (command units) from the outlines
+pub fn paths(options: &TextOptions, tolerance: f32, unit_size: f32) -> Result<Vec<Polyline>, String> {
+  let glyphs = Glyphs::load(options)?;
+  let text = options.text.replace("\\n", "\n");
+  let mut paths = vec![];
+  for (index, line) in text.lines().enumerate() {
+    let (line_paths, width) = glyphs.line(line, options.size, tolerance * unit_size);
+    let shift = match options.align {
+      TextAlign::Left => 0.0,
+      TextAlign::Center => width / 2.0,
+      TextAlign::Right => width,
+    };
+    let origin = (options.position.0 - shift, options.position.1 + index as f32 * options.size * options.line_spacing);
+    paths.extend(
+      line_paths
+        .into_iter()
+        .map(|path| path.into_iter().map(|(x, y)| ((origin.0 + x) / unit_size, (origin.1 + y) / unit_size)).collect()),
+    );
+  }
+  Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn hershey(text: &str) -> TextOptions {
+    TextOptions { text: text.to_string(), size: hershey::CAP_HEIGHT, ..TextOptions::default() }
+  }
+
+  fn bounds(paths: &[Polyline]) -> (Point, Point) {
+    crate::path::bounds(&paths.concat())
+  }
+
+  #[test]
+  fn hershey_lines() {
+    // font units are mm at the capital height, y points down
+    assert_eq!(paths(&hershey("I"), 0.1, 1.0).unwrap(), vec![vec![(4.0, -21.0), (4.0, 0.0)]]);
+    assert_eq!(paths(&hershey("II"), 0.1, 1.0).unwrap()[1], vec![(12.0, -21.0), (12.0, 0.0)]);
+    // sizes are in mm, points in command units
+    let options = TextOptions { size: 10.5, position: (1.0, 2.0), ..hershey("I") };
+    assert_eq!(paths(&options, 0.1, 0.5).unwrap(), vec![vec![(6.0, -17.0), (6.0, 4.0)]]);
+    // unknown characters are drawn as a question mark
+    assert_eq!(paths(&hershey("é"), 0.1, 1.0).unwrap(), paths(&hershey("?"), 0.1, 1.0).unwrap());
+    assert!(paths(&hershey(" "), 0.1, 1.0).unwrap().is_empty());
+  }
+
+  #[test]
+  fn alignment_and_line_spacing() {
+    let first = |options: TextOptions| paths(&options, 0.1, 1.0).unwrap()[0][0];
+    assert_eq!(first(TextOptions { align: TextAlign::Center, ..hershey("II") }), (-4.0, -21.0));
+    assert_eq!(first(TextOptions { align: TextAlign::Right, ..hershey("II") }), (-12.0, -21.0));
+
+    for text in ["I\nII", "I\\nII"] {
+      let lines = paths(&TextOptions { line_spacing: 2.0, ..hershey(text) }, 0.1, 1.0).unwrap();
+      assert_eq!(lines.len(), 3);
+      assert_eq!(lines[1], vec![(4.0, 21.0), (4.0, 42.0)]);
+    }
+  }
+
+  #[test]
+  fn outlines() {
+    let options = TextOptions { text: "H".to_string(), font: TextFont::Sans, ..TextOptions::default() };
+    let outline = paths(&options, 0.01, 1.0).unwrap();
+    assert_eq!(outline.len(), 1);
+    assert_eq!(outline[0].first(), outline[0].last());
+    let (min, max) = bounds(&outline);
+    assert!((min.1 + 10.0).abs() < 0.01 && max.1.abs() < 0.01);
+
+    // curves are flattened within the tolerance
+    let o = TextOptions { text: "O".to_string(), ..options };
+    let (coarse, fine) = (paths(&o, 0.5, 1.0).unwrap(), paths(&o, 0.01, 1.0).unwrap());
+    assert_eq!(fine.len(), 2);
+    assert!(coarse.iter().flatten().count() < fine.iter().flatten().count());
+  }
+
+  #[test]
+  fn font_files() {
+    let options = TextOptions { text: "H".to_string(), font: TextFont::File("missing.ttf".into()), ..TextOptions::default() };
+    assert!(paths(&options, 0.1, 1.0).unwrap_err().starts_with("Error reading missing.ttf"));
+
+    let path = std::env::temp_dir().join("text-font-files.ttf");
+    std::fs::write(&path, "not a font").unwrap();
+    let options = TextOptions { font: TextFont::File(path.clone()), ..options };
+    assert_eq!(paths(&options, 0.1, 1.0).unwrap_err(), "The font file is not a valid TrueType or OpenType font");
+    std::fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,288 @@
+// Travelling salesman path: visits every point once, along a single short line
+use crate::fill::Point;
+use crate::path::{bounds, distance, PointGrid};
+
+// candidate moves only connect a point to its closest neighbours
+const NEIGHBOURS: usize = 8;
+// segments of up to this many points are moved by Or-opt
+const OR_OPT_LENGTH: usize = 3;
+// improvements smaller than this are rounding noise
+const EPSILON: f32 = 1e-4;
+
+struct Path<'a> {
+  points: &'a [Point],
+  neighbours: Vec<Vec<usize>>,
+  order: Vec<usize>,
+  // index of each point in order
+  position: Vec<usize>,
+}
+
+impl<'a> Path<'a> {
+  fn distance(&self, a: usize, b: usize) -> f32 {
+    distance(self.points[a], self.points[b])
+  }
+
+  fn update_positions(&mut self, from: usize, to: usize) {
+    for index in from..to {
+      self.position[self.order[index]] = index;
+    }
+  }
+
+  fn reverse(&mut self, from: usize, to: usize) {
+    self.order[from..=to].reverse();
+    self.update_positions(from, to + 1);
+  }
+
+  // 2-opt on an open path: reverses the part between two connections when reconnecting them is shorter
+  fn two_opt(&mut self) -> bool {
+    let n = self.order.len();
+    let mut improved = false;
+    for i in 0..n - 1 {
+      let (a, b) = (self.order[i], self.order[i + 1]);
+      let current = self.distance(a, b);
+
+      // reversing the start or the end of the path only replaces one connection
+      if self.distance(self.order[0], b) < current - EPSILON {
+        self.reverse(0, i);
+        improved = true;
+        continue;
+      }
+      if self.distance(a, self.order[n - 1]) < current - EPSILON {
+        self.reverse(i + 1, n - 1);
+        improved = true;
+        continue;
+      }
+
+      for k in 0..self.neighbours[a].len() {
+        let c = self.neighbours[a][k];
+        if self.distance(a, c) >= current {
+          break; // neighbours are sorted, no closer connection is left
+        }
+        let j = self.position[c];
+        if j > i + 1 {
+          // a b ... c d becomes a c ... b d
+          let mut delta = self.distance(a, c) - current;
+          if j + 1 < n {
+            let d = self.order[j + 1];
+            delta += self.distance(b, d) - self.distance(c, d);
+          }
+          if delta < -EPSILON {
+            self.reverse(i + 1, j);
+            improved = true;
+            break;
+          }
+        }
+        else if j + 1 < i {
+          // c e ... a b becomes c a ... e b
+          let e = self.order[j + 1];
+          let delta = self.distance(c, a) + self.distance(e, b) - self.distance(c, e) - current;
+          if delta < -EPSILON {
+            self.reverse(j + 1, i);
+            improved = true;
+            break;
+          }
+        }
+      }
+    }
+    improved
+  }
+
+  // length added by connecting first...last between u and v
+  fn insertion_cost(&self, u: Option<usize>, v: Option<usize>, first: usize, last: usize) -> f32 {
+    let mut cost = 0.0;
+    if let Some(u) = u {
+      cost += self.distance(u, first);
+    }
+    if let Some(v) = v {
+      cost += self.distance(last, v);
+    }
+    if let (Some(u), Some(v)) = (u, v) {
+      cost -= self.distance(u, v);
+    }
+    cost
+  }
+
+  // Or-opt: moves the segment starting at `start` next to one of the neighbours of its ends, possibly reversed
+  fn move_segment(&mut self, start: usize, length: usize) -> bool {
+    let n = self.order.len();
+    let end = start + length - 1;
+    let (first, last) = (self.order[start], self.order[end]);
+    let previous = if start > 0 { Some(self.order[start - 1]) } else { None };
+    let next = self.order.get(end + 1).copied();
+    let removal_gain = self.insertion_cost(previous, next, first, last);
+    let in_segment = |point: usize, position: &[usize]| position[point] >= start && position[point] <= end;
+
+    let mut best: Option<(f32, Option<usize>, Option<usize>, bool)> = None;
+    for &c in self.neighbours[first].iter().chain(self.neighbours[last].iter()) {
+      if in_segment(c, &self.position) {
+        continue;
+      }
+      let at = self.position[c];
+      // the slots after and before c
+      let slots = [
+        (Some(c), self.order.get(at + 1).copied()),
+        (if at > 0 { Some(self.order[at - 1]) } else { None }, Some(c)),
+      ];
+      for (u, v) in slots {
+        if matches!(u, Some(u) if in_segment(u, &self.position)) || matches!(v, Some(v) if in_segment(v, &self.position)) {
+          continue;
+        }
+        for reversed in [false, true] {
+          let cost = if reversed {
+            self.insertion_cost(u, v, last, first)
+          }
+          else {
+            self.insertion_cost(u, v, first, last)
+          };
+          let delta = cost - removal_gain;
+          let better = match best {
+            Some((best_delta, ..)) => delta < best_delta,
+            None => true,
+          };
+          if delta < -EPSILON && better {
+            best = Some((delta, u, v, reversed));
+          }
+        }
+      }
+    }
+
+    match best {
+      Some((_, u, _, reversed)) => {
+        let mut segment = self.order.drain(start..=end).collect::<Vec<usize>>();
+        if reversed {
+          segment.reverse();
+        }
+        let at = match u {
+          Some(u) => self.order.iter().position(|&p| p == u).unwrap() + 1,
+          None => 0,
+        };
+        self.order.splice(at..at, segment);
+        self.update_positions(0, n);
+        true
+      },
+      None => false,
+    }
+  }
+
+  fn or_opt(&mut self) -> bool {
+    let mut improved = false;
+    for length in 1..=OR_OPT_LENGTH {
+      let mut start = 0;
+      while start + length <= self.order.len() {
+        improved |= self.move_segment(start, length);
+        start += 1;
+      }
+    }
+    improved
+  }
+}
+
+// greedy nearest neighbour path, starting with the point closest to the origin
+fn nearest_neighbour(points: &[Point], cell: f32) -> Vec<usize> {
+  let mut grid = PointGrid::new(points.to_vec(), cell);
+  let mut order = Vec::with_capacity(points.len());
+  let mut position = (0.0, 0.0);
+  while let Some(index) = grid.nearest(position) {
+    grid.remove(index);
+    order.push(index);
+    position = points[index];
+  }
+  order
+}
+
+// visiting order of the points: a nearest neighbour path improved by 2-opt and Or-opt passes
+// until nothing improves or the iteration budget runs out
+pub fn solve(points: &[Point], iterations: usize) -> Vec<usize> {
+  if points.len() < 4 {
+    return nearest_neighbour(points, 1.0);
+  }
+  let (low, high) = bounds(points);
+  let cell = PointGrid::cell_for(high.0 - low.0, high.1 - low.1, points.len());
+
+  let neighbours = {
+    let grid = PointGrid::new(points.to_vec(), cell);
+    points
+      .iter()
+      .enumerate()
+      .map(|(index, p)| {
+        grid
+          .nearest_k(*p, NEIGHBOURS + 1)
+          .into_iter()
+          .filter(|&other| other != index)
+          .take(NEIGHBOURS)
+          .collect()
+      })
+      .collect()
+  };
+
+  let order = nearest_neighbour(points, cell);
+  let mut position = vec![0; points.len()];
+  for (index, point) in order.iter().enumerate() {
+    position[*point] = index;
+  }
+  let mut path = Path {
+    points,
+    neighbours,
+    order,
+    position,
+  };
+
+  for _ in 0..iterations {
+    let two_opt = path.two_opt();
+    let or_opt = path.or_opt();
+    if !two_opt && !or_opt {
+      break;
+    }
+  }
+  path.order
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::rngs::StdRng;
+  use rand::{Rng, SeedableRng};
+
+  fn length(points: &[Point], order: &[usize]) -> f32 {
+    order.windows(2).map(|pair| distance(points[pair[0]], points[pair[1]])).sum()
+  }
+
+  fn is_permutation(order: &[usize], count: usize) -> bool {
+    let mut sorted = order.to_vec();
+    sorted.sort();
+    sorted == (0..count).collect::<Vec<usize>>()
+  }
+
+  #[test]
+  fn small_inputs() {
+    assert!(solve(&[], 10).is_empty());
+    assert_eq!(solve(&[(5.0, 5.0)], 10), vec![0]);
+    assert_eq!(solve(&[(9.0, 0.0), (1.0, 0.0), (5.0, 0.0)], 10), vec![1, 2, 0]);
+  }
+
+  #[test]
+  fn points_on_a_line() {
+    // shuffled, the shortest path goes from one end to the other
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut xs = (0..50).map(|x| x as f32).collect::<Vec<f32>>();
+    for i in (1..xs.len()).rev() {
+      xs.swap(i, rng.gen_range(0..=i));
+    }
+    let points = xs.iter().map(|x| (*x, 3.0)).collect::<Vec<Point>>();
+    let order = solve(&points, 20);
+    assert!(is_permutation(&order, points.len()));
+    assert!((length(&points, &order) - 49.0).abs() < 1e-3);
+  }
+
+  #[test]
+  fn shorter_than_the_greedy_path() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let points = (0..1000).map(|_| (rng.gen_range(0.0..200.0), rng.gen_range(0.0..200.0))).collect::<Vec<Point>>();
+    let greedy = length(&points, &nearest_neighbour(&points, 6.0));
+    let order = solve(&points, 20);
+    assert!(is_permutation(&order, points.len()));
+    assert!(length(&points, &order) < greedy * 0.95);
+    // no iterations leave the greedy path as it is
+    assert_eq!(length(&points, &solve(&points, 0)), greedy);
+  }
+}
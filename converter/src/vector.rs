@@ -0,0 +1,321 @@
+// Vector drawings read from files instead of traced from images, curves are flattened into polylines
+use crate::fill::{Point, Polyline};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drawing {
+  // size of the drawing, in the units of its paths
+  pub width: f32,
+  pub height: f32,
+  pub paths: Vec<Polyline>,
+  // circles the paths were flattened from, as (center, radius), so that G-code can follow them with exact arcs
+  pub circles: Vec<(Point, f32)>,
+}
+
+impl Drawing {
+  // a drawing sized to fit its paths, when the file does not tell
+  pub fn fitted(paths: Vec<Polyline>) -> Drawing {
+    let (width, height) = paths
+      .iter()
+      .flatten()
+      .fold((0.0f32, 0.0f32), |(w, h), p| (w.max(p.0), h.max(p.1)));
+    Drawing { width, height, paths, circles: vec![] }
+  }
+}
+
+fn lerp(a: Point, b: Point, t: f32) -> Point {
+  (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+fn length(v: Point) -> f32 {
+  (v.0 * v.0 + v.1 * v.1).sqrt()
+}
+
+// number of segments keeping a bezier curve within `tolerance` of its polyline (Wang's formula)
+// `bend` is the largest second difference of the control points, `factor` depends on the degree of the curve
+fn segments(bend: f32, factor: f32, tolerance: f32) -> usize {
+  ((factor * bend / tolerance.max(1e-4)).sqrt().ceil() as usize).clamp(1, 1000)
+}
+
+// points of a quadratic bezier curve, the first control point is not included
+pub fn quadratic(p0: Point, p1: Point, p2: Point, tolerance: f32, points: &mut Polyline) {
+  let bend = length((p0.0 - 2.0 * p1.0 + p2.0, p0.1 - 2.0 * p1.1 + p2.1));
+  let n = segments(bend, 0.25, tolerance);
+  for step in 1..=n {
+    let t = step as f32 / n as f32;
+    points.push(lerp(lerp(p0, p1, t), lerp(p1, p2, t), t));
+  }
+}
+
+// points of a cubic bezier curve, the first control point is not included
+pub fn cubic(p0: Point, p1: Point, p2: Point, p3: Point, tolerance: f32, points: &mut Polyline) {
+  let bend = length((p0.0 - 2.0 * p1.0 + p2.0, p0.1 - 2.0 * p1.1 + p2.1))
+    .max(length((p1.0 - 2.0 * p2.0 + p3.0, p1.1 - 2.0 * p2.1 + p3.1)));
+  let n = segments(bend, 0.75, tolerance);
+  for step in 1..=n {
+    let t = step as f32 / n as f32;
+    let (a, b, c) = (lerp(p0, p1, t), lerp(p1, p2, t), lerp(p2, p3, t));
+    points.push(lerp(lerp(a, b, t), lerp(b, c, t), t));
+  }
+}
+
+// points of an elliptical arc going from `start` through `sweep` radians (counterclockwise when positive)
+// `rotation` is the angle of the x radius from the x axis, the starting point is not included
+pub fn arc(center: Point, radii: Point, rotation: f32, start: f32, sweep: f32, tolerance: f32, points: &mut Polyline) {
+  let radius = radii.0.abs().max(radii.1.abs());
+  // angle spanned by a chord that strays `tolerance` from the arc
+  let step = if radius > tolerance { 2.0 * (1.0 - tolerance / radius).acos() } else { PI / 2.0 };
+  let n = ((sweep.abs() / step.max(1e-3)).ceil() as usize).clamp(1, 1000);
+  let (sin, cos) = rotation.sin_cos();
+  for index in 1..=n {
+    let angle = start + sweep * index as f32 / n as f32;
+    let (x, y) = (radii.0 * angle.cos(), radii.1 * angle.sin());
+    points.push((center.0 + x * cos - y * sin, center.1 + x * sin + y * cos));
+  }
+}
+
+// points of a circular arc from `from` to `to` around `center`, a full turn when both ends are the same
+// the radius is the one at `from`, the last point lands exactly on `to`
+pub fn arc_between(from: Point, to: Point, center: Point, clockwise: bool, tolerance: f32, points: &mut Polyline) {
+  let start = (from.1 - center.1).atan2(from.0 - center.0);
+  let mut sweep = (to.1 - center.1).atan2(to.0 - center.0) - start;
+  if clockwise && sweep >= 0.0 {
+    sweep -= 2.0 * PI;
+  }
+  else if !clockwise && sweep <= 0.0 {
+    sweep += 2.0 * PI;
+  }
+  let radius = length((from.0 - center.0, from.1 - center.1));
+  arc(center, (radius, radius), 0.0, start, sweep, tolerance, points);
+  let last = points.len() - 1;
+  points[last] = to;
+}
+
+// closed polyline around an ellipse, starting and ending on its x radius
+pub fn ellipse(center: Point, radii: Point, tolerance: f32) -> Polyline {
+  let mut points = vec![(center.0 + radii.0, center.1)];
+  arc(center, radii, 0.0, 0.0, 2.0 * PI, tolerance, &mut points);
+  // the last point lands on the first one, give or take the rounding
+  let last = points.len() - 1;
+  points[last] = points[0];
+  points
+}
+
+// point of a rational b-spline at `t`, from the homogeneous control points (de Boor's algorithm)
+fn de_boor(degree: usize, knots: &[f32], controls: &[(f32, f32, f32)], span: usize, t: f32) -> Point {
+  let mut d = (0..=degree).map(|j| controls[span + j - degree]).collect::<Vec<_>>();
+  for r in 1..=degree {
+    for j in (r..=degree).rev() {
+      let i = span + j - degree;
+      let denominator = knots[i + degree + 1 - r] - knots[i];
+      let alpha = if denominator == 0.0 { 0.0 } else { (t - knots[i]) / denominator };
+      d[j] = (
+        (1.0 - alpha) * d[j - 1].0 + alpha * d[j].0,
+        (1.0 - alpha) * d[j - 1].1 + alpha * d[j].1,
+        (1.0 - alpha) * d[j - 1].2 + alpha * d[j].2,
+      );
+    }
+  }
+  let (x, y, w) = d[degree];
+  (x / w, y / w)
+}
+
+// polyline along a (rational) b-spline, weights default to 1
+// each knot span is split as a bezier curve of the same degree would be
+pub fn nurbs(degree: usize, knots: &[f32], controls: &[Point], weights: &[f32], tolerance: f32) -> Polyline {
+  if degree == 0 || controls.len() <= degree || knots.len() != controls.len() + degree + 1 {
+    return controls.to_vec();
+  }
+  let homogeneous = controls
+    .iter()
+    .enumerate()
+    .map(|(i, p)| {
+      let w = weights.get(i).copied().filter(|w| *w > 0.0).unwrap_or(1.0);
+      (p.0 * w, p.1 * w, w)
+    })
+    .collect::<Vec<_>>();
+
+  let factor = (degree * (degree - 1)) as f32 / 8.0;
+  let mut points = vec![de_boor(degree, knots, &homogeneous, degree, knots[degree])];
+  for span in degree..controls.len() {
+    let (from, to) = (knots[span], knots[span + 1]);
+    if to <= from {
+      continue;
+    }
+    let bend = controls[span - degree..=span]
+      .windows(3)
+      .map(|w| length((w[0].0 - 2.0 * w[1].0 + w[2].0, w[0].1 - 2.0 * w[1].1 + w[2].1)))
+      .fold(0.0, f32::max);
+    let n = segments(bend, factor.max(0.25), tolerance);
+    for step in 1..=n {
+      points.push(de_boor(degree, knots, &homogeneous, span, from + (to - from) * step as f32 / n as f32));
+    }
+  }
+  points
+}
+
+// joins the pieces sharing an end (within `tolerance`) into continuous paths, reversing pieces as needed
+// a path coming back to its first point is closed on it exactly
+// the ends of the pieces are indexed on a grid of `tolerance` wide cells, only those of the nearby cells are compared
+pub fn chain(pieces: Vec<Polyline>, tolerance: f32) -> Vec<Polyline> {
+  let near = |a: Point, b: Point| length((a.0 - b.0, a.1 - b.1)) <= tolerance;
+  let size = tolerance.max(1e-6);
+  let cell = |p: Point| ((p.0 / size).floor() as i64, (p.1 / size).floor() as i64);
+  let mut pieces = pieces.into_iter().filter(|p| !p.is_empty()).map(Some).collect::<Vec<_>>();
+  let mut paths = vec![];
+
+  let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+  for (index, piece) in pieces.iter().enumerate() {
+    if let Some(piece) = piece {
+      grid.entry(cell(piece[0])).or_default().push(index);
+      grid.entry(cell(piece[piece.len() - 1])).or_default().push(index);
+    }
+  }
+
+  for index in 0..pieces.len() {
+    let mut path = match pieces[index].take() {
+      Some(path) => path,
+      None => continue,
+    };
+    // grows the path at its end, then at its start
+    for _ in 0..2 {
+      while path.first() != path.last() || path.len() == 1 {
+        let end = path[path.len() - 1];
+        // the first piece of the file starting or ending there
+        let (x, y) = cell(end);
+        let next = (x - 1..=x + 1)
+          .flat_map(|x| (y - 1..=y + 1).map(move |y| (x, y)))
+          .filter_map(|key| grid.get(&key))
+          .flatten()
+          .copied()
+          .filter(|&next| match &pieces[next] {
+            Some(piece) => near(end, piece[0]) || near(end, piece[piece.len() - 1]),
+            None => false,
+          })
+          .min();
+        match next.and_then(|next| pieces[next].take()) {
+          Some(mut piece) => {
+            if !near(end, piece[0]) {
+              piece.reverse();
+            }
+            path.extend(piece.into_iter().skip(1));
+          },
+          None => break,
+        }
+        if path.len() > 2 && near(path[0], path[path.len() - 1]) {
+          let last = path.len() - 1;
+          path[last] = path[0];
+        }
+      }
+      path.reverse();
+    }
+    paths.push(path);
+  }
+  paths
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn near(a: Point, b: Point, tolerance: f32) -> bool {
+    length((a.0 - b.0, a.1 - b.1)) <= tolerance
+  }
+
+  #[test]
+  fn fitted_size() {
+    let drawing = Drawing::fitted(vec![vec![(1.0, 2.0), (5.0, 3.0)], vec![(2.0, 7.0)]]);
+    assert_eq!((drawing.width, drawing.height), (5.0, 7.0));
+  }
+
+  #[test]
+  fn beziers_stay_within_tolerance() {
+    let mut points = vec![(0.0, 0.0)];
+    quadratic((0.0, 0.0), (5.0, 10.0), (10.0, 0.0), 0.01, &mut points);
+    assert!(points.len() > 3);
+    assert_eq!(points[points.len() - 1], (10.0, 0.0));
+    // the top of the curve is half way to the control point, the points are on the curve
+    let top = points.iter().map(|p| p.1).fold(0.0, f32::max);
+    assert!(top <= 5.0 && top > 4.95);
+    assert!(points.iter().all(|p| (p.1 - (2.0 * p.0 - p.0 * p.0 / 5.0)).abs() < 1e-3));
+
+    let mut points = vec![(0.0, 0.0)];
+    cubic((0.0, 0.0), (0.0, 10.0), (10.0, 10.0), (10.0, 0.0), 0.01, &mut points);
+    assert_eq!(points[points.len() - 1], (10.0, 0.0));
+    let top = points.iter().map(|p| p.1).fold(0.0, f32::max);
+    assert!(top <= 7.5 && top > 7.45);
+
+    // a straight curve is a single segment
+    let mut points = vec![];
+    cubic((0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0), 0.01, &mut points);
+    assert_eq!(points, vec![(3.0, 0.0)]);
+  }
+
+  #[test]
+  fn arcs_between_points() {
+    let mut points = vec![(10.0, 0.0)];
+    arc_between((10.0, 0.0), (0.0, 10.0), (0.0, 0.0), false, 0.01, &mut points);
+    assert_eq!(points[points.len() - 1], (0.0, 10.0));
+    assert!(points.iter().all(|p| (length(*p) - 10.0).abs() < 0.01 && p.0 >= -1e-3 && p.1 >= -1e-3));
+
+    // the other way around the circle
+    let mut points = vec![(10.0, 0.0)];
+    arc_between((10.0, 0.0), (0.0, 10.0), (0.0, 0.0), true, 0.01, &mut points);
+    assert!(points.iter().any(|p| p.0 < -9.0));
+    assert!(points.iter().any(|p| p.1 < -9.0));
+
+    // a full turn when both ends are the same
+    let mut points = vec![(10.0, 0.0)];
+    arc_between((10.0, 0.0), (10.0, 0.0), (0.0, 0.0), false, 0.01, &mut points);
+    assert!(points.iter().any(|p| p.0 < -9.0));
+    assert_eq!(points[points.len() - 1], (10.0, 0.0));
+  }
+
+  #[test]
+  fn closed_ellipse() {
+    let points = ellipse((1.0, 1.0), (4.0, 2.0), 0.01);
+    assert_eq!(points[0], (5.0, 1.0));
+    assert_eq!(points[0], points[points.len() - 1]);
+    assert!(points.iter().all(|p| (((p.0 - 1.0) / 4.0).powi(2) + ((p.1 - 1.0) / 2.0).powi(2) - 1.0).abs() < 0.01));
+  }
+
+  #[test]
+  fn splines() {
+    // a clamped spline of degree 1 is its control polygon
+    let controls = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)];
+    let points = nurbs(1, &[0.0, 0.0, 0.5, 1.0, 1.0], &controls, &[], 0.01);
+    assert_eq!(points, controls);
+
+    // a clamped spline of degree 2 over 3 points is a quadratic bezier curve
+    let controls = vec![(0.0, 0.0), (5.0, 10.0), (10.0, 0.0)];
+    let points = nurbs(2, &[0.0, 0.0, 0.0, 1.0, 1.0, 1.0], &controls, &[], 0.01);
+    assert!(near(points[0], (0.0, 0.0), 1e-4));
+    assert!(near(points[points.len() - 1], (10.0, 0.0), 1e-4));
+    assert!(points.iter().all(|p| (p.1 - (2.0 * p.0 - p.0 * p.0 / 5.0)).abs() < 1e-3));
+
+    // weights pull the curve towards their control point
+    let heavy = nurbs(2, &[0.0, 0.0, 0.0, 1.0, 1.0, 1.0], &controls, &[1.0, 3.0, 1.0], 0.01);
+    assert!(heavy.iter().map(|p| p.1).fold(0.0, f32::max) > 6.0);
+
+    // invalid knots leave the control points as they are
+    assert_eq!(nurbs(2, &[0.0, 1.0], &controls, &[], 0.01), controls);
+  }
+
+  #[test]
+  fn chained_pieces() {
+    let pieces = vec![
+      vec![(0.0, 0.0), (1.0, 0.0)],
+      vec![(1.0, 1.0), (1.0, 0.0)],
+      vec![(5.0, 5.0), (6.0, 5.0)],
+      vec![(0.0, 1.0), (1.0, 1.0)],
+      vec![(0.0, 1.0), (0.0, 0.05)],
+    ];
+    let paths = chain(pieces, 0.1);
+    assert_eq!(paths.len(), 2);
+    // the square is closed exactly on its first point
+    assert_eq!(paths[0].len(), 5);
+    assert_eq!(paths[0][0], paths[0][4]);
+    assert_eq!(paths[1], vec![(5.0, 5.0), (6.0, 5.0)]);
+  }
+}
@@ -0,0 +1,109 @@
+// Command line of the simulator
+use clap::{Arg, App, ArgGroup, ArgMatches};
+
+use converter::args_parse::parse_envelope;
+use converter::machine::Envelope;
+use converter::resume::ResumePoint;
+
+use crate::live::Transport;
+
+pub struct SimulatorArgs {
+    // image, drawing or job file to convert instead of the image in ./assets
+    pub input: Option<String>,
+    // the playback starts where a resumed job would start
+    pub resume: Option<ResumePoint>,
+    // the simulator acts as a virtual GRBL device
+    pub transport: Option<Transport>,
+    // soft limits of the simulated machine (mm), unlimited when missing
+    pub envelope: Option<Envelope>,
+}
+
+fn get_raw() -> ArgMatches {
+    App::new("simulator")
+        .version("0.1.0")
+        .author("Virghileanu Teodor <@GaussianWonder>")
+        .about("Simulator for the converter package")
+        .arg(Arg::new("input")
+            .long("input")
+            .value_name("PATH")
+            .help("Converts the given image, drawing or job file instead of the first image in ./assets")
+            .takes_value(true))
+        .arg(Arg::new("envelope")
+            .long("envelope")
+            .value_name("X_MIN,Y_MIN,X_MAX,Y_MAX")
+            .help("Sets the soft limits of the simulated machine (mm). Segments outside of it are drawn in red")
+            .takes_value(true))
+        .arg(Arg::new("resume_line")
+            .long("resume-line")
+            .value_name("LINE")
+            .help("Starts the playback at the given (1 based) line, where a resumed job would start")
+            .takes_value(true))
+        .arg(Arg::new("resume_edge")
+            .long("resume-edge")
+            .value_name("INDEX")
+            .help("Starts the playback at the start of the given (0 based) edge")
+            .takes_value(true))
+        .arg(Arg::new("resume_percent")
+            .long("resume-percent")
+            .value_name("PERCENT")
+            .help("Starts the playback at the given percentage of the estimated job time")
+            .takes_value(true))
+        .group(ArgGroup::new("resume_point")
+            .args(&["resume_line", "resume_edge", "resume_percent"]))
+        .arg(Arg::new("grbl_pty")
+            .long("grbl-pty")
+            .help("Acts as a GRBL device on a pseudo-terminal, drawing the G-code it receives"))
+        .arg(Arg::new("grbl_tcp")
+            .long("grbl-tcp")
+            .value_name("ADDRESS")
+            .help("Acts as a GRBL device listening on the given TCP address, drawing the G-code it receives")
+            .takes_value(true)
+            .min_values(0)
+            .default_missing_value("127.0.0.1:2323"))
+        .group(ArgGroup::new("transport")
+            .args(&["grbl_pty", "grbl_tcp"]))
+        .get_matches()
+}
+
+fn parse_index(args: &ArgMatches, name: &str) -> Option<usize> {
+    args.value_of(name).map(|value| match value.parse::<usize>() {
+        Ok(v) => v,
+        Err(_) => {
+            panic!("The {} provided is not a valid integer.", name.replace('_', " "));
+        }
+    })
+}
+
+fn get_resume_point(args: &ArgMatches) -> Option<ResumePoint> {
+    if let Some(line) = parse_index(args, "resume_line") {
+        return Some(ResumePoint::Line(line));
+    }
+    if let Some(edge) = parse_index(args, "resume_edge") {
+        return Some(ResumePoint::Edge(edge));
+    }
+    args.value_of("resume_percent").map(|value| match value.parse::<f32>() {
+        Ok(v) => ResumePoint::Percent(v),
+        Err(_) => {
+            panic!("The resume percent provided is not a valid float32.");
+        }
+    })
+}
+
+fn get_transport(args: &ArgMatches) -> Option<Transport> {
+    if args.is_present("grbl_pty") {
+        Some(Transport::Pty)
+    }
+    else {
+        args.value_of("grbl_tcp").map(|address| Transport::Tcp(address.to_string()))
+    }
+}
+
+pub fn parse() -> SimulatorArgs {
+    let args = get_raw();
+    SimulatorArgs {
+        input: args.value_of("input").map(|input| input.to_string()),
+        resume: get_resume_point(&args),
+        transport: get_transport(&args),
+        envelope: args.value_of("envelope").map(parse_envelope),
+    }
+}
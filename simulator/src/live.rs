@@ -0,0 +1,108 @@
+// Virtual GRBL device: G-code received on a pseudo-terminal or a TCP socket drives the tracer
+use converter::command::Command;
+use converter::grbl::{Controller, Emulator, Interpreter};
+use converter::machine::MachineModel;
+
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub enum LiveEvent {
+    Commands(Vec<Command>),
+    Hold(bool),
+    Reset,
+}
+
+// where the virtual device listens
+pub enum Transport {
+    Pty,
+    Tcp(String),
+}
+
+// interprets G-code and forwards the resulting commands to the simulation
+struct SimulatedController {
+    interpreter: Interpreter,
+    events: Sender<LiveEvent>,
+    // animated head position (mm), updated by the simulation
+    head: Arc<Mutex<(f32, f32)>>,
+}
+
+impl Controller for SimulatedController {
+    fn execute(&mut self, block: &str) -> Result<(), u8> {
+        self.interpreter.execute(block)?;
+        let commands = self.interpreter.take_commands();
+        if !commands.is_empty() {
+            let _ = self.events.send(LiveEvent::Commands(commands));
+        }
+        Ok(())
+    }
+
+    fn position(&self) -> (f32, f32, f32) {
+        let (x, y) = *self.head.lock().unwrap();
+        (x, y, self.interpreter.z)
+    }
+
+    fn alarm(&self) -> Option<u8> {
+        self.interpreter.alarm()
+    }
+
+    fn unlock(&mut self) {
+        self.interpreter.unlock();
+    }
+
+    fn reset(&mut self) {
+        self.interpreter.reset();
+        self.interpreter.take_commands();
+        let _ = self.events.send(LiveEvent::Reset);
+    }
+
+    fn feed_hold(&mut self, hold: bool) {
+        let _ = self.events.send(LiveEvent::Hold(hold));
+    }
+}
+
+pub struct LiveLink {
+    pub events: Receiver<LiveEvent>,
+    pub head: Arc<Mutex<(f32, f32)>>,
+}
+
+// starts the virtual device on a background thread, one client is served at a time
+pub fn start(transport: Transport, machine: &MachineModel) -> LiveLink {
+    let (tx, rx) = mpsc::channel();
+    let head = Arc::new(Mutex::new((0.0, 0.0)));
+    let mut emulator = Emulator::new(SimulatedController {
+        interpreter: Interpreter::new(machine.unit_size, machine.envelope),
+        events: tx,
+        head: head.clone(),
+    });
+
+    thread::spawn(move || match transport {
+        Transport::Tcp(address) => {
+            let listener = TcpListener::bind(&address).expect("Could not bind the GRBL emulator socket");
+            println!("GRBL emulator listening on tcp://{}", address);
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(mut stream) => {
+                        if let Err(e) = emulator.serve(&mut stream) {
+                            println!("GRBL client disconnected: {:?}", e);
+                        }
+                    },
+                    Err(e) => println!("GRBL connection failed: {:?}", e),
+                }
+            }
+        },
+        #[cfg(unix)]
+        Transport::Pty => {
+            let (mut master, _slave, path) = converter::grbl::open_pty().expect("Could not open a pseudo-terminal");
+            println!("GRBL emulator listening on {}", path.display());
+            if let Err(e) = emulator.serve(&mut master) {
+                println!("GRBL emulator stopped: {:?}", e);
+            }
+        },
+        #[cfg(not(unix))]
+        Transport::Pty => println!("Pseudo-terminals are only available on unix platforms, use --grbl-tcp instead"),
+    });
+
+    LiveLink { events: rx, head }
+}
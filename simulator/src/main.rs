@@ -1,15 +1,65 @@
+mod args;
+mod live;
 mod tracer;
 
+use live::{LiveEvent, LiveLink};
 use tracer::*;
 
 use nannou::prelude::*;
 use nannou::ui::*;
+use nannou::wgpu;
 
 use converter::args_parse::*;
+use converter::command::Command;
+use converter::machine;
+use converter::resume::{self, ResumePoint};
 
 use std::fs;
 use std::io;
 use std::io::prelude::*;
+use std::path::Path;
+
+// opacity of the image drawn under the toolpath
+const UNDERLAY_ALPHA: u8 = 96;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Underlay {
+    Hidden,
+    Original,
+    Edges,
+}
+
+impl Underlay {
+    fn next(self) -> Underlay {
+        match self {
+            Underlay::Hidden => Underlay::Original,
+            Underlay::Original => Underlay::Edges,
+            Underlay::Edges => Underlay::Hidden,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Underlay::Hidden => "Underlay: None",
+            Underlay::Original => "Underlay: Original",
+            Underlay::Edges => "Underlay: Edges",
+        }
+    }
+}
+
+// loads an image as a semi-transparent texture, flipped to match the toolpath orientation
+fn load_underlay(app: &App, path: &Path) -> Option<wgpu::Texture> {
+    let image = nannou::image::open(path).ok()?;
+    let mut rgba = nannou::image::imageops::flip_vertical(&image.to_rgba8());
+    for pixel in rgba.pixels_mut() {
+        pixel[3] = UNDERLAY_ALPHA;
+    }
+    Some(wgpu::Texture::from_image(app, &nannou::image::DynamicImage::ImageRgba8(rgba)))
+}
+
+fn edges_image_path(config: &Config) -> std::path::PathBuf {
+    config.export_path.join(format!("{}_edges.{}", config.input_name, config.input_extension))
+}
 
 fn detect_image_in_folder(dir_path: &str) -> String {
     let mut image_path = String::new();
@@ -63,6 +113,29 @@ fn parse_commands_file(config: &Config) -> Vec<String> {
     commands
 }
 
+// starts the playback where a resumed job would start
+fn seek_resume_point(tracer: &mut Tracer, commands: &[String], config: &Config, resume: Option<ResumePoint>) {
+    if let Some(point) = resume {
+        match resume::find_start(&commands.join("\n"), point, &config.machine) {
+            Ok(start) => tracer.seek(start),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+fn restart_tracer(model: &mut Model) {
+    let commands = if model.live.is_some() { vec![] } else { model.commands.clone() };
+    model.tracer = Tracer::new(commands, model.config.machine);
+}
+
+fn estimate_job_time(commands: &[String], config: &Config) -> f32 {
+    let parsed = commands
+        .iter()
+        .filter_map(|line| Command::parse(line))
+        .collect::<Vec<Command>>();
+    machine::estimate(&parsed, &config.machine).total
+}
+
 fn main() {
     nannou::app(init)
         .update(update)
@@ -86,8 +159,23 @@ struct Model {
 
     tracer: Tracer,
 
-    frame_count: u32,
-    speed: u32,
+    // estimated duration of the whole job (s)
+    job_time: f32,
+    // simulated seconds per real second
+    speed: f32,
+
+    show_travels: bool,
+    underlay: Underlay,
+    original_texture: Option<wgpu::Texture>,
+    edges_texture: Option<wgpu::Texture>,
+
+    // last mouse position while panning
+    drag: Option<Point2>,
+
+    // set when the simulator acts as a virtual GRBL device
+    live: Option<LiveLink>,
+    // where R restarts the playback
+    resume: Option<ResumePoint>,
 }
 
 widget_ids! {
@@ -99,6 +187,15 @@ widget_ids! {
         high_threshold,
         reset_button,
         speed,
+        play_pause,
+        step_backward,
+        step_forward,
+        next_pen_down,
+        timeline,
+        current_command,
+        clock,
+        show_travels,
+        underlay,
     }
 }
 
@@ -116,18 +213,29 @@ fn init(app: &App) -> Model {
     // Generate some ids for our widgets.
     let ids = Ids::new(ui.widget_id_generator());
 
-    let config = Config::new(
-        detect_image_in_folder("./assets").as_str(),
+    let args = args::parse();
+    let input = args.input.unwrap_or_else(|| detect_image_in_folder("./assets"));
+    let mut config = Config::new(
+        input.as_str(),
         "./assets/export",
         50.0f32,
         60.0f32,
         false,
         0.9f32
     );
+    config.machine.envelope = args.envelope;
 
     let config_hash = hash_config(&config);
     converter::execute(&config);
     let commands = parse_commands_file(&config);
+    let job_time = estimate_job_time(&commands, &config);
+    let machine = config.machine;
+    let live = args.transport.map(|transport| live::start(transport, &machine));
+    // a virtual device only draws what it receives
+    let mut tracer = Tracer::new(if live.is_some() { vec![] } else { commands.clone() }, machine);
+    seek_resume_point(&mut tracer, &commands, &config, args.resume);
+    let original_texture = load_underlay(app, &config.input_file);
+    let edges_texture = load_underlay(app, &edges_image_path(&config));
 
     Model {
         window_id: w_id,
@@ -141,10 +249,20 @@ fn init(app: &App) -> Model {
 
         commands: commands.clone(),
 
-        tracer: Tracer::new(commands),
+        job_time,
+        tracer,
+
+        speed: 1.0,
 
-        frame_count: 0,
-        speed: 30,
+        show_travels: false,
+        underlay: Underlay::Hidden,
+        original_texture,
+        edges_texture,
+
+        drag: None,
+
+        live,
+        resume: args.resume,
     }
 }
 
@@ -154,7 +272,8 @@ fn init(app: &App) -> Model {
 #[allow(unused_variables)]
 fn update(app: &App, model: &mut Model, update: Update) {
     // Calling `set_widgets` allows us to instantiate some widgets.
-    let ui = &mut model.ui.set_widgets();
+    let mut ui_cell = model.ui.set_widgets();
+    let ui = &mut ui_cell;
 
     fn slider(val: f32, min: f32, max: f32) -> widget::Slider<'static, f32> {
         widget::Slider::new(val, min, max)
@@ -189,12 +308,12 @@ fn update(app: &App, model: &mut Model, update: Update) {
         model.config.high_threshold = value;
     }
 
-    for value in slider(model.speed as f32, 1.0, 60.0)
+    for value in slider(model.speed, 0.1, 60.0)
         .down(10.0)
         .label("Speed")
         .set(model.ids.speed, ui)
     {
-        model.speed = value.round() as u32;
+        model.speed = value;
     }
 
     for value in slider(model.scale, 0.0001, 5.0)
@@ -232,6 +351,8 @@ fn update(app: &App, model: &mut Model, update: Update) {
     else {
         0.3
     };
+    // the tracer restarts once the widgets release the model
+    let mut restart = false;
     for _click in widget::Button::new()
         .down(10.0)
         .w_h(200.0, 60.0)
@@ -247,24 +368,226 @@ fn update(app: &App, model: &mut Model, update: Update) {
             converter::execute(&model.config);
             let commands = parse_commands_file(&model.config);
             model.config_hash = new_hash_config;
+            model.job_time = estimate_job_time(&commands, &model.config);
+            model.edges_texture = load_underlay(app, &edges_image_path(&model.config));
             model.commands = commands;
         }
-        model.tracer = Tracer::new(model.commands.clone());
+        restart = true;
+    }
+
+    fn button(label: &str) -> widget::Button<'_, widget::button::Flat> {
+        widget::Button::new()
+            .label(label)
+            .label_font_size(15)
+            .rgb(0.3, 0.3, 0.3)
+            .label_rgb(1.0, 1.0, 1.0)
+            .border(0.0)
+    }
+
+    let play_label = if model.tracer.is_paused() { "Play" } else { "Pause" };
+    for _click in button(play_label)
+        .down(10.0)
+        .w_h(200.0, 40.0)
+        .set(model.ids.play_pause, ui)
+    {
+        model.tracer.toggle_pause();
+    }
+
+    for _click in button("Step <")
+        .down(10.0)
+        .w_h(95.0, 40.0)
+        .set(model.ids.step_backward, ui)
+    {
+        model.tracer.pause();
+        model.tracer.step_backward();
+    }
+
+    for _click in button("Step >")
+        .right(10.0)
+        .w_h(95.0, 40.0)
+        .set(model.ids.step_forward, ui)
+    {
+        model.tracer.pause();
+        model.tracer.step_forward();
+    }
+
+    for _click in button("Next Pen Down")
+        .down_from(model.ids.step_backward, 10.0)
+        .w_h(200.0, 40.0)
+        .set(model.ids.next_pen_down, ui)
+    {
+        model.tracer.pause();
+        model.tracer.next_pen_down();
+    }
+
+    let command_count = model.tracer.command_count().max(1) as f32;
+    for value in slider(model.tracer.cursor() as f32, 0.0, command_count)
+        .down(10.0)
+        .label("Command")
+        .set(model.ids.timeline, ui)
+    {
+        model.tracer.pause();
+        model.tracer.seek(value.round() as usize);
+    }
+
+    let current_command = match model.tracer.last_command() {
+        Some((line, command)) => format!("{}/{}: {}", line, model.tracer.command_count(), command),
+        None => format!("0/{}: -", model.tracer.command_count()),
+    };
+    widget::Text::new(&current_command)
+        .down(10.0)
+        .w(200.0)
+        .font_size(15)
+        .rgb(1.0, 1.0, 1.0)
+        .set(model.ids.current_command, ui);
+
+    let clock = format!(
+        "Time: {} / {}",
+        machine::format_duration(model.tracer.clock()),
+        machine::format_duration(model.job_time),
+    );
+    widget::Text::new(&clock)
+        .down(10.0)
+        .w(200.0)
+        .font_size(15)
+        .rgb(1.0, 1.0, 1.0)
+        .set(model.ids.clock, ui);
+
+    for value in widget::Toggle::new(model.show_travels)
+        .down(10.0)
+        .w_h(200.0, 30.0)
+        .label("Travel Moves")
+        .label_font_size(15)
+        .rgb(0.3, 0.3, 0.3)
+        .label_rgb(1.0, 1.0, 1.0)
+        .border(0.0)
+        .set(model.ids.show_travels, ui)
+    {
+        model.show_travels = value;
+    }
+
+    for _click in button(model.underlay.label())
+        .down(10.0)
+        .w_h(200.0, 30.0)
+        .set(model.ids.underlay, ui)
+    {
+        model.underlay = model.underlay.next();
+    }
+
+    drop(ui_cell);
+    if restart {
+        restart_tracer(model);
+    }
+
+    if let Some(live) = &model.live {
+        while let Ok(event) = live.events.try_recv() {
+            match event {
+                LiveEvent::Commands(commands) => {
+                    for command in commands {
+                        model.tracer.push(command.to_string());
+                    }
+                },
+                LiveEvent::Hold(true) => model.tracer.pause(),
+                LiveEvent::Hold(false) => model.tracer.play(),
+                LiveEvent::Reset => model.tracer.cancel_pending(),
+            }
+        }
     }
 
-    model.frame_count += 1;
-    if model.frame_count % model.speed == 0 {
-        model.tracer.enable_execution();
+    model.tracer.advance(update.since_last.as_secs_f32() * model.speed);
+
+    // status reports of the virtual device follow the animated head
+    if let Some(live) = &model.live {
+        let head = model.tracer.head();
+        let unit = model.config.machine.unit_size;
+        *live.head.lock().unwrap() = (head.x * unit, head.y * unit);
     }
-    model.tracer.execute_next();
 }
 
 /**
  * Window Events
  * https://github.com/nannou-org/nannou/blob/master/examples/nannou_basics/all_functions.rs
  */
-#[allow(unused_variables)]
 fn event(app: &App, model: &mut Model, event: Event) {
+    if let Event::WindowEvent { simple: Some(event), .. } = event {
+        let over_ui = model.ui.global_input().current.widget_under_mouse.is_some();
+        match event {
+            WindowEvent::MouseWheel(delta, _) if !over_ui => {
+                let steps = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+                zoom_around(model, app.mouse.position(), ZOOM_STEP.powf(steps));
+            },
+            WindowEvent::MousePressed(MouseButton::Left) if !over_ui => {
+                model.drag = Some(app.mouse.position());
+            },
+            WindowEvent::MouseReleased(MouseButton::Left) => {
+                model.drag = None;
+            },
+            WindowEvent::MouseMoved(position) => {
+                if let Some(last) = model.drag {
+                    model.offset += position - last;
+                    model.drag = Some(position);
+                }
+            },
+            WindowEvent::KeyPressed(key) => on_key(app, model, key),
+            _ => {},
+        }
+    }
+}
+
+// zoom factor of a single scroll wheel step
+const ZOOM_STEP: f32 = 1.1;
+
+// keeps the drawing point under `anchor` in place while scaling
+fn zoom_around(model: &mut Model, anchor: Point2, factor: f32) {
+    let scale = (model.scale * factor).clamp(0.0001, 5.0);
+    model.offset = anchor - (anchor - model.offset) * (scale / model.scale);
+    model.scale = scale;
+}
+
+// fits the whole toolpath in the window
+fn fit_to_window(app: &App, model: &mut Model) {
+    if let Some((min, max)) = model.tracer.bounds() {
+        let window = app.window_rect();
+        let size = (max - min).max(Point2::new(1.0, 1.0));
+        model.scale = (window.w() / size.x).min(window.h() / size.y).clamp(0.0001, 5.0) * 0.9;
+        model.offset = -(min + max) / 2.0 * model.scale;
+    }
+}
+
+/**
+ * Keyboard shortcuts
+ * Space play/pause, Left/Right step, N next pen down, Home/End seek, R restart,
+ * F fit to window, -/= speed down/up
+ */
+fn on_key(app: &App, model: &mut Model, key: Key) {
+    match key {
+        Key::Space => model.tracer.toggle_pause(),
+        Key::Right => {
+            model.tracer.pause();
+            model.tracer.step_forward();
+        },
+        Key::Left => {
+            model.tracer.pause();
+            model.tracer.step_backward();
+        },
+        Key::N => {
+            model.tracer.pause();
+            model.tracer.next_pen_down();
+        },
+        Key::Home => model.tracer.seek(0),
+        Key::End => model.tracer.seek(model.tracer.command_count()),
+        Key::R => {
+            restart_tracer(model);
+            seek_resume_point(&mut model.tracer, &model.commands, &model.config, model.resume);
+        },
+        Key::F => fit_to_window(app, model),
+        Key::Minus => model.speed = (model.speed / 1.5).max(0.1),
+        Key::Equals => model.speed = (model.speed * 1.5).min(60.0),
+        _ => {},
+    }
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
@@ -278,6 +601,24 @@ fn view(app: &App, model: &Model, frame: Frame) {
         draw.background().rgb(0.02, 0.02, 0.02);
     }
 
+    let underlay = match model.underlay {
+        Underlay::Hidden => None,
+        Underlay::Original => model.original_texture.as_ref(),
+        Underlay::Edges => model.edges_texture.as_ref(),
+    };
+    if let Some(texture) = underlay {
+        // one command unit per pixel, pixel (0, 0) sits on the origin just like the toolpath
+        let [w, h] = texture.size();
+        let (w, h) = (w as f32 * model.scale, h as f32 * model.scale);
+        draw.texture(texture)
+            .x_y(w / 2.0 + model.offset.x, h / 2.0 + model.offset.y)
+            .w_h(w, h);
+    }
+
+    model.tracer.draw_envelope(&draw, model.scale, model.offset);
+    if model.show_travels {
+        model.tracer.draw_travels(&draw, model.scale, model.offset);
+    }
     model.tracer.draw_current(&draw, model.scale, model.offset);
 
     // Write the result of our drawing to the window's frame.
@@ -1,158 +1,408 @@
 // Drawing tip of the cnc machine
 use converter::canny::PenDirection;
+use converter::command::Command;
+use converter::machine::{HeadState, MachineModel};
 use nannou::prelude::*;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct DrawingTip {
-  x: f32,
-  y: f32,
+// the physical motion caused by the last executed command
+#[derive(Debug, Clone, Copy)]
+struct Motion {
+  from: Point2,
+  to: Point2,
   pen: PenDirection,
+  // crossing a holding tab
+  tab: bool,
+  duration: f32,
+  elapsed: f32,
 }
 
 pub struct Tracer {
-  enable: bool,
+  paused: bool,
   finished: bool,
   commands: Vec<String>,
+  // index of the next command to be executed
+  cursor: usize,
 
-  current: DrawingTip,
-  previous: DrawingTip,
+  machine: MachineModel,
+  motion: Option<Motion>,
+  // simulated machine time (s)
+  clock: f32,
 
-  lines: Vec<(Point2, Point2)>,
+  current: HeadState,
+  previous: HeadState,
+
+  // color of the pen in use, set by TOOL commands
+  color: Option<[u8; 3]>,
+  // drawn lines, along with the pen color and the depth they were cut at
+  lines: Vec<(Point2, Point2, Option<[u8; 3]>, f32)>,
+  // moves performed with the pen up
+  travels: Vec<(Point2, Point2)>,
+  // holding tabs, crossed with the tool raised
+  tabs: Vec<(Point2, Point2)>,
 }
 
 impl Tracer {
-  pub fn new(commands: Vec<String>) -> Tracer {
+  pub fn new(commands: Vec<String>, machine: MachineModel) -> Tracer {
     Tracer {
-      enable: true,
-      finished: false,
-      commands: commands,
-
-      current: DrawingTip {
-        x: 0.0,
-        y: 0.0,
-        pen: PenDirection::UP,
-      },
-      previous: DrawingTip {
-        x: 0.0,
-        y: 0.0,
-        pen: PenDirection::UP,
-      },
+      paused: false,
+      finished: commands.is_empty(),
+      commands,
+      cursor: 0,
+
+      machine,
+      motion: None,
+      clock: 0.0,
 
+      current: HeadState::origin(),
+      previous: HeadState::origin(),
+
+      color: None,
       lines: vec![],
+      travels: vec![],
+      tabs: vec![],
     }
   }
 
   pub fn disable(&mut self) {
-    self.enable = false;
+    self.motion = None;
     self.finished = true;
   }
 
-  pub fn enable_execution(&mut self) {
-    self.enable = true;
-  }
-
   #[allow(dead_code)]
   pub fn is_finished(&self) -> bool {
-    self.finished
+    self.finished && self.motion.is_none()
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+
+  pub fn pause(&mut self) {
+    self.paused = true;
+  }
+
+  pub fn play(&mut self) {
+    self.paused = false;
+  }
+
+  pub fn toggle_pause(&mut self) {
+    self.paused = !self.paused;
+  }
+
+  pub fn command_count(&self) -> usize {
+    self.commands.len()
+  }
+
+  pub fn cursor(&self) -> usize {
+    self.cursor
+  }
+
+  pub fn clock(&self) -> f32 {
+    self.clock
+  }
+
+  // the last executed command along with its (1 based) line number in the command file
+  pub fn last_command(&self) -> Option<(usize, &str)> {
+    if self.cursor == 0 {
+      return None;
+    }
+    self.commands
+      .get(self.cursor - 1)
+      .map(|command| (self.cursor, command.as_str()))
   }
 
   pub fn next_command(&mut self) -> Option<String> {
-    if self.finished || !self.enable {
+    if self.finished {
       return None;
     }
 
-    let command = self.commands.remove(0);
-    if self.commands.len() == 0 {
+    let command = self.commands.get(self.cursor).cloned();
+    self.cursor += 1;
+    if self.cursor >= self.commands.len() {
       self.finished = true;
     }
 
-    Some(command)
+    command
   }
 
   #[allow(dead_code)]
   pub fn get_next_command(&self) -> Option<String> {
-    if self.finished || !self.enable {
+    if self.finished {
       return None;
     }
 
-    let command = self.commands.get(0);
-    match command {
-      Some(command) => Some(command.to_string()),
-      None => None,
-    }
-  }
-
-  pub fn execute_next(&mut self) {
-    if self.enable {
-      let command = self.next_command();
-      let mut mutated = true;
-      if let Some(command) = command {
-        let copy_current = self.current.clone();
-        match command.as_str() {
-          "PEN UP" => self.current.pen = PenDirection::UP,
-          "PEN DOWN" => self.current.pen = PenDirection::DOWN,
-          "RESET" => {
-            self.current.pen = PenDirection::UP; // safeguard
-            self.current.x = 0.0;
-            self.current.y = 0.0;
-          }
-          "END" => self.finished = true,
-          _ => {
-            // parse "MOVE {X} {Y}" command
-            let mut iter = command.split_whitespace();
-            let command = iter.next().unwrap();
-
-            match command {
-              "MOVE" => {
-                let x = iter.next().unwrap().parse::<f32>().unwrap();
-                let y = iter.next().unwrap().parse::<f32>().unwrap();
-                self.current.x = x;
-                self.current.y = y;
-              }
-              _ => {
-                // panic!("Unknown command: {}", command);
-                // nothing to do, skip
-                println!("Unknown command: {}", command);
-                mutated = false;
-              }
-            }
-          }
-        };
-
-        if mutated {
-          self.previous = copy_current;
-
-          if self.current.pen == PenDirection::DOWN && self.previous.pen == PenDirection::DOWN {
-            self.lines.push((
-              Point2::new(self.previous.x, self.previous.y),
-              Point2::new(self.current.x, self.current.y)
-            ));
-          }
+    self.commands.get(self.cursor).cloned()
+  }
+
+  // advances the simulation by `dt` seconds of machine time, does nothing while paused
+  pub fn advance(&mut self, dt: f32) {
+    if self.paused {
+      return;
+    }
+
+    let mut dt = dt;
+    while dt > 0.0 {
+      if let Some(motion) = self.motion.as_mut() {
+        let left = motion.duration - motion.elapsed;
+        if dt < left {
+          motion.elapsed += dt;
+          self.clock += dt;
+          return;
         }
+        dt -= left;
+        self.clock += left;
+        self.motion = None;
+      }
+
+      if self.finished {
+        return;
+      }
+      self.execute();
+    }
+  }
+
+  // executes the next command, starting the motion it causes
+  fn execute(&mut self) {
+    let command = self.next_command();
+    if let Some(command) = command {
+      let parsed = match Command::parse(&command) {
+        Some(parsed) => parsed,
+        None => {
+          // nothing to do, skip
+          println!("Unknown command: {}", command);
+          return;
+        },
+      };
+      match parsed {
+        Command::Tool(_, color) => self.color = color,
+        Command::End => self.finished = true,
+        _ => {},
+      }
+      let tab = matches!(parsed, Command::Tab(..));
+
+      self.previous = self.current;
+      let step = self.current.apply(&parsed, &self.machine);
+
+      if tab {
+        self.tabs.push((
+          Point2::new(self.previous.x, self.previous.y),
+          Point2::new(self.current.x, self.current.y),
+        ));
+      }
+      else if self.current.pen == PenDirection::DOWN && self.previous.pen == PenDirection::DOWN {
+        self.lines.push((
+          Point2::new(self.previous.x, self.previous.y),
+          Point2::new(self.current.x, self.current.y),
+          self.color,
+          self.current.depth,
+        ));
+      }
+      else if self.current.x != self.previous.x || self.current.y != self.previous.y {
+        self.travels.push((
+          Point2::new(self.previous.x, self.previous.y),
+          Point2::new(self.current.x, self.current.y)
+        ));
+      }
+
+      let from = Point2::new(self.previous.x, self.previous.y);
+      let to = Point2::new(self.current.x, self.current.y);
+      self.motion = Some(Motion {
+        from,
+        to,
+        pen: step.pen,
+        tab,
+        duration: step.duration(),
+        elapsed: 0.0,
+      });
+    }
+  }
+
+  // completes the motion in progress without animating it
+  fn skip_motion(&mut self) {
+    if let Some(motion) = self.motion.take() {
+      self.clock += motion.duration - motion.elapsed;
+    }
+  }
+
+  // manual stepping ignores the pause state and does not animate the motion
+  pub fn step_forward(&mut self) {
+    self.skip_motion();
+    self.execute();
+    self.skip_motion();
+  }
+
+  pub fn step_backward(&mut self) {
+    if self.cursor > 0 {
+      self.seek(self.cursor - 1);
+    }
+  }
+
+  // commands are not invertible (ie: RESET), so seeking replays everything up to the given index
+  pub fn seek(&mut self, index: usize) {
+    let index = index.min(self.commands.len());
+    self.cursor = 0;
+    self.finished = self.commands.is_empty();
+    self.motion = None;
+    self.clock = 0.0;
+    self.current = HeadState::origin();
+    self.previous = HeadState::origin();
+    self.color = None;
+    self.lines.clear();
+    self.travels.clear();
+    self.tabs.clear();
+
+    while self.cursor < index && !self.finished {
+      self.step_forward();
+    }
+    // a later seek may still go past an END command
+    self.finished = self.cursor >= self.commands.len();
+  }
+
+  // executes commands until the pen is put down, or until there is nothing left to execute
+  pub fn next_pen_down(&mut self) {
+    while !self.finished {
+      self.step_forward();
+      if self.current.pen == PenDirection::DOWN && self.previous.pen == PenDirection::UP {
+        break;
+      }
+    }
+  }
+
+  // bounding box of every position the head reaches
+  pub fn bounds(&self) -> Option<(Point2, Point2)> {
+    self.commands
+      .iter()
+      .filter_map(|command| match Command::parse(command) {
+        Some(Command::Move(x, y)) | Some(Command::Tab(x, y)) | Some(Command::Ramp(x, y, _)) => Some(Point2::new(x, y)),
+        Some(Command::Reset) => Some(Point2::new(0.0, 0.0)),
+        _ => None,
+      })
+      .fold(None, |bounds, pt| match bounds {
+        Some((min, max)) => Some((pt.min(min), pt.max(max))),
+        None => Some((pt, pt)),
+      })
+  }
+
+  // appends a command received while running, playback continues with it
+  pub fn push(&mut self, command: String) {
+    self.commands.push(command);
+    if self.cursor < self.commands.len() {
+      self.finished = false;
+    }
+  }
+
+  // drops every command that was not executed yet
+  pub fn cancel_pending(&mut self) {
+    self.commands.truncate(self.cursor);
+    self.finished = true;
+  }
+
+  // position of the head, taking the motion in progress into account
+  pub fn head(&self) -> Point2 {
+    match self.motion {
+      Some(motion) => {
+        let distance = motion.from.distance(motion.to);
+        if distance <= 0.0 {
+          return motion.to;
+        }
+        let covered = self.machine.distance_at(distance, motion.pen, motion.elapsed);
+        motion.from + (motion.to - motion.from) * (covered / distance)
+      },
+      None => Point2::new(self.current.x, self.current.y),
+    }
+  }
+
+  pub fn draw_envelope(&self, draw: &nannou::Draw, scale: f32, offset: Point2) {
+    if let Some(envelope) = self.machine.envelope {
+      // the envelope is given in mm, convert it back to command units
+      let unit = self.machine.unit_size;
+      let min = Point2::new(envelope.min_x / unit * scale + offset.x, envelope.min_y / unit * scale + offset.y);
+      let max = Point2::new(envelope.max_x / unit * scale + offset.x, envelope.max_y / unit * scale + offset.y);
+
+      draw.rect()
+        .xy((min + max) / 2.0)
+        .wh(max - min)
+        .no_fill()
+        .stroke_weight(1.0)
+        .stroke(GRAY);
+    }
+  }
+
+  // pen up moves are drawn as dim dashed lines
+  pub fn draw_travels(&self, draw: &nannou::Draw, scale: f32, offset: Point2) {
+    let dash = 6.0;
+    let travelling = self.motion.is_some() && self.current.pen == PenDirection::UP;
+    let head = self.head();
+
+    for (index, ln) in self.travels.iter().enumerate() {
+      let end = if travelling && index + 1 == self.travels.len() { head } else { ln.1 };
+      let pt1 = Point2::new(ln.0.x * scale + offset.x, ln.0.y * scale + offset.y);
+      let pt2 = Point2::new(end.x * scale + offset.x, end.y * scale + offset.y);
+      let length = pt1.distance(pt2);
+      if length <= 0.0 {
+        continue;
+      }
+
+      let direction = (pt2 - pt1) / length;
+      let mut start = 0.0;
+      while start < length {
+        let stop = (start + dash).min(length);
+        draw.line()
+          .start(pt1 + direction * start)
+          .end(pt1 + direction * stop)
+          .rgba(0.5, 0.5, 0.5, 0.6);
+        start += dash * 2.0;
       }
-      self.enable = false;
     }
   }
 
   pub fn draw_current(&self, draw: &nannou::Draw, scale: f32, offset: Point2) {
-    for ln in &self.lines {
+    let head = self.head();
+    // the last line is still being drawn while its motion is in progress
+    let drawing = matches!(self.motion, Some(motion) if !motion.tab)
+      && self.current.pen == PenDirection::DOWN
+      && self.previous.pen == PenDirection::DOWN;
+    let crossing_tab = matches!(self.motion, Some(motion) if motion.tab);
+    // multi pass jobs are colored by depth, deeper lines are darker
+    let deepest = self.lines.iter().map(|ln| ln.3).fold(0.0, f32::min);
+
+    for (index, ln) in self.lines.iter().enumerate() {
+      let end = if drawing && index + 1 == self.lines.len() { head } else { ln.1 };
       let pt1 = Point2::new(ln.0.x * scale + offset.x, ln.0.y * scale + offset.y);
-      let pt2 = Point2::new(ln.1.x * scale + offset.x, ln.1.y * scale + offset.y);
+      let pt2 = Point2::new(end.x * scale + offset.x, end.y * scale + offset.y);
+      let in_bounds = self.machine.within_limits(ln.0.x, ln.0.y) && self.machine.within_limits(ln.1.x, ln.1.y);
 
       draw.line()
         .start(pt1)
         .end(pt2)
-        .color(YELLOW);
+        .color(match (in_bounds, ln.2) {
+          (false, _) => rgb(1.0, 0.0, 0.0),
+          (true, color) => {
+            let [r, g, b] = color.map(|c| [c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0]).unwrap_or([1.0, 1.0, 0.0]);
+            let shade = if deepest < 0.0 { 1.0 - 0.7 * ln.3 / deepest } else { 1.0 };
+            rgb(r * shade, g * shade, b * shade)
+          },
+        });
     }
 
-    let pt = Point2::new(self.current.x * scale + offset.x, self.current.y * scale + offset.y);
+    // tabs stand out as thick cyan bridges
+    for (index, ln) in self.tabs.iter().enumerate() {
+      let end = if crossing_tab && index + 1 == self.tabs.len() { head } else { ln.1 };
+      draw.line()
+        .start(Point2::new(ln.0.x * scale + offset.x, ln.0.y * scale + offset.y))
+        .end(Point2::new(end.x * scale + offset.x, end.y * scale + offset.y))
+        .stroke_weight(3.0)
+        .color(CYAN);
+    }
+
+    let pt = Point2::new(head.x * scale + offset.x, head.y * scale + offset.y);
     draw.ellipse()
       .xy(pt)
       .radius(10.0)
       .resolution(30.0)
       .color(WHITE);
 
-    if self.current.pen == PenDirection::DOWN && self.previous.pen == PenDirection::DOWN {
+    if self.current.pen == PenDirection::DOWN && self.previous.pen == PenDirection::DOWN && !crossing_tab {
       draw.ellipse()
         .xy(pt)
         .radius(5.0)
@@ -161,3 +411,134 @@ impl Tracer {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use converter::machine;
+
+  const PROGRAM: &str = "MOVE 0 10\nPEN DOWN\nMOVE 10 10\nMOVE 10 0\nPEN UP\nMOVE 20 0\nPEN DOWN\nMOVE 30 0\nPEN UP\nEND\nMOVE 5 5";
+
+  fn tracer(program: &str) -> Tracer {
+    Tracer::new(program.lines().map(String::from).collect(), MachineModel::default())
+  }
+
+  fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+  }
+
+  // time the estimator gives to the first `count` commands
+  fn estimated(program: &str, count: usize) -> f32 {
+    let commands = program.lines().take(count).filter_map(Command::parse).collect::<Vec<Command>>();
+    machine::estimate(&commands, &MachineModel::default()).total
+  }
+
+  #[test]
+  fn stepping() {
+    let mut tracer = tracer(PROGRAM);
+    for _ in 0..4 {
+      tracer.step_forward();
+    }
+    assert_eq!(tracer.cursor(), 4);
+    assert_eq!(tracer.last_command(), Some((4, "MOVE 10 0")));
+    assert_eq!((tracer.lines.len(), tracer.travels.len()), (2, 1));
+    assert_eq!(tracer.head(), Point2::new(10.0, 0.0));
+    assert!(close(tracer.clock(), estimated(PROGRAM, 4)));
+
+    tracer.step_backward();
+    assert_eq!(tracer.cursor(), 3);
+    assert_eq!(tracer.lines.len(), 1);
+    assert_eq!(tracer.head(), Point2::new(10.0, 10.0));
+    assert!(close(tracer.clock(), estimated(PROGRAM, 3)));
+
+    tracer.seek(0);
+    tracer.step_backward();
+    assert_eq!((tracer.cursor(), tracer.last_command()), (0, None));
+  }
+
+  #[test]
+  fn seeking_replays_from_the_start() {
+    let mut stepped = tracer(PROGRAM);
+    for _ in 0..7 {
+      stepped.step_forward();
+    }
+    let mut sought = tracer(PROGRAM);
+    sought.seek(9);
+    sought.seek(7);
+    assert_eq!((sought.cursor(), sought.current, sought.previous), (stepped.cursor(), stepped.current, stepped.previous));
+    assert_eq!((sought.lines.len(), sought.travels.len()), (stepped.lines.len(), stepped.travels.len()));
+    assert_eq!(sought.clock(), stepped.clock());
+
+    // seeking stops at END, the commands after it can still be stepped through
+    sought.seek(usize::MAX);
+    assert_eq!(sought.cursor(), 10);
+    assert!(!sought.is_finished());
+    sought.step_forward();
+    assert_eq!(sought.head(), Point2::new(5.0, 5.0));
+    assert!(sought.is_finished());
+  }
+
+  #[test]
+  fn next_pen_down() {
+    let mut tracer = tracer(PROGRAM);
+    tracer.next_pen_down();
+    assert_eq!(tracer.cursor(), 2);
+    tracer.next_pen_down();
+    assert_eq!(tracer.cursor(), 7);
+    assert_eq!(tracer.head(), Point2::new(20.0, 0.0));
+    // runs up to the END command when no pen down is left
+    tracer.next_pen_down();
+    assert_eq!(tracer.cursor(), 10);
+    assert!(tracer.is_finished());
+  }
+
+  #[test]
+  fn advance() {
+    let mut tracer = tracer(PROGRAM);
+    tracer.pause();
+    tracer.advance(1.0);
+    assert_eq!((tracer.cursor(), tracer.clock()), (0, 0.0));
+
+    // halfway through the first travel, which accelerates and decelerates symmetrically
+    tracer.play();
+    let first = estimated(PROGRAM, 1);
+    tracer.advance(first / 2.0);
+    assert_eq!(tracer.cursor(), 1);
+    assert!(close(tracer.head().y, 5.0));
+    assert!(!tracer.is_finished());
+
+    // the whole job takes the estimated time
+    tracer.advance(100.0);
+    assert!(tracer.is_finished());
+    assert_eq!(tracer.cursor(), 10);
+    assert!(close(tracer.clock(), estimated(PROGRAM, 10)));
+  }
+
+  #[test]
+  fn tabs_tools_and_unknown_commands() {
+    let mut tracer = tracer("TOOL 2 #ff0000\nPEN DOWN\nMOVE 10 0\nTAB 20 0\nNOPE\nMOVE 30 0\nTOOL 3\nMOVE 0 0");
+    tracer.seek(6);
+    assert_eq!(tracer.tabs, vec![(Point2::new(10.0, 0.0), Point2::new(20.0, 0.0))]);
+    assert_eq!(tracer.lines.iter().map(|line| line.2).collect::<Vec<_>>(), vec![Some([255, 0, 0]); 2]);
+    assert!(close(tracer.clock(), estimated("TOOL 2 #ff0000\nPEN DOWN\nMOVE 10 0\nTAB 20 0\nMOVE 30 0", 5)));
+
+    // the pen is lifted to be swapped
+    tracer.seek(8);
+    assert_eq!((tracer.color, tracer.current.pen), (None, PenDirection::UP));
+    assert_eq!(tracer.travels.len(), 1);
+  }
+
+  #[test]
+  fn pushed_and_cancelled_commands() {
+    let mut tracer = tracer("");
+    assert!(tracer.is_finished());
+    tracer.push("PEN DOWN".to_string());
+    tracer.push("MOVE 10 0".to_string());
+    tracer.step_forward();
+    assert!(!tracer.is_finished());
+    tracer.cancel_pending();
+    assert_eq!(tracer.command_count(), 1);
+    assert!(tracer.is_finished());
+    assert_eq!(tracer.bounds(), None);
+  }
+}